@@ -0,0 +1,160 @@
+use pipewire as pw;
+use pw::properties::properties;
+use tokio::sync::mpsc;
+
+use super::{param_pod, s16_format_param, PwCmd, SAMPLE_SIZE};
+
+/// A PipeWire input stream running on its own thread.
+///
+/// Captured samples are interleaved S16 PCM and are forwarded as they arrive, the buffer
+/// sizes are chosen by the PipeWire graph so consumers must re-chunk them themselves.
+pub struct CaptureStream {
+    pw_tx: pw::channel::Sender<PwCmd>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl CaptureStream {
+    /// Start capturing from `target`, a PipeWire node name or serial, or the default source.
+    ///
+    /// Samples are sent to `samples_tx`, which lets the consumer outlive the stream when
+    /// switching devices.
+    pub fn new(
+        target: Option<String>,
+        sample_rate: u32,
+        num_channels: u32,
+        samples_tx: mpsc::UnboundedSender<Vec<i16>>,
+    ) -> Self {
+        let (pw_tx, pw_rx) = pw::channel::channel();
+
+        let thread = std::thread::spawn(move || {
+            if let Err(err) = capture_thread(target, sample_rate, num_channels, samples_tx, pw_rx) {
+                log::error!("audio capture failed: {:?}", err);
+            }
+        });
+
+        Self {
+            pw_tx,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for CaptureStream {
+    fn drop(&mut self) {
+        let _ = self.pw_tx.send(PwCmd::Terminate);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn capture_thread(
+    target: Option<String>,
+    sample_rate: u32,
+    num_channels: u32,
+    samples_tx: mpsc::UnboundedSender<Vec<i16>>,
+    pw_rx: pw::channel::Receiver<PwCmd>,
+) -> Result<(), pw::Error> {
+    let mainloop = pw::main_loop::MainLoopRc::new(None)?;
+    let context = pw::context::ContextRc::new(&mainloop, None)?;
+    let core = context.connect_rc(None)?;
+
+    let mut props = properties! {
+        *pw::keys::MEDIA_TYPE => "Audio",
+        *pw::keys::MEDIA_ROLE => "Communication",
+        *pw::keys::MEDIA_CATEGORY => "Capture",
+        *pw::keys::AUDIO_CHANNELS => num_channels.to_string(),
+    };
+    if let Some(target) = target {
+        props.insert(*pw::keys::TARGET_OBJECT, target);
+    }
+
+    let stream = pw::stream::StreamBox::new(&core, "verdant-capture", props)?;
+
+    let _listener = stream
+        .add_local_listener_with_user_data(samples_tx)
+        .process(|stream, samples_tx| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+
+            let datas = buffer.datas_mut();
+            if datas.is_empty() {
+                return;
+            }
+
+            let data = &mut datas[0];
+            let offset = data.chunk().offset() as usize;
+            let size = data.chunk().size() as usize;
+            if let Some(slice) = data.data() {
+                let end = (offset + size).min(slice.len());
+                let samples = slice[offset.min(end)..end]
+                    .chunks_exact(SAMPLE_SIZE)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                    .collect::<Vec<i16>>();
+
+                let _ = samples_tx.send(samples);
+            }
+        })
+        .register()?;
+
+    let values = s16_format_param(sample_rate, num_channels);
+    let mut params = [param_pod(&values)];
+
+    stream.connect(
+        pw::spa::utils::Direction::Input,
+        None,
+        pw::stream::StreamFlags::AUTOCONNECT
+            | pw::stream::StreamFlags::MAP_BUFFERS
+            | pw::stream::StreamFlags::RT_PROCESS,
+        &mut params,
+    )?;
+
+    let _receiver = pw_rx.attach(mainloop.loop_(), {
+        let mainloop = mainloop.clone();
+        move |cmd| match cmd {
+            PwCmd::Terminate => mainloop.quit(),
+        }
+    });
+
+    mainloop.run();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::SAMPLE_RATE;
+    use std::time::{Duration, Instant};
+
+    /// Needs PipeWire and a source to capture, for example the monitor of a null sink:
+    /// `pactl load-module module-null-sink sink_name=verdant-test` and
+    /// `VERDANT_TEST_SOURCE=verdant-test.monitor cargo test`. Skipped when it is not set.
+    #[test]
+    fn captures_from_a_loopback_source() {
+        let Ok(target) = std::env::var("VERDANT_TEST_SOURCE") else {
+            eprintln!("VERDANT_TEST_SOURCE is not set, skipping");
+            return;
+        };
+
+        let (samples_tx, mut samples_rx) = mpsc::unbounded_channel();
+        let _stream = CaptureStream::new(Some(target), SAMPLE_RATE, 1, samples_tx);
+
+        // A tenth of a second of audio
+        let start = Instant::now();
+        let mut received = 0;
+        while received < SAMPLE_RATE as usize / 10 {
+            match samples_rx.try_recv() {
+                Ok(samples) => received += samples.len(),
+                Err(mpsc::error::TryRecvError::Empty) => {
+                    assert!(
+                        start.elapsed() < Duration::from_secs(5),
+                        "no audio captured"
+                    );
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Err(mpsc::error::TryRecvError::Disconnected) => panic!("capture stream ended"),
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use super::{NUM_CHANNELS, SAMPLE_RATE};
+
+// Cues sit well below speech so they don't get in the way
+const CUE_AMPLITUDE: f32 = 0.25;
+// Fade in and out of every note, avoids clicks
+const FADE_MS: u32 = 5;
+
+/// Room events that can be signalled with a short local sound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SoundCue {
+    Join,
+    Leave,
+    ChatMessage,
+    RecordingStarted,
+    ConnectionLost,
+}
+
+impl SoundCue {
+    pub const ALL: [SoundCue; 5] = [
+        SoundCue::Join,
+        SoundCue::Leave,
+        SoundCue::ChatMessage,
+        SoundCue::RecordingStarted,
+        SoundCue::ConnectionLost,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SoundCue::Join => "Participant joined",
+            SoundCue::Leave => "Participant left",
+            SoundCue::ChatMessage => "Chat message",
+            SoundCue::RecordingStarted => "Recording started",
+            SoundCue::ConnectionLost => "Connection lost",
+        }
+    }
+
+    /// Notes of the cue as (frequency in Hz, duration in ms), a frequency of 0 is a rest.
+    fn notes(&self) -> &'static [(f32, u32)] {
+        match self {
+            SoundCue::Join => &[(660.0, 90), (880.0, 140)],
+            SoundCue::Leave => &[(880.0, 90), (660.0, 140)],
+            SoundCue::ChatMessage => &[(1320.0, 70)],
+            SoundCue::RecordingStarted => {
+                &[(990.0, 80), (0.0, 60), (990.0, 80), (0.0, 60), (990.0, 80)]
+            }
+            SoundCue::ConnectionLost => &[(523.0, 150), (392.0, 150), (262.0, 300)],
+        }
+    }
+
+    /// Render the cue as interleaved PCM at the mixer's rate and channel count.
+    pub fn render(&self) -> Vec<i16> {
+        render_notes(self.notes())
+    }
+}
+
+/// A second of A4, played to check the speakers before joining a room.
+pub fn test_tone() -> Vec<i16> {
+    render_notes(&[(440.0, 1000)])
+}
+
+fn render_notes(notes: &[(f32, u32)]) -> Vec<i16> {
+    let mut samples = Vec::new();
+    for (freq, duration_ms) in notes {
+        let len = (SAMPLE_RATE * duration_ms / 1000) as usize;
+        let fade = (SAMPLE_RATE * FADE_MS / 1000) as usize;
+
+        for i in 0..len {
+            let envelope = (i.min(len - i) as f32 / fade as f32).min(1.0);
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let val = if *freq > 0.0 {
+                CUE_AMPLITUDE * envelope * f32::sin(2.0 * PI * freq * t)
+            } else {
+                0.0
+            };
+            let sample = (val * 32768.0) as i16;
+            samples.extend(std::iter::repeat(sample).take(NUM_CHANNELS as usize));
+        }
+    }
+    samples
+}
+
+/// Which sound cues are played, all of them by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SoundCues {
+    pub join: bool,
+    pub leave: bool,
+    pub chat_message: bool,
+    pub recording_started: bool,
+    pub connection_lost: bool,
+}
+
+impl Default for SoundCues {
+    fn default() -> Self {
+        Self {
+            join: true,
+            leave: true,
+            chat_message: true,
+            recording_started: true,
+            connection_lost: true,
+        }
+    }
+}
+
+impl SoundCues {
+    pub fn enabled(&self, cue: SoundCue) -> bool {
+        match cue {
+            SoundCue::Join => self.join,
+            SoundCue::Leave => self.leave,
+            SoundCue::ChatMessage => self.chat_message,
+            SoundCue::RecordingStarted => self.recording_started,
+            SoundCue::ConnectionLost => self.connection_lost,
+        }
+    }
+
+    pub fn enabled_mut(&mut self, cue: SoundCue) -> &mut bool {
+        match cue {
+            SoundCue::Join => &mut self.join,
+            SoundCue::Leave => &mut self.leave,
+            SoundCue::ChatMessage => &mut self.chat_message,
+            SoundCue::RecordingStarted => &mut self.recording_started,
+            SoundCue::ConnectionLost => &mut self.connection_lost,
+        }
+    }
+}
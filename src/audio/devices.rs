@@ -0,0 +1,158 @@
+use parking_lot::Mutex;
+use pipewire as pw;
+use pw::types::ObjectType;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+use super::PwCmd;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceKind {
+    Source,
+    Sink,
+}
+
+/// An audio node exposed by PipeWire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioDevice {
+    pub id: u32,
+    /// `node.name`, stable across restarts and used as the stream target
+    pub name: String,
+    pub description: String,
+    pub kind: DeviceKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum DeviceEvent {
+    Added(AudioDevice),
+    Removed(AudioDevice),
+}
+
+/// Keeps track of the PipeWire audio sources and sinks as they come and go.
+pub struct DeviceMonitor {
+    devices: Arc<Mutex<Vec<AudioDevice>>>,
+    events_tx: broadcast::Sender<DeviceEvent>,
+    pw_tx: pw::channel::Sender<PwCmd>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl DeviceMonitor {
+    pub fn new() -> Self {
+        let devices = Arc::new(Mutex::new(Vec::new()));
+        let (events_tx, _) = broadcast::channel(64);
+        let (pw_tx, pw_rx) = pw::channel::channel();
+
+        let thread = std::thread::spawn({
+            let devices = devices.clone();
+            let events_tx = events_tx.clone();
+            move || {
+                if let Err(err) = monitor_thread(devices, events_tx, pw_rx) {
+                    log::error!("audio device monitor failed: {:?}", err);
+                }
+            }
+        });
+
+        Self {
+            devices,
+            events_tx,
+            pw_tx,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn devices(&self, kind: DeviceKind) -> Vec<AudioDevice> {
+        self.devices
+            .lock()
+            .iter()
+            .filter(|device| device.kind == kind)
+            .cloned()
+            .collect()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.events_tx.subscribe()
+    }
+}
+
+impl Default for DeviceMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for DeviceMonitor {
+    fn drop(&mut self) {
+        let _ = self.pw_tx.send(PwCmd::Terminate);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn monitor_thread(
+    devices: Arc<Mutex<Vec<AudioDevice>>>,
+    events_tx: broadcast::Sender<DeviceEvent>,
+    pw_rx: pw::channel::Receiver<PwCmd>,
+) -> Result<(), pw::Error> {
+    let mainloop = pw::main_loop::MainLoopRc::new(None)?;
+    let context = pw::context::ContextRc::new(&mainloop, None)?;
+    let core = context.connect_rc(None)?;
+    let registry = core.get_registry_rc()?;
+
+    let _registry_listener = registry
+        .add_listener_local()
+        .global({
+            let devices = devices.clone();
+            let events_tx = events_tx.clone();
+            move |obj| {
+                if obj.type_ != ObjectType::Node {
+                    return;
+                }
+                let Some(props) = obj.props else {
+                    return;
+                };
+
+                let kind = match props.get(*pw::keys::MEDIA_CLASS) {
+                    Some(class) if class.starts_with("Audio/Source") => DeviceKind::Source,
+                    Some(class) if class.starts_with("Audio/Sink") => DeviceKind::Sink,
+                    _ => return,
+                };
+                let Some(name) = props.get(*pw::keys::NODE_NAME) else {
+                    return;
+                };
+
+                let device = AudioDevice {
+                    id: obj.id,
+                    name: name.to_string(),
+                    description: props
+                        .get(*pw::keys::NODE_DESCRIPTION)
+                        .unwrap_or(name)
+                        .to_string(),
+                    kind,
+                };
+
+                log::info!("audio device added: {:?}", device);
+                devices.lock().push(device.clone());
+                let _ = events_tx.send(DeviceEvent::Added(device));
+            }
+        })
+        .global_remove(move |id| {
+            let mut devices = devices.lock();
+            if let Some(index) = devices.iter().position(|device| device.id == id) {
+                let device = devices.remove(index);
+                log::info!("audio device removed: {:?}", device);
+                let _ = events_tx.send(DeviceEvent::Removed(device));
+            }
+        })
+        .register();
+
+    let _receiver = pw_rx.attach(mainloop.loop_(), {
+        let mainloop = mainloop.clone();
+        move |cmd| match cmd {
+            PwCmd::Terminate => mainloop.quit(),
+        }
+    });
+
+    mainloop.run();
+    Ok(())
+}
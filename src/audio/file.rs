@@ -0,0 +1,244 @@
+use audiopus::{coder::Decoder, packet::Packet, Channels, MutSignals, SampleRate};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use thiserror::Error;
+
+use super::AudioGenerator;
+
+// Opus always decodes at 48kHz, regardless of the input rate stored in the header
+const OPUS_SAMPLE_RATE: u32 = 48000;
+// The longest Opus packet is 120ms
+const OPUS_MAX_FRAME: usize = OPUS_SAMPLE_RATE as usize * 120 / 1000;
+
+#[derive(Debug, Error)]
+pub enum AudioFileError {
+    #[error("failed to read audio file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to decode wav file: {0}")]
+    Wav(#[from] hound::Error),
+    #[error("failed to read ogg container: {0}")]
+    Ogg(#[from] ogg::OggReadError),
+    #[error("failed to decode opus stream: {0}")]
+    Opus(#[from] audiopus::Error),
+    #[error("unsupported audio file: {0}")]
+    Unsupported(String),
+}
+
+/// A fully decoded audio file, resampled to the rate of the track publishing it.
+#[derive(Clone)]
+pub struct AudioFile {
+    samples: Vec<i16>,
+    num_channels: usize,
+}
+
+impl AudioFile {
+    /// Decode a WAV or Ogg/Opus file, picked by extension, and resample it to `sample_rate`.
+    pub fn open(path: &Path, sample_rate: u32) -> Result<Self, AudioFileError> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        let (samples, file_rate, num_channels) = match extension.as_deref() {
+            Some("wav") => decode_wav(path)?,
+            Some("ogg") | Some("opus") => decode_opus(path)?,
+            _ => return Err(AudioFileError::Unsupported(path.display().to_string())),
+        };
+
+        Ok(Self {
+            samples: resample(&samples, num_channels, file_rate, sample_rate),
+            num_channels,
+        })
+    }
+
+    /// Duration in frames, one frame holds a sample for every channel.
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.num_channels
+    }
+
+    fn frame(&self, index: usize) -> &[i16] {
+        &self.samples[index * self.num_channels..(index + 1) * self.num_channels]
+    }
+}
+
+fn decode_wav(path: &Path) -> Result<(Vec<i16>, u32, usize), AudioFileError> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+
+    let samples = match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Int, 16) => reader.samples::<i16>().collect::<Result<_, _>>()?,
+        (hound::SampleFormat::Int, bits) => {
+            let shift = bits as i32 - 16;
+            reader
+                .samples::<i32>()
+                .map(|s| {
+                    s.map(|s| {
+                        if shift > 0 {
+                            (s >> shift) as i16
+                        } else {
+                            (s << -shift) as i16
+                        }
+                    })
+                })
+                .collect::<Result<_, _>>()?
+        }
+        (hound::SampleFormat::Float, _) => reader
+            .samples::<f32>()
+            .map(|s| s.map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16))
+            .collect::<Result<_, _>>()?,
+    };
+
+    Ok((samples, spec.sample_rate, spec.channels as usize))
+}
+
+fn decode_opus(path: &Path) -> Result<(Vec<i16>, u32, usize), AudioFileError> {
+    let mut reader = ogg::PacketReader::new(BufReader::new(File::open(path)?));
+
+    // OpusHead: magic, version, channel count, pre-skip, ...
+    let head = reader
+        .read_packet()?
+        .ok_or_else(|| AudioFileError::Unsupported("empty ogg stream".to_string()))?;
+    if head.data.len() < 19 || &head.data[..8] != b"OpusHead" {
+        return Err(AudioFileError::Unsupported(
+            "ogg stream is not opus".to_string(),
+        ));
+    }
+    let num_channels = head.data[9] as usize;
+    let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as usize;
+    let channels = match num_channels {
+        1 => Channels::Mono,
+        2 => Channels::Stereo,
+        n => {
+            return Err(AudioFileError::Unsupported(format!(
+                "{} channel opus streams",
+                n
+            )))
+        }
+    };
+
+    // OpusTags, nothing we need in there
+    reader.read_packet()?;
+
+    let mut decoder = Decoder::new(SampleRate::Hz48000, channels)?;
+    let mut buffer = vec![0i16; OPUS_MAX_FRAME * num_channels];
+    let mut samples = Vec::new();
+
+    while let Some(packet) = reader.read_packet()? {
+        let packet = Packet::try_from(&packet.data)?;
+        let output = MutSignals::try_from(&mut buffer)?;
+        let frames = decoder.decode(Some(packet), output, false)?;
+        samples.extend_from_slice(&buffer[..frames * num_channels]);
+    }
+
+    let skip = (pre_skip * num_channels).min(samples.len());
+    samples.drain(..skip);
+
+    Ok((samples, OPUS_SAMPLE_RATE, num_channels))
+}
+
+/// Linear resampling, plenty for test material.
+fn resample(samples: &[i16], num_channels: usize, from: u32, to: u32) -> Vec<i16> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let in_frames = samples.len() / num_channels;
+    let out_frames = (in_frames as u64 * to as u64 / from as u64) as usize;
+    let ratio = from as f64 / to as f64;
+
+    let mut out = Vec::with_capacity(out_frames * num_channels);
+    for frame in 0..out_frames {
+        let pos = frame as f64 * ratio;
+        let index = pos as usize;
+        let next = (index + 1).min(in_frames - 1);
+        let t = pos - index as f64;
+
+        for c in 0..num_channels {
+            let a = samples[index * num_channels + c] as f64;
+            let b = samples[next * num_channels + c] as f64;
+            out.push((a + (b - a) * t) as i16);
+        }
+    }
+    out
+}
+
+/// Plays an [`AudioFile`] once or in a loop.
+pub struct FileGenerator {
+    file: AudioFile,
+    position: usize,
+    looping: bool,
+}
+
+impl FileGenerator {
+    pub fn new(file: AudioFile, looping: bool) -> Self {
+        Self {
+            file,
+            position: 0,
+            looping,
+        }
+    }
+}
+
+impl AudioGenerator for FileGenerator {
+    fn generate(&mut self, samples: &mut [i16], num_channels: usize) -> bool {
+        let frames = self.file.frames();
+        if frames == 0 || (self.position >= frames && !self.looping) {
+            return false;
+        }
+
+        for out in samples.chunks_exact_mut(num_channels) {
+            if self.position >= frames {
+                if !self.looping {
+                    out.fill(0);
+                    continue;
+                }
+                self.position = 0;
+            }
+
+            let frame = self.file.frame(self.position);
+            for (c, sample) in out.iter_mut().enumerate() {
+                *sample = if frame.len() == 1 {
+                    frame[0]
+                } else if num_channels == 1 {
+                    (frame.iter().map(|s| *s as i32).sum::<i32>() / frame.len() as i32) as i16
+                } else {
+                    frame[c.min(frame.len() - 1)]
+                };
+            }
+            self.position += 1;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resample_keeps_matching_rates() {
+        let samples = [1, -2, 3, -4];
+        assert_eq!(resample(&samples, 2, 48000, 48000), samples);
+        assert!(resample(&[], 2, 44100, 48000).is_empty());
+    }
+
+    #[test]
+    fn resample_interpolates_each_channel() {
+        // Stereo ramp up on the left and down on the right
+        let samples = [0, 100, 10, 90, 20, 80, 30, 70];
+        let out = resample(&samples, 2, 24000, 48000);
+        assert_eq!(out.len(), samples.len() * 2);
+        assert_eq!(
+            out,
+            [0, 100, 5, 95, 10, 90, 15, 85, 20, 80, 25, 75, 30, 70, 30, 70]
+        );
+    }
+
+    #[test]
+    fn resample_scales_the_length() {
+        let samples = vec![0; 44100];
+        assert_eq!(resample(&samples, 1, 44100, 48000).len(), 48000);
+        assert_eq!(resample(&samples, 1, 44100, 16000).len(), 16000);
+    }
+}
@@ -0,0 +1,159 @@
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Meters fall back to silence when a stream stops delivering audio
+const STALE_AFTER: Duration = Duration::from_millis(250);
+// Applied to the held peak on every update so it falls off smoothly
+const PEAK_DECAY: f32 = 0.95;
+const METER_FLOOR_DB: f32 = -60.0;
+
+/// Signal level of a block of samples, both values are linear in `0.0..=1.0` of full scale.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AudioLevel {
+    pub rms: f32,
+    pub peak: f32,
+}
+
+impl AudioLevel {
+    pub fn measure(samples: &[i16]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+
+        let mut sum = 0.0f64;
+        let mut peak = 0i32;
+        for sample in samples {
+            let value = *sample as i32;
+            sum += (value * value) as f64;
+            peak = peak.max(value.abs());
+        }
+
+        Self {
+            rms: ((sum / samples.len() as f64).sqrt() / 32768.0) as f32,
+            peak: peak as f32 / 32768.0,
+        }
+    }
+
+    pub fn rms_db(&self) -> f32 {
+        to_db(self.rms)
+    }
+
+    pub fn peak_db(&self) -> f32 {
+        to_db(self.peak)
+    }
+
+    /// Map a level to `0.0..=1.0` on a -60 dBFS scale, which is how meters should be drawn.
+    pub fn meter_fraction(level: f32) -> f32 {
+        ((to_db(level) - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0)
+    }
+
+    pub fn max(self, other: Self) -> Self {
+        Self {
+            rms: self.rms.max(other.rms),
+            peak: self.peak.max(other.peak),
+        }
+    }
+}
+
+fn to_db(level: f32) -> f32 {
+    if level <= 0.0 {
+        f32::NEG_INFINITY
+    } else {
+        20.0 * level.log10()
+    }
+}
+
+#[derive(Default)]
+struct MeterInner {
+    level: AudioLevel,
+    updated: Option<Instant>,
+}
+
+impl MeterInner {
+    fn update(&mut self, samples: &[i16]) {
+        let level = AudioLevel::measure(samples);
+        self.level = AudioLevel {
+            rms: level.rms,
+            peak: level.peak.max(self.level().peak * PEAK_DECAY),
+        };
+        self.updated = Some(Instant::now());
+    }
+
+    fn level(&self) -> AudioLevel {
+        match self.updated {
+            Some(updated) if updated.elapsed() < STALE_AFTER => self.level,
+            _ => AudioLevel::default(),
+        }
+    }
+}
+
+/// Level of a live audio stream, shared between the thread producing audio and the UI.
+#[derive(Clone, Default)]
+pub struct LevelMeter {
+    inner: Arc<Mutex<MeterInner>>,
+}
+
+impl LevelMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&self, samples: &[i16]) {
+        self.inner.lock().update(samples);
+    }
+
+    pub fn level(&self) -> AudioLevel {
+        self.inner.lock().level()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_rms_and_peak() {
+        assert_eq!(AudioLevel::measure(&[]), AudioLevel::default());
+
+        let level = AudioLevel::measure(&[16384, -16384, 16384, -16384]);
+        assert_eq!(level.rms, 0.5);
+        assert_eq!(level.peak, 0.5);
+
+        let level = AudioLevel::measure(&[0, 0, 0, i16::MIN]);
+        assert_eq!(level.rms, 0.5);
+        assert_eq!(level.peak, 1.0);
+    }
+
+    #[test]
+    fn converts_to_decibels() {
+        let level = AudioLevel {
+            rms: 0.1,
+            peak: 1.0,
+        };
+        assert!((level.rms_db() + 20.0).abs() < 1e-4);
+        assert_eq!(level.peak_db(), 0.0);
+        assert_eq!(AudioLevel::default().rms_db(), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn meter_fraction_spans_sixty_decibels() {
+        assert_eq!(AudioLevel::meter_fraction(0.0), 0.0);
+        assert_eq!(AudioLevel::meter_fraction(0.0001), 0.0);
+        assert!(AudioLevel::meter_fraction(0.001).abs() < 1e-4);
+        assert!((AudioLevel::meter_fraction(10f32.powf(-1.5)) - 0.5).abs() < 1e-4);
+        assert_eq!(AudioLevel::meter_fraction(1.0), 1.0);
+    }
+
+    #[test]
+    fn meter_holds_a_decaying_peak() {
+        let meter = LevelMeter::new();
+        assert_eq!(meter.level(), AudioLevel::default());
+
+        meter.update(&[i16::MIN]);
+        meter.update(&[0]);
+        let level = meter.level();
+        assert_eq!(level.rms, 0.0);
+        assert!((level.peak - PEAK_DECAY).abs() < 1e-6);
+    }
+}
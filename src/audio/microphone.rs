@@ -0,0 +1,290 @@
+use livekit::webrtc::audio_frame::AudioFrame;
+use livekit::webrtc::audio_source::RtcAudioSource;
+use livekit::{prelude::*, webrtc::audio_source::native::NativeAudioSource};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use super::capture::CaptureStream;
+use super::{AudioProcessing, LevelMeter, SAMPLE_RATE};
+
+#[derive(Clone)]
+pub struct MicrophoneParameters {
+    pub sample_rate: u32,
+    pub num_channels: u32,
+    /// PipeWire node name or serial to capture from, `None` follows the default source.
+    ///
+    /// Pointing this at the monitor of a null sink, e.g. one created with
+    /// `pactl load-module module-null-sink sink_name=verdant-test`, allows testing
+    /// without real hardware.
+    pub target: Option<String>,
+}
+
+impl Default for MicrophoneParameters {
+    fn default() -> Self {
+        Self {
+            sample_rate: SAMPLE_RATE,
+            num_channels: 1,
+            target: None,
+        }
+    }
+}
+
+struct TrackHandle {
+    close_tx: oneshot::Sender<()>,
+    track: LocalAudioTrack,
+    task: JoinHandle<()>,
+    samples_tx: mpsc::UnboundedSender<Vec<i16>>,
+    capture: Option<CaptureStream>,
+}
+
+/// Publishes audio captured from a PipeWire input node.
+pub struct MicrophoneTrack {
+    rtc_source: NativeAudioSource,
+    params: MicrophoneParameters,
+    processing: AudioProcessing,
+    meter: LevelMeter,
+    room: Arc<Room>,
+    handle: Option<TrackHandle>,
+}
+
+impl MicrophoneTrack {
+    pub fn new(
+        room: Arc<Room>,
+        params: MicrophoneParameters,
+        processing: AudioProcessing,
+        meter: LevelMeter,
+    ) -> Self {
+        let num_channels = processing.num_channels(params.num_channels);
+        Self {
+            rtc_source: NativeAudioSource::new(
+                processing.source_options(),
+                params.sample_rate,
+                num_channels,
+                1000,
+            ),
+            params,
+            processing,
+            meter,
+            room,
+            handle: None,
+        }
+    }
+
+    /// Processing changes apply right away, channel count and encoding on the next publish.
+    pub fn set_processing(&mut self, processing: AudioProcessing) {
+        self.processing = processing;
+        self.rtc_source
+            .set_audio_options(processing.source_options());
+    }
+
+    pub fn is_published(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    pub async fn publish(&mut self) -> Result<(), RoomError> {
+        self.unpublish().await?;
+
+        let num_channels = self.processing.num_channels(self.params.num_channels);
+        if num_channels != self.rtc_source.num_channels() {
+            self.rtc_source = NativeAudioSource::new(
+                self.processing.source_options(),
+                self.params.sample_rate,
+                num_channels,
+                1000,
+            );
+        }
+        // Capture with the same layout the source was created with
+        let params = MicrophoneParameters {
+            num_channels,
+            ..self.params.clone()
+        };
+
+        let (close_tx, close_rx) = oneshot::channel();
+        let track = LocalAudioTrack::create_audio_track(
+            "microphone",
+            RtcAudioSource::Native(self.rtc_source.clone()),
+        );
+
+        let (samples_tx, samples_rx) = mpsc::unbounded_channel();
+        let capture = CaptureStream::new(
+            params.target.clone(),
+            params.sample_rate,
+            params.num_channels,
+            samples_tx.clone(),
+        );
+
+        let task = tokio::spawn(Self::track_task(
+            close_rx,
+            samples_rx,
+            self.rtc_source.clone(),
+            self.meter.clone(),
+            params,
+        ));
+
+        self.room
+            .local_participant()
+            .publish_track(
+                LocalTrack::Audio(track.clone()),
+                self.processing.publish_options(TrackSource::Microphone),
+            )
+            .await?;
+
+        let handle = TrackHandle {
+            close_tx,
+            track,
+            task,
+            samples_tx,
+            capture: Some(capture),
+        };
+
+        self.handle = Some(handle);
+        Ok(())
+    }
+
+    pub fn target(&self) -> Option<&str> {
+        self.params.target.as_deref()
+    }
+
+    /// Capture from another PipeWire node, the published track is kept as is.
+    pub fn set_target(&mut self, target: Option<String>) {
+        if self.params.target == target {
+            return;
+        }
+        self.params.target = target;
+
+        if let Some(handle) = self.handle.as_mut() {
+            // The old stream has to be torn down first so both never capture at once
+            handle.capture.take();
+            handle.capture = Some(CaptureStream::new(
+                self.params.target.clone(),
+                self.params.sample_rate,
+                self.rtc_source.num_channels(),
+                handle.samples_tx.clone(),
+            ));
+        }
+    }
+
+    pub async fn unpublish(&mut self) -> Result<(), RoomError> {
+        if let Some(handle) = self.handle.take() {
+            handle.close_tx.send(()).ok();
+            handle.task.await.ok();
+            self.room
+                .local_participant()
+                .unpublish_track(&handle.track.sid())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn track_task(
+        mut close_rx: oneshot::Receiver<()>,
+        mut samples_rx: mpsc::UnboundedReceiver<Vec<i16>>,
+        rtc_source: NativeAudioSource,
+        meter: LevelMeter,
+        params: MicrophoneParameters,
+    ) {
+        let mut frames = Frames10ms::new(params.sample_rate, params.num_channels);
+
+        loop {
+            tokio::select! {
+                _ = &mut close_rx => {
+                    break;
+                }
+                samples = samples_rx.recv() => {
+                    let Some(samples) = samples else {
+                        break;
+                    };
+                    frames.push(&samples);
+                }
+            }
+
+            while let Some(samples_10ms) = frames.pop() {
+                meter.update(&samples_10ms);
+                let res = rtc_source
+                    .capture_frame(&AudioFrame {
+                        data: samples_10ms.into(),
+                        sample_rate: params.sample_rate,
+                        num_channels: params.num_channels,
+                        samples_per_channel: params.sample_rate / 100,
+                    })
+                    .await;
+
+                if let Err(err) = res {
+                    log::error!("failed to capture microphone frame: {:?}", err);
+                }
+            }
+        }
+    }
+}
+
+/// Re-chunks captured audio into 10ms frames.
+///
+/// PipeWire hands us buffers of whatever size the graph runs at, WebRTC wants 10ms.
+struct Frames10ms {
+    pending: Vec<i16>,
+    samples_count: usize,
+}
+
+impl Frames10ms {
+    fn new(sample_rate: u32, num_channels: u32) -> Self {
+        let samples_count = (sample_rate / 100) as usize * num_channels as usize;
+        Self {
+            pending: Vec::with_capacity(samples_count * 2),
+            samples_count,
+        }
+    }
+
+    fn push(&mut self, samples: &[i16]) {
+        self.pending.extend_from_slice(samples);
+    }
+
+    /// The oldest complete frame, the rest waits for more samples.
+    fn pop(&mut self) -> Option<Vec<i16>> {
+        (self.pending.len() >= self.samples_count)
+            .then(|| self.pending.drain(..self.samples_count).collect())
+    }
+}
+
+impl Drop for MicrophoneTrack {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.close_tx.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_10ms_of_interleaved_samples() {
+        let mut frames = Frames10ms::new(48000, 2);
+        let samples = (0..2500).map(|i| i as i16).collect::<Vec<_>>();
+
+        // Buffer sizes don't line up with frames
+        frames.push(&samples[..700]);
+        assert_eq!(frames.pop(), None);
+        frames.push(&samples[700..2500]);
+
+        assert_eq!(frames.pop(), Some(samples[..960].to_vec()));
+        assert_eq!(frames.pop(), Some(samples[960..1920].to_vec()));
+        assert_eq!(frames.pop(), None);
+
+        frames.push(&samples[..380]);
+        let frame = frames.pop().unwrap();
+        assert_eq!(frame[..580], samples[1920..2500]);
+        assert_eq!(frame[580..], samples[..380]);
+    }
+
+    #[test]
+    fn frames_follow_the_sample_rate() {
+        let mut frames = Frames10ms::new(16000, 1);
+        frames.push(&[0; 320]);
+        assert_eq!(frames.pop().map(|frame| frame.len()), Some(160));
+        assert_eq!(frames.pop().map(|frame| frame.len()), Some(160));
+        assert_eq!(frames.pop(), None);
+    }
+}
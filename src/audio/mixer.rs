@@ -0,0 +1,310 @@
+use livekit::prelude::*;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use super::{AudioLevel, LevelMeter, NUM_CHANNELS, SAMPLE_RATE};
+
+// Drop the oldest samples once a source gets this far ahead of playback
+const MAX_BUFFERED_MS: usize = 200;
+// Keep the outermost participants slightly in the other ear, hard panning is tiring
+const MAX_PAN: f32 = 0.8;
+
+/// Identifies a single remote audio track inside the mixer.
+pub type MixerKey = (ParticipantIdentity, TrackSid);
+
+/// Local playback volume of a single participant, this never affects what others hear.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ParticipantVolume {
+    pub gain: f32,
+    pub muted: bool,
+}
+
+impl Default for ParticipantVolume {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl ParticipantVolume {
+    fn effective_gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.gain
+        }
+    }
+}
+
+/// Mixer volumes, participants are keyed by identity so they survive reconnects.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MixerSettings {
+    pub master_volume: f32,
+    pub participants: HashMap<String, ParticipantVolume>,
+}
+
+impl Default for MixerSettings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.0,
+            participants: HashMap::new(),
+        }
+    }
+}
+
+impl MixerSettings {
+    pub fn participant(&self, identity: &ParticipantIdentity) -> ParticipantVolume {
+        self.participants
+            .get(identity.as_str())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn set_participant(&mut self, identity: &ParticipantIdentity, volume: ParticipantVolume) {
+        self.participants
+            .insert(identity.as_str().to_string(), volume);
+    }
+}
+
+#[derive(Default)]
+struct MixerSource {
+    buffer: VecDeque<i16>,
+    meter: LevelMeter,
+}
+
+/// A copy of the mix on its way out, blocks come back on `spare_rx` to be filled again.
+struct MixerTap {
+    tx: mpsc::UnboundedSender<Vec<i16>>,
+    spare_rx: mpsc::UnboundedReceiver<Vec<i16>>,
+}
+
+#[derive(Default)]
+struct MixerInner {
+    sources: HashMap<MixerKey, MixerSource>,
+    settings: MixerSettings,
+    taps: Vec<MixerTap>,
+    spatial: bool,
+    /// position of each participant from -1.0 (left) to 1.0 (right), missing ones are centered
+    pans: HashMap<ParticipantIdentity, f32>,
+    /// local sounds waiting to be played, overlapping sounds are already summed
+    cues: VecDeque<i16>,
+    /// sum of the sources, kept between blocks so mixing doesn't allocate
+    acc: Vec<i32>,
+}
+
+/// Sums every remote audio track into a single interleaved stream.
+///
+/// Tracks push decoded frames as they arrive while the PipeWire playback thread pulls
+/// mixed samples on its own schedule, so every source is buffered independently.
+#[derive(Clone, Default)]
+pub struct AudioMixer {
+    inner: Arc<Mutex<MixerInner>>,
+}
+
+impl AudioMixer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn settings(&self) -> MixerSettings {
+        self.inner.lock().settings.clone()
+    }
+
+    pub fn set_settings(&self, settings: MixerSettings) {
+        self.inner.lock().settings = settings;
+    }
+
+    /// Pan participants by their position while enabled, this only applies to stereo output.
+    pub fn set_spatial(&self, spatial: bool) {
+        self.inner.lock().spatial = spatial;
+    }
+
+    pub fn set_pans(&self, pans: HashMap<ParticipantIdentity, f32>) {
+        self.inner.lock().pans = pans;
+    }
+
+    /// Play a local sound on top of the mix, it is not sent to taps.
+    pub fn play(&self, samples: &[i16]) {
+        let mut inner = self.inner.lock();
+        let cues = &mut inner.cues;
+        for (i, sample) in samples.iter().enumerate() {
+            match cues.get_mut(i) {
+                Some(queued) => *queued = queued.saturating_add(*sample),
+                None => cues.push_back(*sample),
+            }
+        }
+    }
+
+    pub fn add_source(&self, key: MixerKey) {
+        self.inner.lock().sources.entry(key).or_default();
+    }
+
+    pub fn remove_source(&self, key: &MixerKey) {
+        self.inner.lock().sources.remove(key);
+    }
+
+    pub fn clear(&self) {
+        self.inner.lock().sources.clear();
+    }
+
+    /// Queue interleaved samples for a source, samples for unknown sources are dropped.
+    pub fn push(&self, key: &MixerKey, samples: &[i16]) {
+        let mut inner = self.inner.lock();
+        let Some(source) = inner.sources.get_mut(key) else {
+            return;
+        };
+
+        source.meter.update(samples);
+        source.buffer.extend(samples.iter().copied());
+
+        let max_len = (SAMPLE_RATE as usize / 1000) * MAX_BUFFERED_MS * NUM_CHANNELS as usize;
+        if source.buffer.len() > max_len {
+            let excess = source.buffer.len() - max_len;
+            source.buffer.drain(..excess);
+        }
+    }
+
+    /// Receive a copy of every mixed block, the tap is removed once `tx` is closed.
+    ///
+    /// Blocks sent back on `spare_rx` are refilled rather than allocating new ones on the
+    /// playback thread, which only happens while none are back yet.
+    pub fn add_tap(
+        &self,
+        tx: mpsc::UnboundedSender<Vec<i16>>,
+        spare_rx: mpsc::UnboundedReceiver<Vec<i16>>,
+    ) {
+        self.inner.lock().taps.push(MixerTap { tx, spare_rx });
+    }
+
+    /// Level of everything a participant publishes, measured before any local gain.
+    pub fn participant_level(&self, identity: &ParticipantIdentity) -> AudioLevel {
+        self.inner
+            .lock()
+            .sources
+            .iter()
+            .filter(|((source_identity, _), _)| source_identity == identity)
+            .fold(AudioLevel::default(), |level, (_, source)| {
+                level.max(source.meter.level())
+            })
+    }
+
+    /// Fill `out` with the sum of every source, sources that ran dry contribute silence.
+    /// Sound cues are added last.
+    pub fn mix(&self, out: &mut [i16]) {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        // Only reallocates when the period grows
+        inner.acc.clear();
+        inner.acc.resize(out.len(), 0);
+
+        let spatial = inner.spatial && NUM_CHANNELS == 2;
+
+        for ((identity, _), source) in inner.sources.iter_mut() {
+            let gain = inner.settings.participant(identity).effective_gain()
+                * inner.settings.master_volume;
+            let channel_gains = if spatial {
+                let pan = inner.pans.get(identity).copied().unwrap_or_default();
+                pan_gains(pan * MAX_PAN)
+            } else {
+                [1.0, 1.0]
+            };
+
+            let len = out.len().min(source.buffer.len());
+            let samples = inner.acc.iter_mut().zip(source.buffer.drain(..len));
+            for (i, (sample, value)) in samples.enumerate() {
+                let gain = gain * channel_gains[i % channel_gains.len()];
+                *sample += (value as f32 * gain) as i32;
+            }
+        }
+
+        for (sample, value) in out.iter_mut().zip(&inner.acc) {
+            *sample = (*value).clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        }
+
+        inner.taps.retain_mut(|tap| {
+            let mut block = tap.spare_rx.try_recv().unwrap_or_default();
+            block.clear();
+            block.extend_from_slice(out);
+            tap.tx.send(block).is_ok()
+        });
+
+        let len = out.len().min(inner.cues.len());
+        let master_volume = inner.settings.master_volume;
+        for (sample, value) in out.iter_mut().zip(inner.cues.drain(..len)) {
+            *sample = sample.saturating_add((value as f32 * master_volume) as i16);
+        }
+    }
+}
+
+/// Constant power pan law, scaled so a centered source keeps its level.
+fn pan_gains(pan: f32) -> [f32; 2] {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+    let scale = std::f32::consts::SQRT_2;
+    [
+        (angle.cos() * scale).min(1.0),
+        (angle.sin() * scale).min(1.0),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_gains(pan: f32, expected: [f32; 2]) {
+        let gains = pan_gains(pan);
+        assert!(
+            gains
+                .iter()
+                .zip(expected)
+                .all(|(gain, expected)| (gain - expected).abs() < 1e-5),
+            "pan {} gave {:?}, expected {:?}",
+            pan,
+            gains,
+            expected
+        );
+    }
+
+    #[test]
+    fn pan_gains_keep_the_centre_at_full_volume() {
+        assert_gains(0.0, [1.0, 1.0]);
+    }
+
+    #[test]
+    fn pan_gains_silence_the_far_side() {
+        assert_gains(-1.0, [1.0, 0.0]);
+        assert_gains(1.0, [0.0, 1.0]);
+        // Out of range pans are clamped
+        assert_gains(-3.0, [1.0, 0.0]);
+        assert_gains(3.0, [0.0, 1.0]);
+    }
+
+    #[test]
+    fn pan_gains_fade_the_far_side_only() {
+        // Constant power: sin(pi / 8) * sqrt(2)
+        assert_gains(0.5, [0.541_196, 1.0]);
+        assert_gains(-0.5, [1.0, 0.541_196]);
+    }
+
+    #[test]
+    fn taps_refill_the_blocks_sent_back() {
+        let mixer = AudioMixer::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let (spare_tx, spare_rx) = mpsc::unbounded_channel();
+        mixer.add_tap(tx, spare_rx);
+
+        let mut out = [0i16; 960];
+        mixer.mix(&mut out);
+        let block = rx.try_recv().unwrap();
+        assert_eq!(block.len(), out.len());
+        let data = block.as_ptr();
+        spare_tx.send(block).unwrap();
+
+        mixer.mix(&mut out);
+        assert_eq!(rx.try_recv().unwrap().as_ptr(), data);
+    }
+}
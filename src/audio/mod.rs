@@ -1 +1,85 @@
+//! Local audio devices backed by PipeWire.
+//!
+//! Remote participants are decoded by WebRTC into 16-bit PCM, pushed into an [`AudioMixer`]
+//! and played back by [`AudioOutput`] through a single PipeWire playback stream. Local audio
+//! is captured from a PipeWire input node by [`MicrophoneTrack`], and [`DeviceMonitor`] keeps
+//! track of which sources and sinks are currently available. Synthesized and decoded audio,
+//! such as the signal generator or an [`AudioFile`], is published through a
+//! [`GeneratedTrack`]. A [`Recorder`] taps the mix to keep a copy of the call, and
+//! [`SoundCue`]s are played on top of it. What other applications play can be captured
+//! with [`SystemAudioCapture`] to share it along with the screen.
+pub mod capture;
+pub mod cues;
+pub mod devices;
+pub mod file;
+pub mod levels;
+pub mod microphone;
+pub mod mixer;
+pub mod output;
+pub mod processing;
+pub mod recorder;
+pub mod system;
+pub mod track;
 
+pub use capture::CaptureStream;
+pub use cues::{test_tone, SoundCue, SoundCues};
+pub use devices::{AudioDevice, DeviceEvent, DeviceKind, DeviceMonitor};
+pub use file::{AudioFile, AudioFileError, FileGenerator};
+pub use levels::{AudioLevel, LevelMeter};
+pub use microphone::{MicrophoneParameters, MicrophoneTrack};
+pub use mixer::{AudioMixer, MixerKey, MixerSettings, ParticipantVolume};
+pub use output::AudioOutput;
+pub use processing::AudioProcessing;
+pub use recorder::{Recorder, RecorderError, RecordingFormat};
+pub use system::{SystemAudioCapture, SystemAudioGenerator};
+pub use track::{AudioGenerator, GeneratedTrack};
+
+use pipewire as pw;
+use pw::spa;
+use pw::spa::pod::Pod;
+
+/// Sample rate used for every local audio stream, this is what WebRTC works with natively.
+pub const SAMPLE_RATE: u32 = 48000;
+/// Channel count used for every local audio stream.
+pub const NUM_CHANNELS: u32 = 2;
+// WebRTC uses 16-bit signed PCM
+pub(crate) const SAMPLE_SIZE: usize = std::mem::size_of::<i16>();
+
+/// Messages sent to the PipeWire main loop running on a dedicated thread.
+pub(crate) enum PwCmd {
+    Terminate,
+}
+
+/// Serialize an `EnumFormat` param describing interleaved S16LE audio.
+pub(crate) fn s16_format_param(sample_rate: u32, num_channels: u32) -> Vec<u8> {
+    let mut audio_info = spa::param::audio::AudioInfoRaw::new();
+    audio_info.set_format(spa::param::audio::AudioFormat::S16LE);
+    audio_info.set_rate(sample_rate);
+    audio_info.set_channels(num_channels);
+
+    let mut position = [0; spa::param::audio::MAX_CHANNELS];
+    if num_channels == 1 {
+        position[0] = spa::sys::SPA_AUDIO_CHANNEL_MONO;
+    } else {
+        position[0] = spa::sys::SPA_AUDIO_CHANNEL_FL;
+        position[1] = spa::sys::SPA_AUDIO_CHANNEL_FR;
+    }
+    audio_info.set_position(position);
+
+    pw::spa::pod::serialize::PodSerializer::serialize(
+        std::io::Cursor::new(Vec::new()),
+        &pw::spa::pod::Value::Object(pw::spa::pod::Object {
+            type_: spa::sys::SPA_TYPE_OBJECT_Format,
+            id: spa::sys::SPA_PARAM_EnumFormat,
+            properties: audio_info.into(),
+        }),
+    )
+    .unwrap()
+    .0
+    .into_inner()
+}
+
+/// Borrow a serialized param as a [`Pod`] suitable for `Stream::connect`.
+pub(crate) fn param_pod(values: &[u8]) -> &Pod {
+    Pod::from_bytes(values).unwrap()
+}
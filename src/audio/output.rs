@@ -0,0 +1,248 @@
+use futures::StreamExt;
+use livekit::webrtc::audio_stream::native::NativeAudioStream;
+use livekit::webrtc::prelude::*;
+use pipewire as pw;
+use pw::properties::properties;
+use std::collections::HashMap;
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use super::{
+    param_pod, s16_format_param, AudioMixer, MixerKey, PwCmd, NUM_CHANNELS, SAMPLE_RATE,
+    SAMPLE_SIZE,
+};
+
+struct StreamHandle {
+    close_tx: oneshot::Sender<()>,
+    #[allow(dead_code)]
+    task: JoinHandle<()>,
+}
+
+struct PlaybackData {
+    mixer: AudioMixer,
+    scratch: Vec<i16>,
+}
+
+/// The PipeWire playback stream, running its main loop on a dedicated thread.
+struct PlaybackStream {
+    pw_tx: pw::channel::Sender<PwCmd>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl PlaybackStream {
+    fn new(mixer: AudioMixer, target: Option<String>) -> Self {
+        let (pw_tx, pw_rx) = pw::channel::channel();
+
+        let thread = std::thread::spawn(move || {
+            if let Err(err) = playback_thread(mixer, target, pw_rx) {
+                log::error!("audio playback failed: {:?}", err);
+            }
+        });
+
+        Self {
+            pw_tx,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for PlaybackStream {
+    fn drop(&mut self) {
+        let _ = self.pw_tx.send(PwCmd::Terminate);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Plays every subscribed remote audio track through a PipeWire playback stream.
+///
+/// Each track is read from its own [`NativeAudioStream`] on the async runtime and fed into
+/// the shared [`AudioMixer`], so the playback stream can be moved to another device without
+/// touching the tracks.
+pub struct AudioOutput {
+    mixer: AudioMixer,
+    streams: HashMap<MixerKey, StreamHandle>,
+    async_handle: Handle,
+    target: Option<String>,
+    playback: Option<PlaybackStream>,
+}
+
+impl AudioOutput {
+    pub fn new(async_handle: &Handle) -> Self {
+        let mixer = AudioMixer::new();
+        let playback = PlaybackStream::new(mixer.clone(), None);
+
+        Self {
+            mixer,
+            streams: HashMap::new(),
+            async_handle: async_handle.clone(),
+            target: None,
+            playback: Some(playback),
+        }
+    }
+
+    pub fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    /// Play through another PipeWire sink, `None` follows the default sink.
+    pub fn set_target(&mut self, target: Option<String>) {
+        if self.target == target {
+            return;
+        }
+        self.target = target;
+
+        self.playback.take();
+        self.playback = Some(PlaybackStream::new(self.mixer.clone(), self.target.clone()));
+    }
+
+    pub fn mixer(&self) -> &AudioMixer {
+        &self.mixer
+    }
+
+    /// Start playing a remote audio track, replacing any stream already playing under `key`.
+    pub fn add_track(&mut self, key: MixerKey, rtc_track: RtcAudioTrack) {
+        self.remove_track(&key);
+        self.mixer.add_source(key.clone());
+
+        let (close_tx, close_rx) = oneshot::channel();
+        let stream = NativeAudioStream::new(rtc_track, SAMPLE_RATE as i32, NUM_CHANNELS as i32);
+        let task = self.async_handle.spawn(Self::track_task(
+            close_rx,
+            stream,
+            self.mixer.clone(),
+            key.clone(),
+        ));
+
+        self.streams.insert(key, StreamHandle { close_tx, task });
+    }
+
+    pub fn remove_track(&mut self, key: &MixerKey) {
+        if let Some(handle) = self.streams.remove(key) {
+            let _ = handle.close_tx.send(());
+        }
+        self.mixer.remove_source(key);
+    }
+
+    /// Stop every remote stream, used when leaving the room.
+    pub fn clear(&mut self) {
+        for (_, handle) in self.streams.drain() {
+            let _ = handle.close_tx.send(());
+        }
+        self.mixer.clear();
+    }
+
+    async fn track_task(
+        mut close_rx: oneshot::Receiver<()>,
+        mut stream: NativeAudioStream,
+        mixer: AudioMixer,
+        key: MixerKey,
+    ) {
+        loop {
+            tokio::select! {
+                _ = &mut close_rx => {
+                    break;
+                }
+                frame = stream.next() => {
+                    let Some(frame) = frame else {
+                        break;
+                    };
+                    mixer.push(&key, &frame.data);
+                }
+            }
+        }
+
+        stream.close();
+    }
+}
+
+impl Drop for AudioOutput {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+fn playback_thread(
+    mixer: AudioMixer,
+    target: Option<String>,
+    pw_rx: pw::channel::Receiver<PwCmd>,
+) -> Result<(), pw::Error> {
+    let mainloop = pw::main_loop::MainLoopRc::new(None)?;
+    let context = pw::context::ContextRc::new(&mainloop, None)?;
+    let core = context.connect_rc(None)?;
+
+    let mut props = properties! {
+        *pw::keys::MEDIA_TYPE => "Audio",
+        *pw::keys::MEDIA_ROLE => "Communication",
+        *pw::keys::MEDIA_CATEGORY => "Playback",
+        *pw::keys::AUDIO_CHANNELS => NUM_CHANNELS.to_string(),
+    };
+    if let Some(target) = target {
+        props.insert(*pw::keys::TARGET_OBJECT, target);
+    }
+
+    let stream = pw::stream::StreamBox::new(&core, "verdant-playback", props)?;
+
+    let data = PlaybackData {
+        mixer,
+        scratch: Vec::new(),
+    };
+
+    let _listener = stream
+        .add_local_listener_with_user_data(data)
+        .process(|stream, data| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+
+            let datas = buffer.datas_mut();
+            if datas.is_empty() {
+                return;
+            }
+
+            let stride = SAMPLE_SIZE * NUM_CHANNELS as usize;
+            let out = &mut datas[0];
+            let n_frames = if let Some(slice) = out.data() {
+                let n_frames = slice.len() / stride;
+                data.scratch.resize(n_frames * NUM_CHANNELS as usize, 0);
+                data.mixer.mix(&mut data.scratch);
+
+                for (chunk, sample) in slice.chunks_exact_mut(SAMPLE_SIZE).zip(&data.scratch) {
+                    chunk.copy_from_slice(&sample.to_le_bytes());
+                }
+                n_frames
+            } else {
+                0
+            };
+
+            let chunk = out.chunk_mut();
+            *chunk.offset_mut() = 0;
+            *chunk.stride_mut() = stride as _;
+            *chunk.size_mut() = (stride * n_frames) as _;
+        })
+        .register()?;
+
+    let values = s16_format_param(SAMPLE_RATE, NUM_CHANNELS);
+    let mut params = [param_pod(&values)];
+
+    stream.connect(
+        pw::spa::utils::Direction::Output,
+        None,
+        pw::stream::StreamFlags::AUTOCONNECT
+            | pw::stream::StreamFlags::MAP_BUFFERS
+            | pw::stream::StreamFlags::RT_PROCESS,
+        &mut params,
+    )?;
+
+    let _receiver = pw_rx.attach(mainloop.loop_(), {
+        let mainloop = mainloop.clone();
+        move |cmd| match cmd {
+            PwCmd::Terminate => mainloop.quit(),
+        }
+    });
+
+    mainloop.run();
+    Ok(())
+}
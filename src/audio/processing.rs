@@ -0,0 +1,76 @@
+use livekit::options::{audio, TrackPublishOptions};
+use livekit::prelude::*;
+use livekit::webrtc::prelude::AudioSourceOptions;
+use serde::{Deserialize, Serialize};
+
+/// WebRTC processing applied to the audio we publish.
+///
+/// Music mode is a preset for sharing music or instruments: processing is turned off and
+/// the track is published in stereo at a high bitrate without DTX, so quiet passages are
+/// not cut.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioProcessing {
+    pub echo_cancellation: bool,
+    pub noise_suppression: bool,
+    pub auto_gain_control: bool,
+    pub music_mode: bool,
+}
+
+impl Default for AudioProcessing {
+    fn default() -> Self {
+        Self {
+            echo_cancellation: true,
+            noise_suppression: true,
+            auto_gain_control: true,
+            music_mode: false,
+        }
+    }
+}
+
+impl AudioProcessing {
+    pub fn music() -> Self {
+        Self {
+            echo_cancellation: false,
+            noise_suppression: false,
+            auto_gain_control: false,
+            music_mode: true,
+        }
+    }
+
+    pub fn source_options(&self) -> AudioSourceOptions {
+        if self.music_mode {
+            return AudioSourceOptions::default();
+        }
+
+        AudioSourceOptions {
+            echo_cancellation: self.echo_cancellation,
+            noise_suppression: self.noise_suppression,
+            auto_gain_control: self.auto_gain_control,
+        }
+    }
+
+    /// Channel count to capture with, music mode always publishes stereo.
+    pub fn num_channels(&self, requested: u32) -> u32 {
+        if self.music_mode {
+            2
+        } else {
+            requested
+        }
+    }
+
+    pub fn publish_options(&self, source: TrackSource) -> TrackPublishOptions {
+        if self.music_mode {
+            return TrackPublishOptions {
+                source,
+                audio_encoding: Some(audio::MUSIC_HIGH_QUALITY_STEREO.encoding),
+                dtx: false,
+                ..Default::default()
+            };
+        }
+
+        TrackPublishOptions {
+            source,
+            ..Default::default()
+        }
+    }
+}
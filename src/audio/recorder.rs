@@ -0,0 +1,355 @@
+use audiopus::{coder::Encoder, Application, Channels, SampleRate};
+use ogg::writing::PacketWriteEndInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+use super::{AudioMixer, CaptureStream, NUM_CHANNELS, SAMPLE_RATE};
+
+// Opus frames are 20ms, the usual size for stored audio
+const OPUS_FRAME_SAMPLES: usize = SAMPLE_RATE as usize / 50;
+const OPUS_MAX_PACKET: usize = 4000;
+const OPUS_BITRATE: i32 = 128_000;
+// Microphone audio that is further ahead of the mix than this is dropped
+const MAX_MIC_BUFFERED_MS: usize = 200;
+// Blocks handed to the mixer up front, each with room for a long playback period
+const TAP_BLOCKS: usize = 4;
+const TAP_BLOCK_MS: usize = 200;
+
+#[derive(Debug, Error)]
+pub enum RecorderError {
+    #[error("failed to write recording: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to write wav file: {0}")]
+    Wav(#[from] hound::Error),
+    #[error("failed to encode opus: {0}")]
+    Opus(#[from] audiopus::Error),
+    #[error("recorder thread panicked")]
+    Panicked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RecordingFormat {
+    #[default]
+    Wav,
+    OggOpus,
+}
+
+impl RecordingFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecordingFormat::Wav => "wav",
+            RecordingFormat::OggOpus => "ogg",
+        }
+    }
+}
+
+enum Writer {
+    Wav(hound::WavWriter<BufWriter<File>>),
+    Opus(OpusWriter),
+}
+
+impl Writer {
+    fn create(path: &Path, format: RecordingFormat) -> Result<Self, RecorderError> {
+        Ok(match format {
+            RecordingFormat::Wav => Writer::Wav(hound::WavWriter::create(
+                path,
+                hound::WavSpec {
+                    channels: NUM_CHANNELS as u16,
+                    sample_rate: SAMPLE_RATE,
+                    bits_per_sample: 16,
+                    sample_format: hound::SampleFormat::Int,
+                },
+            )?),
+            RecordingFormat::OggOpus => Writer::Opus(OpusWriter::create(path)?),
+        })
+    }
+
+    fn write(&mut self, samples: &[i16]) -> Result<(), RecorderError> {
+        match self {
+            Writer::Wav(writer) => {
+                let mut writer = writer.get_i16_writer(samples.len() as u32);
+                for sample in samples {
+                    writer.write_sample(*sample);
+                }
+                writer.flush()?;
+            }
+            Writer::Opus(writer) => writer.write(samples)?,
+        }
+        Ok(())
+    }
+
+    fn finalize(self) -> Result<(), RecorderError> {
+        match self {
+            Writer::Wav(writer) => writer.finalize()?,
+            Writer::Opus(writer) => writer.finalize()?,
+        }
+        Ok(())
+    }
+}
+
+/// Minimal Ogg/Opus muxer following RFC 7845.
+struct OpusWriter {
+    writer: ogg::PacketWriter<BufWriter<File>>,
+    encoder: Encoder,
+    serial: u32,
+    pending: Vec<i16>,
+    /// samples the decoder drops at the start, the encoder's lookahead
+    pre_skip: u64,
+    /// samples per channel recorded so far, without padding
+    samples: u64,
+    /// samples per channel encoded so far, counting from the start of the decoder output
+    granule: u64,
+    packet: Vec<u8>,
+}
+
+impl OpusWriter {
+    fn create(path: &Path) -> Result<Self, RecorderError> {
+        let mut writer = ogg::PacketWriter::new(BufWriter::new(File::create(path)?));
+        let mut encoder = Encoder::new(SampleRate::Hz48000, Channels::Stereo, Application::Audio)?;
+        encoder.set_bitrate(audiopus::Bitrate::BitsPerSecond(OPUS_BITRATE))?;
+        let pre_skip = encoder.lookahead()? as u16;
+        let serial = rand::random();
+
+        let mut head = b"OpusHead".to_vec();
+        head.push(1); // version
+        head.push(NUM_CHANNELS as u8);
+        head.extend_from_slice(&pre_skip.to_le_bytes());
+        head.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+        head.push(0); // mono or stereo channel mapping
+        writer.write_packet(head.into(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+        let vendor = env!("CARGO_PKG_NAME").as_bytes();
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor);
+        tags.extend_from_slice(&0u32.to_le_bytes()); // no user comments
+        writer.write_packet(tags.into(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(Self {
+            writer,
+            encoder,
+            serial,
+            pending: Vec::new(),
+            pre_skip: pre_skip as u64,
+            samples: 0,
+            granule: 0,
+            packet: vec![0; OPUS_MAX_PACKET],
+        })
+    }
+
+    fn write(&mut self, samples: &[i16]) -> Result<(), RecorderError> {
+        self.pending.extend_from_slice(samples);
+        self.samples += (samples.len() / NUM_CHANNELS as usize) as u64;
+
+        let frame_len = OPUS_FRAME_SAMPLES * NUM_CHANNELS as usize;
+        while self.pending.len() >= frame_len {
+            let frame: Vec<i16> = self.pending.drain(..frame_len).collect();
+            self.write_frame(&frame, PacketWriteEndInfo::NormalPacket)?;
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self, frame: &[i16], end: PacketWriteEndInfo) -> Result<(), RecorderError> {
+        let len = self.encoder.encode(frame, &mut self.packet)?;
+        self.granule += OPUS_FRAME_SAMPLES as u64;
+        let granule = match end {
+            // A last granule position short of what was encoded trims the padding
+            PacketWriteEndInfo::EndStream => self.pre_skip + self.samples,
+            _ => self.granule,
+        };
+        self.writer.write_packet(
+            self.packet[..len].to_vec().into(),
+            self.serial,
+            end,
+            granule,
+        )?;
+        Ok(())
+    }
+
+    fn finalize(mut self) -> Result<(), RecorderError> {
+        // Pad with silence until the encoder delay has pushed out everything recorded, the
+        // stream has to be terminated by a packet
+        let frame_len = OPUS_FRAME_SAMPLES * NUM_CHANNELS as usize;
+        let end = self.pre_skip + self.samples;
+        let mut frame = std::mem::take(&mut self.pending);
+        loop {
+            frame.resize(frame_len, 0);
+            if self.granule + OPUS_FRAME_SAMPLES as u64 >= end {
+                return self.write_frame(&frame, PacketWriteEndInfo::EndStream);
+            }
+            self.write_frame(&frame, PacketWriteEndInfo::NormalPacket)?;
+            frame.clear();
+        }
+    }
+}
+
+/// Writes the remote audio mix, optionally with the local microphone, to a file.
+///
+/// The mix is tapped after participant and master volume, so the recording sounds like
+/// what is played locally. Writing happens on a dedicated thread and stops when the
+/// recorder is stopped or dropped.
+pub struct Recorder {
+    path: PathBuf,
+    started: Instant,
+    stop_tx: mpsc::UnboundedSender<Vec<i16>>,
+    thread: Option<std::thread::JoinHandle<Result<(), RecorderError>>>,
+    _microphone: Option<CaptureStream>,
+}
+
+impl Recorder {
+    /// Start recording into `path`, the file is created right away so errors are reported
+    /// before anything is captured.
+    ///
+    /// `microphone` is the PipeWire node to mix in, `Some(None)` records the default source.
+    pub fn start(
+        path: PathBuf,
+        format: RecordingFormat,
+        mixer: &AudioMixer,
+        microphone: Option<Option<String>>,
+    ) -> Result<Self, RecorderError> {
+        let writer = Writer::create(&path, format)?;
+
+        let (mix_tx, mix_rx) = mpsc::unbounded_channel();
+        let (spare_tx, spare_rx) = mpsc::unbounded_channel();
+        let (mic_tx, mic_rx) = mpsc::unbounded_channel();
+
+        let block_len = (SAMPLE_RATE as usize / 1000) * TAP_BLOCK_MS * NUM_CHANNELS as usize;
+        for _ in 0..TAP_BLOCKS {
+            let _ = spare_tx.send(Vec::with_capacity(block_len));
+        }
+
+        let microphone =
+            microphone.map(|target| CaptureStream::new(target, SAMPLE_RATE, NUM_CHANNELS, mic_tx));
+
+        let thread = std::thread::spawn(move || record_thread(writer, mix_rx, spare_tx, mic_rx));
+        mixer.add_tap(mix_tx.clone(), spare_rx);
+
+        Ok(Self {
+            path,
+            started: Instant::now(),
+            stop_tx: mix_tx,
+            thread: Some(thread),
+            _microphone: microphone,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn elapsed(&self) -> std::time::Duration {
+        self.started.elapsed()
+    }
+
+    /// Stop recording and finish the file.
+    pub fn stop(mut self) -> Result<(), RecorderError> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<(), RecorderError> {
+        // The mixer never produces empty blocks, so an empty one marks the end
+        let _ = self.stop_tx.send(Vec::new());
+        match self.thread.take() {
+            Some(thread) => thread.join().map_err(|_| RecorderError::Panicked)?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            log::error!("failed to finish recording: {:?}", err);
+        }
+    }
+}
+
+fn record_thread(
+    mut writer: Writer,
+    mut mix_rx: mpsc::UnboundedReceiver<Vec<i16>>,
+    spare_tx: mpsc::UnboundedSender<Vec<i16>>,
+    mut mic_rx: mpsc::UnboundedReceiver<Vec<i16>>,
+) -> Result<(), RecorderError> {
+    let mut mic = VecDeque::new();
+    let max_mic = (SAMPLE_RATE as usize / 1000) * MAX_MIC_BUFFERED_MS * NUM_CHANNELS as usize;
+
+    while let Some(mut samples) = mix_rx.blocking_recv() {
+        if samples.is_empty() {
+            break;
+        }
+
+        while let Ok(captured) = mic_rx.try_recv() {
+            mic.extend(captured);
+        }
+        if mic.len() > max_mic {
+            let excess = mic.len() - max_mic;
+            mic.drain(..excess);
+        }
+
+        let len = samples.len().min(mic.len());
+        for (sample, value) in samples.iter_mut().zip(mic.drain(..len)) {
+            *sample = sample.saturating_add(value);
+        }
+
+        writer.write(&samples)?;
+        // Back to the mixer to be filled again
+        let _ = spare_tx.send(samples);
+    }
+
+    writer.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opus_granules_count_from_zero_and_trim_the_padding() {
+        let path = std::env::temp_dir().join(format!("recorder-test-{}.ogg", std::process::id()));
+        // Not a multiple of the frame size, so the last frame is padded
+        let samples = OPUS_FRAME_SAMPLES as u64 * 5 + 123;
+
+        let mut writer = OpusWriter::create(&path).unwrap();
+        let pre_skip = writer.pre_skip;
+        writer
+            .write(&vec![1000; samples as usize * NUM_CHANNELS as usize])
+            .unwrap();
+        writer.finalize().unwrap();
+
+        let mut reader = ogg::PacketReader::new(File::open(&path).unwrap());
+        let mut packets = Vec::new();
+        while let Some(packet) = reader.read_packet().unwrap() {
+            packets.push(packet);
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(packets[0].data.starts_with(b"OpusHead"));
+        assert_eq!(
+            u16::from_le_bytes([packets[0].data[10], packets[0].data[11]]) as u64,
+            pre_skip
+        );
+        assert!(packets[1].data.starts_with(b"OpusTags"));
+        assert_eq!(packets[0].absgp_page(), 0);
+        assert_eq!(packets[1].absgp_page(), 0);
+
+        let audio = &packets[2..];
+        let granules: Vec<u64> = audio.iter().map(|packet| packet.absgp_page()).collect();
+        assert!(granules.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // Enough packets to decode everything recorded past the pre-skip, and no more
+        let decoded = audio.len() as u64 * OPUS_FRAME_SAMPLES as u64;
+        assert!(decoded >= pre_skip + samples);
+        assert!(decoded - OPUS_FRAME_SAMPLES as u64 < pre_skip + samples);
+
+        let last = audio.last().unwrap();
+        assert!(last.last_in_stream());
+        assert_eq!(last.absgp_page(), pre_skip + samples);
+    }
+}
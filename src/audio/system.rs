@@ -0,0 +1,317 @@
+use pipewire as pw;
+use pw::properties::properties;
+use pw::types::ObjectType;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+use tokio::sync::mpsc;
+
+use super::{
+    param_pod, s16_format_param, AudioGenerator, PwCmd, NUM_CHANNELS, SAMPLE_RATE, SAMPLE_SIZE,
+};
+
+const NODE_NAME: &str = "verdant-system-audio";
+// Captured audio that is further ahead of the track than this is dropped
+const MAX_BUFFERED_MS: usize = 200;
+
+#[derive(Debug)]
+struct Port {
+    node: u32,
+    output: bool,
+    /// `audio.channel`, e.g. FL, FR or MONO
+    channel: String,
+}
+
+/// What we know of the PipeWire graph, enough to link other applications into our stream.
+#[derive(Default)]
+struct Graph {
+    /// Our capture node, once it shows up in the registry
+    capture_node: Option<u32>,
+    /// Playback streams of other applications
+    playback_nodes: HashSet<u32>,
+    ports: HashMap<u32, Port>,
+    /// Links we created, keyed by (output port, input port)
+    links: HashMap<(u32, u32), pw::link::Link>,
+}
+
+impl Graph {
+    /// Link every output port of other applications to the capture port of the same
+    /// channel, mono outputs feed both of our channels.
+    fn update_links(&mut self, core: &pw::core::CoreRc) {
+        let Some(capture_node) = self.capture_node else {
+            return;
+        };
+
+        let mut wanted = Vec::new();
+        for (output_id, output) in &self.ports {
+            if !output.output || !self.playback_nodes.contains(&output.node) {
+                continue;
+            }
+            for (input_id, input) in &self.ports {
+                if input.output || input.node != capture_node {
+                    continue;
+                }
+                if output.channel == input.channel || output.channel == "MONO" {
+                    wanted.push((*output_id, *input_id));
+                }
+            }
+        }
+
+        for (output_port, input_port) in wanted {
+            if self.links.contains_key(&(output_port, input_port)) {
+                continue;
+            }
+
+            let output_node = self.ports[&output_port].node;
+            let link = core.create_object::<pw::link::Link>(
+                "link-factory",
+                &properties! {
+                    *pw::keys::LINK_OUTPUT_NODE => output_node.to_string(),
+                    *pw::keys::LINK_OUTPUT_PORT => output_port.to_string(),
+                    *pw::keys::LINK_INPUT_NODE => capture_node.to_string(),
+                    *pw::keys::LINK_INPUT_PORT => input_port.to_string(),
+                    // The link goes away with us
+                    *pw::keys::OBJECT_LINGER => "false",
+                },
+            );
+            match link {
+                Ok(link) => {
+                    self.links.insert((output_port, input_port), link);
+                }
+                Err(err) => log::error!("failed to link system audio: {:?}", err),
+            }
+        }
+    }
+
+    fn remove(&mut self, id: u32) {
+        if self.capture_node == Some(id) {
+            self.capture_node = None;
+        }
+        self.playback_nodes.remove(&id);
+        self.ports.remove(&id);
+        self.links
+            .retain(|(output, input), _| *output != id && *input != id);
+    }
+}
+
+/// Captures what other applications play, for sharing along with the screen.
+///
+/// The monitor of the output device would also carry the remote participants we play, who
+/// would then hear themselves. Instead our capture stream is left unconnected and every
+/// playback stream of another process is linked into it, which sums to what the monitor
+/// carries without our own output. Applications that start playing later are picked up as
+/// they appear.
+pub struct SystemAudioCapture {
+    pw_tx: pw::channel::Sender<PwCmd>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl SystemAudioCapture {
+    /// Start capturing interleaved S16 PCM at the shared rate and channel count.
+    pub fn new(samples_tx: mpsc::UnboundedSender<Vec<i16>>) -> Self {
+        let (pw_tx, pw_rx) = pw::channel::channel();
+
+        let thread = std::thread::spawn(move || {
+            if let Err(err) = capture_thread(samples_tx, pw_rx) {
+                log::error!("system audio capture failed: {:?}", err);
+            }
+        });
+
+        Self {
+            pw_tx,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for SystemAudioCapture {
+    fn drop(&mut self) {
+        let _ = self.pw_tx.send(PwCmd::Terminate);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn capture_thread(
+    samples_tx: mpsc::UnboundedSender<Vec<i16>>,
+    pw_rx: pw::channel::Receiver<PwCmd>,
+) -> Result<(), pw::Error> {
+    let mainloop = pw::main_loop::MainLoopRc::new(None)?;
+    let context = pw::context::ContextRc::new(&mainloop, None)?;
+    let core = context.connect_rc(None)?;
+    let registry = core.get_registry_rc()?;
+
+    let props = properties! {
+        *pw::keys::MEDIA_TYPE => "Audio",
+        *pw::keys::MEDIA_CATEGORY => "Capture",
+        *pw::keys::NODE_NAME => NODE_NAME,
+        *pw::keys::AUDIO_CHANNELS => NUM_CHANNELS.to_string(),
+        // We link it ourselves
+        *pw::keys::NODE_AUTOCONNECT => "false",
+    };
+
+    let stream = pw::stream::StreamBox::new(&core, NODE_NAME, props)?;
+
+    let _listener = stream
+        .add_local_listener_with_user_data(samples_tx)
+        .process(|stream, samples_tx| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+
+            let datas = buffer.datas_mut();
+            if datas.is_empty() {
+                return;
+            }
+
+            let data = &mut datas[0];
+            let offset = data.chunk().offset() as usize;
+            let size = data.chunk().size() as usize;
+            if let Some(slice) = data.data() {
+                let end = (offset + size).min(slice.len());
+                let samples = slice[offset.min(end)..end]
+                    .chunks_exact(SAMPLE_SIZE)
+                    .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                    .collect::<Vec<i16>>();
+
+                let _ = samples_tx.send(samples);
+            }
+        })
+        .register()?;
+
+    let values = s16_format_param(SAMPLE_RATE, NUM_CHANNELS);
+    let mut params = [param_pod(&values)];
+
+    stream.connect(
+        pw::spa::utils::Direction::Input,
+        None,
+        pw::stream::StreamFlags::MAP_BUFFERS | pw::stream::StreamFlags::RT_PROCESS,
+        &mut params,
+    )?;
+
+    let graph = Rc::new(RefCell::new(Graph::default()));
+    let own_pid = std::process::id().to_string();
+
+    let _registry_listener = registry
+        .add_listener_local()
+        .global({
+            let graph = graph.clone();
+            let core = core.clone();
+            move |obj| {
+                let Some(props) = obj.props else {
+                    return;
+                };
+                let mut graph = graph.borrow_mut();
+
+                match obj.type_ {
+                    ObjectType::Node => {
+                        let own = props.get(*pw::keys::APP_PROCESS_ID) == Some(own_pid.as_str());
+                        let class = props.get(*pw::keys::MEDIA_CLASS).unwrap_or_default();
+                        if own && props.get(*pw::keys::NODE_NAME) == Some(NODE_NAME) {
+                            graph.capture_node = Some(obj.id);
+                        } else if !own && class == "Stream/Output/Audio" {
+                            graph.playback_nodes.insert(obj.id);
+                        } else {
+                            return;
+                        }
+                    }
+                    ObjectType::Port => {
+                        // Monitor ports mirror the inputs of sinks, they never carry playback
+                        if props.get(*pw::keys::PORT_MONITOR) == Some("true") {
+                            return;
+                        }
+                        let (Some(node), Some(direction)) = (
+                            props.get(*pw::keys::NODE_ID).and_then(|id| id.parse().ok()),
+                            props.get(*pw::keys::PORT_DIRECTION),
+                        ) else {
+                            return;
+                        };
+                        graph.ports.insert(
+                            obj.id,
+                            Port {
+                                node,
+                                output: direction == "out",
+                                channel: props
+                                    .get(*pw::keys::AUDIO_CHANNEL)
+                                    .unwrap_or("MONO")
+                                    .to_string(),
+                            },
+                        );
+                    }
+                    _ => return,
+                }
+
+                graph.update_links(&core);
+            }
+        })
+        .global_remove({
+            let graph = graph.clone();
+            move |id| graph.borrow_mut().remove(id)
+        })
+        .register();
+
+    let _receiver = pw_rx.attach(mainloop.loop_(), {
+        let mainloop = mainloop.clone();
+        move |cmd| match cmd {
+            PwCmd::Terminate => mainloop.quit(),
+        }
+    });
+
+    mainloop.run();
+
+    // Unlink before the stream goes away
+    graph.borrow_mut().links.clear();
+    Ok(())
+}
+
+/// Plays back what a [`SystemAudioCapture`] captured, with silence while nothing plays.
+pub struct SystemAudioGenerator {
+    samples_rx: mpsc::UnboundedReceiver<Vec<i16>>,
+    buffer: VecDeque<i16>,
+    _capture: SystemAudioCapture,
+}
+
+impl SystemAudioGenerator {
+    pub fn new() -> Self {
+        let (samples_tx, samples_rx) = mpsc::unbounded_channel();
+        Self {
+            samples_rx,
+            buffer: VecDeque::new(),
+            _capture: SystemAudioCapture::new(samples_tx),
+        }
+    }
+}
+
+impl Default for SystemAudioGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioGenerator for SystemAudioGenerator {
+    fn generate(&mut self, samples: &mut [i16], num_channels: usize) -> bool {
+        while let Ok(captured) = self.samples_rx.try_recv() {
+            self.buffer.extend(captured);
+        }
+
+        // The capture and the track run on different clocks, drop what piles up
+        let max = (SAMPLE_RATE as usize / 1000) * MAX_BUFFERED_MS * NUM_CHANNELS as usize;
+        if self.buffer.len() > max {
+            let excess = self.buffer.len() - max;
+            self.buffer.drain(..excess);
+        }
+
+        for frame in samples.chunks_exact_mut(num_channels) {
+            let left = self.buffer.pop_front().unwrap_or(0);
+            let right = self.buffer.pop_front().unwrap_or(0);
+            if num_channels == 1 {
+                frame[0] = ((left as i32 + right as i32) / 2) as i16;
+            } else {
+                frame[0] = left;
+                frame[1] = right;
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,240 @@
+use livekit::webrtc::audio_frame::AudioFrame;
+use livekit::webrtc::audio_source::RtcAudioSource;
+use livekit::{prelude::*, webrtc::audio_source::native::NativeAudioSource};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use super::AudioProcessing;
+
+// Generators run ahead of real time by up to this much, which keeps them clear of hiccups
+const DEFAULT_QUEUE_SIZE_MS: u32 = 1000;
+
+/// Produces the audio of a [`GeneratedTrack`], 10ms at a time.
+pub trait AudioGenerator: Send + 'static {
+    /// Fill `samples` with interleaved 16-bit PCM for `num_channels` channels.
+    ///
+    /// Returns false once the generator has nothing left to play, which ends the track task.
+    fn generate(&mut self, samples: &mut [i16], num_channels: usize) -> bool;
+}
+
+type EndedCallback = Arc<dyn Fn() + Send + Sync>;
+
+struct TrackHandle {
+    close_tx: oneshot::Sender<()>,
+    track: LocalAudioTrack,
+    task: JoinHandle<()>,
+    /// Set once the generator ran out
+    ended: Arc<AtomicBool>,
+}
+
+/// An audio track whose samples are synthesized or decoded locally instead of captured.
+pub struct GeneratedTrack {
+    rtc_source: NativeAudioSource,
+    sample_rate: u32,
+    num_channels: u32,
+    processing: AudioProcessing,
+    source: TrackSource,
+    queue_size_ms: u32,
+    room: Arc<Room>,
+    on_ended: Option<EndedCallback>,
+    handle: Option<TrackHandle>,
+}
+
+impl GeneratedTrack {
+    pub fn new(
+        room: Arc<Room>,
+        sample_rate: u32,
+        num_channels: u32,
+        processing: AudioProcessing,
+    ) -> Self {
+        Self {
+            rtc_source: NativeAudioSource::new(
+                processing.source_options(),
+                sample_rate,
+                processing.num_channels(num_channels),
+                DEFAULT_QUEUE_SIZE_MS,
+            ),
+            sample_rate,
+            num_channels,
+            processing,
+            source: TrackSource::Microphone,
+            queue_size_ms: DEFAULT_QUEUE_SIZE_MS,
+            room,
+            on_ended: None,
+            handle: None,
+        }
+    }
+
+    /// Publish with another source than [`TrackSource::Microphone`], which keeps the track
+    /// out of microphone muting.
+    pub fn with_source(mut self, source: TrackSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// How far, in multiples of 10ms, the generator may run ahead of real time. Small queues
+    /// keep the time a sample is generated close to the time it is sent.
+    pub fn with_queue_size(mut self, queue_size_ms: u32) -> Self {
+        self.queue_size_ms = queue_size_ms;
+        self.rtc_source = NativeAudioSource::new(
+            self.processing.source_options(),
+            self.sample_rate,
+            self.processing.num_channels(self.num_channels),
+            queue_size_ms,
+        );
+        self
+    }
+
+    /// Called from the track task when the generator runs out. The track stays published
+    /// until [`GeneratedTrack::unpublish_ended`] is called.
+    pub fn on_ended(mut self, on_ended: impl Fn() + Send + Sync + 'static) -> Self {
+        self.on_ended = Some(Arc::new(on_ended));
+        self
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Takes effect on the next publish.
+    pub fn set_num_channels(&mut self, num_channels: u32) {
+        self.num_channels = num_channels;
+    }
+
+    /// Processing changes apply right away, channel count and encoding on the next publish.
+    pub fn set_processing(&mut self, processing: AudioProcessing) {
+        self.processing = processing;
+        self.rtc_source
+            .set_audio_options(processing.source_options());
+    }
+
+    pub fn is_published(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    pub async fn publish(
+        &mut self,
+        name: &str,
+        generator: Box<dyn AudioGenerator>,
+    ) -> Result<(), RoomError> {
+        self.unpublish().await?;
+
+        let num_channels = self.processing.num_channels(self.num_channels);
+        if num_channels != self.rtc_source.num_channels() {
+            self.rtc_source = NativeAudioSource::new(
+                self.processing.source_options(),
+                self.sample_rate,
+                num_channels,
+                self.queue_size_ms,
+            );
+        }
+
+        let (close_tx, close_rx) = oneshot::channel();
+        let ended = Arc::new(AtomicBool::new(false));
+        let track = LocalAudioTrack::create_audio_track(
+            name,
+            RtcAudioSource::Native(self.rtc_source.clone()),
+        );
+
+        let task = tokio::spawn(Self::track_task(
+            close_rx,
+            self.rtc_source.clone(),
+            generator,
+            ended.clone(),
+            self.on_ended.clone(),
+        ));
+
+        self.room
+            .local_participant()
+            .publish_track(
+                LocalTrack::Audio(track.clone()),
+                self.processing.publish_options(self.source),
+            )
+            .await?;
+
+        let handle = TrackHandle {
+            close_tx,
+            track,
+            task,
+            ended,
+        };
+
+        self.handle = Some(handle);
+        Ok(())
+    }
+
+    pub async fn unpublish(&mut self) -> Result<(), RoomError> {
+        if let Some(handle) = self.handle.take() {
+            handle.close_tx.send(()).ok();
+            handle.task.await.ok();
+            self.room
+                .local_participant()
+                .unpublish_track(&handle.track.sid())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Unpublish the track if its generator ran out, a track published since is kept.
+    pub async fn unpublish_ended(&mut self) -> Result<(), RoomError> {
+        let ended = self
+            .handle
+            .as_ref()
+            .is_some_and(|handle| handle.ended.load(Ordering::Acquire));
+        if ended {
+            self.unpublish().await?;
+        }
+        Ok(())
+    }
+
+    async fn track_task(
+        mut close_rx: oneshot::Receiver<()>,
+        rtc_source: NativeAudioSource,
+        mut generator: Box<dyn AudioGenerator>,
+        ended: Arc<AtomicBool>,
+        on_ended: Option<EndedCallback>,
+    ) {
+        let sample_rate = rtc_source.sample_rate();
+        let num_channels = rtc_source.num_channels();
+        let samples_count = (sample_rate / 100) as usize * num_channels as usize;
+        let mut samples_10ms = vec![0; samples_count];
+        loop {
+            if close_rx.try_recv().is_ok() {
+                break;
+            }
+
+            if !generator.generate(&mut samples_10ms, num_channels as usize) {
+                ended.store(true, Ordering::Release);
+                if let Some(on_ended) = &on_ended {
+                    on_ended();
+                }
+                break;
+            }
+
+            // capture_frame waits while the source queue is full, which paces the loop
+            let res = rtc_source
+                .capture_frame(&AudioFrame {
+                    data: samples_10ms.as_slice().into(),
+                    sample_rate,
+                    num_channels,
+                    samples_per_channel: samples_count as u32 / num_channels,
+                })
+                .await;
+            if let Err(err) = res {
+                log::error!("failed to capture generated audio frame: {:?}", err);
+                break;
+            }
+        }
+    }
+}
+
+impl Drop for GeneratedTrack {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.close_tx.send(());
+        }
+    }
+}
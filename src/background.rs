@@ -0,0 +1,552 @@
+//! Background blur and replacement for the camera, on the CPU.
+//!
+//! There is no segmentation model: the person is told apart from the room by comparing each
+//! frame with a picture of the empty room, the background plate. The plate is learned over the
+//! first second after [`BackgroundModel::reset`], which is only called when the user asks for
+//! it after stepping out of the frame, and then follows slow lighting changes. Until it is
+//! learned a head and shoulders silhouette in the middle of the frame stands in for the person.
+//!
+//! Everything but the final blend runs at a quarter of the resolution, which keeps a 720p frame
+//! within a few milliseconds.
+use crate::video_processing::{planes, planes_mut, FrameProcessor};
+use image::imageops::FilterType;
+use livekit::webrtc::native::yuv_helper;
+use livekit::webrtc::video_frame::{I420Buffer, VideoBuffer};
+use parking_lot::{MappedMutexGuard, Mutex, MutexGuard};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+const PIXEL_SIZE: usize = 4;
+// Downscale factor of the luma for segmentation and blur
+const SCALE: usize = 4;
+// One second at 30 fps
+const LEARN_FRAMES: u32 = 30;
+// How fast the plate follows the room, and someone who stays still for minutes
+const BACKGROUND_RATE: f32 = 1.0 / 50.0;
+const FOREGROUND_RATE: f32 = 1.0 / 3000.0;
+// Share of the previous mask kept each frame, hides flicker along the edges
+const MASK_SMOOTHING: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackgroundMode {
+    Blur,
+    Image,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackgroundSettings {
+    pub mode: BackgroundMode,
+    /// From 1 to 10
+    pub blur: u32,
+    /// Replacement image, cropped to the frame. Blurs when it can't be loaded
+    pub image: String,
+    /// From 1 to 100, higher counts smaller differences from the plate as the person
+    pub sensitivity: u32,
+}
+
+impl Default for BackgroundSettings {
+    fn default() -> Self {
+        Self {
+            mode: BackgroundMode::Blur,
+            blur: 5,
+            image: String::new(),
+            sensitivity: 50,
+        }
+    }
+}
+
+impl BackgroundSettings {
+    /// Difference from the plate at which a pixel is as likely the person as the room.
+    fn threshold(&self) -> f32 {
+        8.0 + (100 - self.sensitivity.clamp(1, 100)) as f32 * 0.6
+    }
+}
+
+/// What the room looks like without the person, and where the person was in the last frame.
+///
+/// Kept outside the processors so that editing other steps or opening the camera again doesn't
+/// throw the plate away.
+pub struct BackgroundModel {
+    width: usize,
+    height: usize,
+    /// Y, U and V of the empty room
+    plate: [Vec<f32>; 3],
+    /// Frames learned since [`BackgroundModel::reset`], `None` until the plate is captured
+    learned: Option<u32>,
+    /// From 0 for the room to 1 for the person
+    mask: Vec<f32>,
+    raw: Vec<f32>,
+    scratch: Vec<f32>,
+}
+
+impl BackgroundModel {
+    pub fn new() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            plate: Default::default(),
+            learned: None,
+            mask: Vec::new(),
+            raw: Vec::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Learn the plate from the next frames, which should show the room without the person.
+    pub fn reset(&mut self) {
+        self.learned = Some(0);
+    }
+
+    fn update(&mut self, frame: &[Vec<f32>; 3], width: usize, height: usize, threshold: f32) {
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.plate = frame.clone();
+            self.mask = vec![0.0; width * height];
+            self.raw = vec![0.0; width * height];
+            // A plate of another capture format doesn't match the frame
+            self.learned = None;
+        }
+
+        let plate_ready = match &mut self.learned {
+            Some(learned) if *learned < LEARN_FRAMES => {
+                *learned += 1;
+                let rate = 1.0 / *learned as f32;
+                for (plate, channel) in self.plate.iter_mut().zip(frame) {
+                    for (plate, value) in plate.iter_mut().zip(channel) {
+                        *plate += (value - *plate) * rate;
+                    }
+                }
+                false
+            }
+            Some(_) => true,
+            None => false,
+        };
+
+        if !plate_ready {
+            for (i, raw) in self.raw.iter_mut().enumerate() {
+                let (x, y) = (i % width, i / width);
+                *raw = silhouette(
+                    (x as f32 + 0.5) / width as f32,
+                    (y as f32 + 0.5) / height as f32,
+                );
+            }
+        } else {
+            for (i, raw) in self.raw.iter_mut().enumerate() {
+                // Chroma weighs more, it changes less with shadows and exposure
+                let diff = (frame[0][i] - self.plate[0][i]).abs()
+                    + 2.0 * (frame[1][i] - self.plate[1][i]).abs()
+                    + 2.0 * (frame[2][i] - self.plate[2][i]).abs();
+                let foreground = smoothstep(threshold * 0.5, threshold * 1.5, diff);
+                let rate = BACKGROUND_RATE + (FOREGROUND_RATE - BACKGROUND_RATE) * foreground;
+                for (plate, channel) in self.plate.iter_mut().zip(frame) {
+                    plate[i] += (channel[i] - plate[i]) * rate;
+                }
+                *raw = foreground;
+            }
+        }
+
+        // Fill small holes and drop specks, then soften the edge
+        box_blur(&mut self.raw, width, height, 2, &mut self.scratch);
+        for (mask, raw) in self.mask.iter_mut().zip(&self.raw) {
+            let target = smoothstep(0.3, 0.7, *raw);
+            *mask += (target - *mask) * (1.0 - MASK_SMOOTHING);
+        }
+    }
+}
+
+impl Default for BackgroundModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The replacement image at the frame size, decoded on its own thread.
+///
+/// Kept by the processing chain like [`BackgroundModel`], so editing other steps doesn't load
+/// it again.
+#[derive(Clone, Default)]
+pub struct BackgroundImage {
+    inner: Arc<Mutex<ImageInner>>,
+}
+
+#[derive(Default)]
+struct ImageInner {
+    /// Path and frame size of the image loaded last
+    key: Option<(String, u32, u32)>,
+    /// `None` while it loads or when it can't be loaded
+    image: Option<I420Buffer>,
+}
+
+impl BackgroundImage {
+    /// The image at `path` cropped to `width`×`height`, loading starts on the first call for a
+    /// path and size. Returns `None` until it is loaded.
+    fn get(&self, path: &str, width: u32, height: u32) -> Option<MappedMutexGuard<'_, I420Buffer>> {
+        let mut inner = self.inner.lock();
+        let key = inner
+            .key
+            .as_ref()
+            .map(|(path, width, height)| (path.as_str(), *width, *height));
+        if key != Some((path, width, height)) {
+            inner.key = Some((path.to_string(), width, height));
+            inner.image = None;
+
+            let this = self.clone();
+            let path = path.to_string();
+            std::thread::spawn(move || {
+                let image = load_image(&path, width, height)
+                    .map_err(|err| log::error!("failed to load background image {}: {}", path, err))
+                    .ok();
+                let mut inner = this.inner.lock();
+                // The path or frame size may have changed while it loaded
+                if inner.key == Some((path, width, height)) {
+                    inner.image = image;
+                }
+            });
+        }
+        MutexGuard::try_map(inner, |inner| inner.image.as_mut()).ok()
+    }
+}
+
+/// Blurs or replaces everything [`BackgroundModel`] doesn't take for the person.
+pub struct Background {
+    settings: BackgroundSettings,
+    model: Arc<Mutex<BackgroundModel>>,
+    image: BackgroundImage,
+    /// Y, U and V of the frame at a quarter of the resolution
+    small: [Vec<f32>; 3],
+    blurred: [Vec<f32>; 3],
+    weights: Vec<f32>,
+    scratch: Vec<f32>,
+    /// The blurred frame at full resolution
+    backdrop: Option<I420Buffer>,
+}
+
+impl Background {
+    pub fn new(
+        settings: BackgroundSettings,
+        model: Arc<Mutex<BackgroundModel>>,
+        image: BackgroundImage,
+    ) -> Self {
+        Self {
+            settings,
+            model,
+            image,
+            small: Default::default(),
+            blurred: Default::default(),
+            weights: Vec::new(),
+            scratch: Vec::new(),
+            backdrop: None,
+        }
+    }
+
+    /// Average each `SCALE`×`SCALE` block of luma, and the chroma under it.
+    fn downscale(&mut self, buffer: &I420Buffer, width: usize, height: usize) {
+        for (i, (channel, (data, _, _, stride))) in
+            self.small.iter_mut().zip(planes(buffer)).enumerate()
+        {
+            let scale = if i == 0 { SCALE } else { SCALE / 2 };
+            let area = (scale * scale) as f32;
+            channel.clear();
+            for y in 0..height {
+                for x in 0..width {
+                    let sum = (0..scale)
+                        .flat_map(|dy| &data[(y * scale + dy) * stride + x * scale..][..scale])
+                        .map(|value| *value as u32)
+                        .sum::<u32>();
+                    channel.push(sum as f32 / area);
+                }
+            }
+        }
+    }
+
+    /// Blur the room without the person into the backdrop.
+    ///
+    /// The person is weighted out before blurring so their colours don't bleed around them.
+    fn blur(
+        &mut self,
+        mask: &[f32],
+        small_width: usize,
+        small_height: usize,
+        width: u32,
+        height: u32,
+    ) {
+        let radius = self.settings.blur.clamp(1, 10) as usize;
+        self.weights.clear();
+        self.weights.extend(mask.iter().map(|mask| 1.0 - mask));
+        for (blurred, channel) in self.blurred.iter_mut().zip(&self.small) {
+            blurred.clear();
+            blurred.extend(
+                channel
+                    .iter()
+                    .zip(&self.weights)
+                    .map(|(value, weight)| value * weight),
+            );
+            for _ in 0..2 {
+                box_blur(
+                    blurred,
+                    small_width,
+                    small_height,
+                    radius,
+                    &mut self.scratch,
+                );
+            }
+        }
+        for _ in 0..2 {
+            box_blur(
+                &mut self.weights,
+                small_width,
+                small_height,
+                radius,
+                &mut self.scratch,
+            );
+        }
+        for (blurred, channel) in self.blurred.iter_mut().zip(&self.small) {
+            for ((value, weight), original) in blurred.iter_mut().zip(&self.weights).zip(channel) {
+                // Deep inside the person there is no room to spread, it is covered anyway
+                *value = if *weight > 0.01 {
+                    *value / weight
+                } else {
+                    *original
+                };
+            }
+        }
+
+        let sized =
+            |backdrop: &I420Buffer| backdrop.width() == width && backdrop.height() == height;
+        if !self.backdrop.as_ref().is_some_and(sized) {
+            self.backdrop = Some(I420Buffer::new(width, height));
+        }
+        let backdrop = self.backdrop.as_mut().unwrap();
+        for (blurred, (data, plane_width, plane_height, stride)) in
+            self.blurred.iter().zip(planes_mut(backdrop))
+        {
+            upscale(
+                blurred,
+                small_width,
+                small_height,
+                plane_width,
+                plane_height,
+                |x, y, value| {
+                    data[y * stride + x] = value as u8;
+                },
+            );
+        }
+    }
+}
+
+impl FrameProcessor for Background {
+    fn process(&mut self, mut buffer: I420Buffer) -> I420Buffer {
+        let (width, height) = (buffer.width(), buffer.height());
+        let (small_width, small_height) = (width as usize / SCALE, height as usize / SCALE);
+        if small_width == 0 || small_height == 0 {
+            return buffer;
+        }
+
+        self.downscale(&buffer, small_width, small_height);
+        let model = self.model.clone();
+        let mut model = model.lock();
+        model.update(
+            &self.small,
+            small_width,
+            small_height,
+            self.settings.threshold(),
+        );
+
+        let image = self.image.clone();
+        let image = match self.settings.mode {
+            BackgroundMode::Image => image.get(&self.settings.image, width, height),
+            BackgroundMode::Blur => None,
+        };
+        if image.is_none() {
+            self.blur(&model.mask, small_width, small_height, width, height);
+        }
+
+        let backdrop = image
+            .as_deref()
+            .unwrap_or_else(|| self.backdrop.as_ref().unwrap());
+        for ((data, plane_width, plane_height, stride), (backdrop, _, _, backdrop_stride)) in
+            planes_mut(&mut buffer).into_iter().zip(planes(backdrop))
+        {
+            upscale(
+                &model.mask,
+                small_width,
+                small_height,
+                plane_width,
+                plane_height,
+                |x, y, mask| {
+                    let value = &mut data[y * stride + x];
+                    let room = backdrop[y * backdrop_stride + x] as f32;
+                    *value = (room + (*value as f32 - room) * mask) as u8;
+                },
+            );
+        }
+        buffer
+    }
+}
+
+fn load_image(path: &str, width: u32, height: u32) -> Result<I420Buffer, image::ImageError> {
+    let image = image::open(path)?
+        .resize_to_fill(width, height, FilterType::Triangle)
+        .to_rgba8();
+
+    let mut buffer = I420Buffer::new(width, height);
+    let (stride_y, stride_u, stride_v) = buffer.strides();
+    let (data_y, data_u, data_v) = buffer.data_mut();
+    yuv_helper::abgr_to_i420(
+        image.as_raw(),
+        width * PIXEL_SIZE as u32,
+        data_y,
+        stride_y,
+        data_u,
+        stride_u,
+        data_v,
+        stride_v,
+        width as i32,
+        height as i32,
+    );
+    Ok(buffer)
+}
+
+/// Head and shoulders in the middle of the frame, in coordinates from 0 to 1.
+fn silhouette(x: f32, y: f32) -> f32 {
+    let inside = |cx: f32, cy: f32, rx: f32, ry: f32| {
+        ((x - cx) / rx).powi(2) + ((y - cy) / ry).powi(2) <= 1.0
+    };
+    if inside(0.5, 0.4, 0.15, 0.25) || inside(0.5, 1.05, 0.38, 0.45) {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Box blur of a `width`×`height` plane in place, the edges are extended.
+fn box_blur(data: &mut [f32], width: usize, height: usize, radius: usize, scratch: &mut Vec<f32>) {
+    scratch.resize(data.len(), 0.0);
+    for (row, out) in data
+        .chunks_exact(width)
+        .zip(scratch.chunks_exact_mut(width))
+    {
+        blur_line(width, radius, |x| row[x], |x, value| out[x] = value);
+    }
+    for x in 0..width {
+        blur_line(
+            height,
+            radius,
+            |y| scratch[y * width + x],
+            |y, value| data[y * width + x] = value,
+        );
+    }
+}
+
+/// Running average over `2 * radius + 1` samples of a line of `len`.
+fn blur_line(
+    len: usize,
+    radius: usize,
+    get: impl Fn(usize) -> f32,
+    mut put: impl FnMut(usize, f32),
+) {
+    let at = |i: isize| get(i.clamp(0, len as isize - 1) as usize);
+    let radius = radius as isize;
+    let scale = 1.0 / (2 * radius + 1) as f32;
+    let mut sum = (-radius..=radius).map(at).sum::<f32>();
+    for i in 0..len as isize {
+        put(i as usize, sum * scale);
+        sum += at(i + radius + 1) - at(i - radius);
+    }
+}
+
+/// Bilinear upscale of a `src_width`×`src_height` plane, `put` is called with each pixel of the
+/// `width`×`height` result.
+fn upscale(
+    src: &[f32],
+    src_width: usize,
+    src_height: usize,
+    width: usize,
+    height: usize,
+    mut put: impl FnMut(usize, usize, f32),
+) {
+    let sample = |i: usize, size: usize, src_size: usize| {
+        let pos = ((i as f32 + 0.5) * src_size as f32 / size as f32 - 0.5)
+            .clamp(0.0, (src_size - 1) as f32);
+        let low = pos as usize;
+        (low, (low + 1).min(src_size - 1), pos - low as f32)
+    };
+    let columns = (0..width)
+        .map(|x| sample(x, width, src_width))
+        .collect::<Vec<_>>();
+
+    for y in 0..height {
+        let (y0, y1, fy) = sample(y, height, src_height);
+        let top = &src[y0 * src_width..][..src_width];
+        let bottom = &src[y1 * src_width..][..src_width];
+        for (x, &(x0, x1, fx)) in columns.iter().enumerate() {
+            let upper = top[x0] + (top[x1] - top[x0]) * fx;
+            let lower = bottom[x0] + (bottom[x1] - bottom[x0]) * fx;
+            put(x, y, upper + (lower - upper) * fy);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 32;
+    const HEIGHT: usize = 24;
+    const THRESHOLD: f32 = 20.0;
+
+    fn room() -> [Vec<f32>; 3] {
+        [
+            vec![60.0; WIDTH * HEIGHT],
+            vec![100.0; WIDTH * HEIGHT],
+            vec![150.0; WIDTH * HEIGHT],
+        ]
+    }
+
+    fn mask_at(model: &BackgroundModel, x: f32, y: f32) -> f32 {
+        model.mask[(y * HEIGHT as f32) as usize * WIDTH + (x * WIDTH as f32) as usize]
+    }
+
+    #[test]
+    fn plate_is_not_learned_before_the_background_is_captured() {
+        let mut model = BackgroundModel::new();
+        for _ in 0..LEARN_FRAMES * 2 {
+            model.update(&room(), WIDTH, HEIGHT, THRESHOLD);
+        }
+        assert_eq!(model.learned, None);
+        // The silhouette stands in for the person
+        assert!(mask_at(&model, 0.5, 0.9) > 0.9);
+        assert!(mask_at(&model, 0.05, 0.1) < 0.1);
+    }
+
+    #[test]
+    fn captured_plate_tells_the_person_apart() {
+        let mut model = BackgroundModel::new();
+        model.update(&room(), WIDTH, HEIGHT, THRESHOLD);
+        model.reset();
+        for _ in 0..LEARN_FRAMES + 10 {
+            model.update(&room(), WIDTH, HEIGHT, THRESHOLD);
+        }
+        assert!(model.mask.iter().all(|mask| *mask < 0.01));
+
+        // Someone steps into the left half
+        let mut frame = room();
+        for (i, value) in frame[1].iter_mut().enumerate() {
+            if i % WIDTH < WIDTH / 2 {
+                *value = 140.0;
+            }
+        }
+        for _ in 0..10 {
+            model.update(&frame, WIDTH, HEIGHT, THRESHOLD);
+        }
+        assert!(mask_at(&model, 0.2, 0.5) > 0.9);
+        assert!(mask_at(&model, 0.8, 0.5) < 0.1);
+    }
+}
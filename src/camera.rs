@@ -0,0 +1,525 @@
+//! Camera discovery, the capture format we ask nokhwa for, and the published camera track.
+use crate::background::BackgroundModel;
+use crate::publish_profile::VideoProfile;
+use crate::video_processing::{ChainUpdate, ProcessingChain, ProcessingSettings};
+use livekit::prelude::*;
+use livekit::webrtc::prelude::RtcVideoTrack;
+use livekit::webrtc::video_source::{RtcVideoSource, VideoResolution};
+use livekit::webrtc::{
+    native::yuv_helper,
+    video_frame::{I420Buffer, VideoFrame, VideoRotation},
+    video_source::native::NativeVideoSource,
+};
+use nokhwa::pixel_format::{RgbAFormat, RgbFormat};
+use nokhwa::utils::{
+    ApiBackend, CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType,
+    Resolution,
+};
+use nokhwa::{Camera, NokhwaError};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use thiserror::Error;
+
+const PIXEL_SIZE: usize = 4;
+
+/// A capture mode supported by a camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct VideoFormat {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+}
+
+impl std::fmt::Display for VideoFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{} @ {} fps", self.width, self.height, self.fps)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CameraDevice {
+    pub name: String,
+    /// Where the backend found the camera, the device path on Linux. Unlike the name it
+    /// tells two cameras of the same model apart.
+    pub id: String,
+    pub index: CameraIndex,
+    /// Supported modes sorted from the largest, empty when the camera could not be opened
+    pub formats: Vec<VideoFormat>,
+}
+
+/// Preferred camera and mode, `None` picks the default camera or its fastest mode.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CameraSettings {
+    /// Name of the camera, used to find it again when its id changed
+    pub device: Option<String>,
+    /// [`CameraDevice::id`] of the camera
+    #[serde(default)]
+    pub device_id: Option<String>,
+    pub format: Option<VideoFormat>,
+}
+
+impl CameraSettings {
+    /// Resolve the preferred camera, falling back to the first one while it is unplugged.
+    ///
+    /// This queries the cameras, which blocks.
+    pub fn index(&self) -> CameraIndex {
+        let cameras = nokhwa::query(ApiBackend::Auto).unwrap_or_default();
+        let by_id = |id: &String| cameras.iter().find(|info| info.description() == id);
+        let by_name = |name: &String| cameras.iter().find(|info| &info.human_name() == name);
+        self.device_id
+            .as_ref()
+            .and_then(by_id)
+            .or_else(|| self.device.as_ref().and_then(by_name))
+            .map(|info| info.index().clone())
+            .unwrap_or(CameraIndex::Index(0))
+    }
+
+    /// Whether `camera` is the preferred one, see [`CameraSettings::index`].
+    pub fn is_device(&self, camera: &CameraDevice) -> bool {
+        match (&self.device_id, &self.device) {
+            (Some(id), _) => &camera.id == id,
+            (None, Some(name)) => &camera.name == name,
+            (None, None) => false,
+        }
+    }
+
+    /// The closest mode to the preferred one, cameras without an exact match pick their own.
+    pub fn requested_format(&self) -> RequestedFormat<'static> {
+        let format_type = match self.format {
+            Some(format) => RequestedFormatType::Closest(CameraFormat::new(
+                Resolution::new(format.width, format.height),
+                FrameFormat::MJPEG,
+                format.fps,
+            )),
+            None => RequestedFormatType::AbsoluteHighestFrameRate,
+        };
+        RequestedFormat::new::<RgbFormat>(format_type)
+    }
+}
+
+/// List cameras with their supported modes, this opens every camera so it is slow.
+pub fn enumerate_cameras() -> Vec<CameraDevice> {
+    let cameras = match nokhwa::query(ApiBackend::Auto) {
+        Ok(cameras) => cameras,
+        Err(err) => {
+            log::error!("failed to list cameras: {:?}", err);
+            return Vec::new();
+        }
+    };
+
+    cameras
+        .into_iter()
+        .map(|info| {
+            let formats = Camera::new(
+                info.index().clone(),
+                RequestedFormat::new::<RgbFormat>(RequestedFormatType::None),
+            )
+            .and_then(|mut camera| camera.compatible_camera_formats())
+            .map(|formats| {
+                let mut formats = formats
+                    .into_iter()
+                    .map(|format| VideoFormat {
+                        width: format.width(),
+                        height: format.height(),
+                        fps: format.frame_rate(),
+                    })
+                    .collect::<Vec<_>>();
+                // Cameras list the same mode once per pixel format
+                formats.sort_by(|a, b| b.cmp(a));
+                formats.dedup();
+                formats
+            })
+            .unwrap_or_else(|err| {
+                log::error!(
+                    "failed to query formats of {}: {:?}",
+                    info.human_name(),
+                    err
+                );
+                Vec::new()
+            });
+
+            CameraDevice {
+                name: info.human_name(),
+                id: info.description().to_string(),
+                index: info.index().clone(),
+                formats,
+            }
+        })
+        .collect()
+}
+
+/// Cameras found by the last scan, scans run on a background thread.
+///
+/// Scanning opens every camera, so nothing is scanned until the list is first asked for.
+#[derive(Clone, Default)]
+pub struct CameraList {
+    cameras: Arc<Mutex<Vec<CameraDevice>>>,
+    scanning: Arc<Mutex<bool>>,
+    scanned: Arc<AtomicBool>,
+}
+
+impl CameraList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cameras found so far, the first call starts a scan.
+    pub fn cameras(&self) -> Vec<CameraDevice> {
+        if !self.scanned.load(Ordering::Relaxed) {
+            self.refresh();
+        }
+        self.cameras.lock().clone()
+    }
+
+    pub fn is_scanning(&self) -> bool {
+        *self.scanning.lock()
+    }
+
+    pub fn refresh(&self) {
+        {
+            let mut scanning = self.scanning.lock();
+            if *scanning {
+                return;
+            }
+            *scanning = true;
+        }
+        self.scanned.store(true, Ordering::Relaxed);
+
+        let list = self.clone();
+        std::thread::spawn(move || {
+            let cameras = enumerate_cameras();
+            *list.cameras.lock() = cameras;
+            *list.scanning.lock() = false;
+        });
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CameraError {
+    #[error("failed to open camera: {0}")]
+    Capture(String),
+    #[error("failed to publish camera: {0}")]
+    Room(#[from] RoomError),
+}
+
+/// Reads a camera on its own thread, runs each frame through the processing chain and
+/// hands it to the published source.
+struct CameraCapture {
+    stop: Arc<AtomicBool>,
+    updates: mpsc::Sender<ChainUpdate>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl CameraCapture {
+    /// Open the camera, errors are returned before the thread starts producing frames. Later
+    /// errors end the thread and are passed to `on_error`.
+    ///
+    /// Returns the source frames are sent to, sized like the captured frames.
+    fn new(
+        index: CameraIndex,
+        format: RequestedFormat<'static>,
+        chain: ProcessingChain,
+        on_error: impl FnOnce(NokhwaError) + Send + 'static,
+    ) -> Result<(Self, NativeVideoSource), CameraError> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let (updates, updates_rx) = mpsc::channel();
+
+        let thread = std::thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let res = capture_thread(index, format, chain, updates_rx, stop.clone(), ready_tx);
+                if let Err(err) = res {
+                    log::error!("camera capture failed: {}", err);
+                    // Errors while stopping on request don't concern anyone
+                    if !stop.load(Ordering::Relaxed) {
+                        on_error(err);
+                    }
+                }
+            }
+        });
+
+        match ready_rx.recv() {
+            Ok(Ok(rtc_source)) => Ok((
+                Self {
+                    stop,
+                    updates,
+                    thread: Some(thread),
+                },
+                rtc_source,
+            )),
+            Ok(Err(err)) => {
+                let _ = thread.join();
+                Err(CameraError::Capture(err.to_string()))
+            }
+            Err(_) => Err(CameraError::Capture(
+                "camera capture thread exited during setup".to_string(),
+            )),
+        }
+    }
+
+    /// Applied by the capture thread before the next frame.
+    fn update(&self, update: ChainUpdate) {
+        let _ = self.updates.send(update);
+    }
+}
+
+impl Drop for CameraCapture {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn capture_thread(
+    index: CameraIndex,
+    format: RequestedFormat<'static>,
+    mut chain: ProcessingChain,
+    updates: mpsc::Receiver<ChainUpdate>,
+    stop: Arc<AtomicBool>,
+    ready_tx: mpsc::Sender<Result<NativeVideoSource, NokhwaError>>,
+) -> Result<(), NokhwaError> {
+    let opened = Camera::new(index, format).and_then(|mut camera| {
+        camera.open_stream()?;
+        Ok(camera)
+    });
+    let mut camera = match opened {
+        Ok(camera) => camera,
+        Err(err) => {
+            let _ = ready_tx.send(Err(err));
+            return Ok(());
+        }
+    };
+
+    let resolution = camera.resolution();
+    let rtc_source = NativeVideoSource::new(VideoResolution {
+        width: resolution.width(),
+        height: resolution.height(),
+    });
+    let _ = ready_tx.send(Ok(rtc_source.clone()));
+
+    let mut rgba = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        for update in updates.try_iter() {
+            chain.apply(update);
+        }
+
+        let frame = camera.frame()?;
+        let (width, height) = (frame.resolution().width(), frame.resolution().height());
+        rgba.resize(width as usize * height as usize * PIXEL_SIZE, 0);
+        if let Err(err) = frame.decode_image_to_buffer::<RgbAFormat>(&mut rgba) {
+            log::warn!("failed to decode camera frame: {}", err);
+            continue;
+        }
+
+        let mut buffer = I420Buffer::new(width, height);
+        let (stride_y, stride_u, stride_v) = buffer.strides();
+        let (data_y, data_u, data_v) = buffer.data_mut();
+        yuv_helper::abgr_to_i420(
+            &rgba,
+            width * PIXEL_SIZE as u32,
+            data_y,
+            stride_y,
+            data_u,
+            stride_u,
+            data_v,
+            stride_v,
+            width as i32,
+            height as i32,
+        );
+
+        let buffer = chain.process(buffer);
+        rtc_source.capture_frame(&VideoFrame {
+            rotation: VideoRotation::VideoRotation0,
+            buffer,
+            timestamp_us: 0,
+        });
+    }
+
+    camera.stop_stream()
+}
+
+/// The camera shown before joining a room, captured and processed like the published track.
+pub struct CameraPreview {
+    track: LocalVideoTrack,
+    // Dropping it stops the camera
+    _capture: CameraCapture,
+}
+
+impl CameraPreview {
+    /// Open the camera, blocks until it is streaming.
+    pub fn new(
+        settings: &CameraSettings,
+        processing: ProcessingSettings,
+    ) -> Result<Self, CameraError> {
+        let chain = ProcessingChain::new(processing);
+        // Only previewed, the error is logged by the capture thread
+        let (capture, rtc_source) =
+            CameraCapture::new(settings.index(), settings.requested_format(), chain, |_| {})?;
+        let track = LocalVideoTrack::create_video_track(
+            "camera_preview",
+            RtcVideoSource::Native(rtc_source),
+        );
+        Ok(Self {
+            track,
+            _capture: capture,
+        })
+    }
+
+    /// For a [`crate::video_renderer::VideoRenderer`], the track is never published.
+    pub fn rtc_track(&self) -> RtcVideoTrack {
+        self.track.rtc_track()
+    }
+}
+
+type FailedCallback = Arc<dyn Fn(String) + Send + Sync>;
+
+struct TrackHandle {
+    track: LocalVideoTrack,
+    // Dropping it stops the camera
+    capture: CameraCapture,
+    /// Set once the capture stopped on its own
+    failed: Arc<AtomicBool>,
+}
+
+/// The local camera, published and unpublished on demand.
+pub struct CameraTrack {
+    room: Arc<Room>,
+    on_failed: Option<FailedCallback>,
+    /// The capture thread builds its chain from these on each publish
+    processing: ProcessingSettings,
+    /// Shared with each chain, the captured background stays when the camera is reopened
+    background: Arc<Mutex<BackgroundModel>>,
+    handle: Option<TrackHandle>,
+}
+
+impl CameraTrack {
+    pub fn new(room: Arc<Room>, processing: ProcessingSettings) -> Self {
+        Self {
+            room,
+            processing,
+            background: Arc::new(Mutex::new(BackgroundModel::new())),
+            on_failed: None,
+            handle: None,
+        }
+    }
+
+    /// Called with the error when capturing stops on its own, for example when the camera is
+    /// unplugged. The track stays published until it is unpublished.
+    pub fn on_failed(mut self, on_failed: impl Fn(String) + Send + Sync + 'static) -> Self {
+        self.on_failed = Some(Arc::new(on_failed));
+        self
+    }
+
+    pub fn is_published(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    /// Whether the current camera stopped capturing, a camera published since has not.
+    pub fn has_failed(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_some_and(|handle| handle.failed.load(Ordering::Acquire))
+    }
+
+    /// Applies to a live camera from the next frame on.
+    pub fn set_processing(&mut self, processing: ProcessingSettings) {
+        if let Some(handle) = &self.handle {
+            handle
+                .capture
+                .update(ChainUpdate::Settings(processing.clone()));
+        }
+        self.processing = processing;
+    }
+
+    /// Learn the room behind the person again, see [`crate::background`].
+    pub fn capture_background(&self) {
+        if let Some(handle) = &self.handle {
+            handle.capture.update(ChainUpdate::CaptureBackground);
+        }
+    }
+
+    /// Open the camera and publish it, a published camera is replaced so new settings apply.
+    pub async fn publish(
+        &mut self,
+        settings: &CameraSettings,
+        profile: VideoProfile,
+    ) -> Result<(), CameraError> {
+        self.unpublish().await?;
+
+        let local = self.room.local_participant();
+        let name = match local.name() {
+            name if name.is_empty() => local.identity().to_string(),
+            name => name,
+        };
+        let mut chain =
+            ProcessingChain::with_background(self.processing.clone(), self.background.clone());
+        chain.set_name(name);
+
+        let failed = Arc::new(AtomicBool::new(false));
+        let on_error = {
+            let failed = failed.clone();
+            let on_failed = self.on_failed.clone();
+            move |err: NokhwaError| {
+                failed.store(true, Ordering::Release);
+                if let Some(on_failed) = on_failed {
+                    on_failed(err.to_string());
+                }
+            }
+        };
+
+        let settings = settings.clone();
+        let (capture, rtc_source) = tokio::task::spawn_blocking(move || {
+            CameraCapture::new(
+                settings.index(),
+                settings.requested_format(),
+                chain,
+                on_error,
+            )
+        })
+        .await
+        .map_err(|err| CameraError::Capture(err.to_string()))??;
+
+        let resolution = rtc_source.video_resolution();
+        let track =
+            LocalVideoTrack::create_video_track("camera", RtcVideoSource::Native(rtc_source));
+
+        local
+            .publish_track(
+                LocalTrack::Video(track.clone()),
+                profile.publish_options(TrackSource::Camera, resolution),
+            )
+            .await?;
+
+        self.handle = Some(TrackHandle {
+            track,
+            capture,
+            failed,
+        });
+        Ok(())
+    }
+    pub async fn unpublish(&mut self) -> Result<(), CameraError> {
+        if let Some(handle) = self.handle.take() {
+            self.room
+                .local_participant()
+                .unpublish_track(&handle.track.sid())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Muting keeps the camera open so unmuting is instant.
+    pub fn set_muted(&self, muted: bool) {
+        if let Some(handle) = &self.handle {
+            if muted {
+                handle.track.mute();
+            } else {
+                handle.track.unmute();
+            }
+        }
+    }
+}
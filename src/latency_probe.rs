@@ -0,0 +1,349 @@
+use crate::audio::{AudioGenerator, AudioProcessing, GeneratedTrack, SAMPLE_RATE};
+use futures::StreamExt;
+use livekit::prelude::*;
+use livekit::webrtc::audio_stream::native::NativeAudioStream;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const PROBE_TRACK_NAME: &str = "latency_probe";
+// One burst per second, latencies above that can't be told apart from the next burst
+const BURST_INTERVAL_MS: u64 = 1000;
+const BURST_LENGTH_MS: u64 = 50;
+const BURST_FREQ: f64 = 1000.0;
+const BURST_AMPLITUDE: f64 = 0.5;
+// Onsets are detected on the first sample above this fraction of full scale
+const DETECT_THRESHOLD: f64 = 0.1;
+// Quiet needed before a new onset, which ignores the rest of the burst and codec ringing
+const DETECT_HOLDOFF_MS: u64 = 200;
+// The source queue is kept at a single frame so bursts leave shortly after they are generated
+const PROBE_QUEUE_SIZE_MS: u32 = 10;
+const MAX_MEASUREMENTS: usize = 1000;
+
+#[derive(Default)]
+struct StatsInner {
+    /// when each burst was generated, waiting to be detected
+    sent: VecDeque<Instant>,
+    latencies: VecDeque<Duration>,
+    lost: u32,
+}
+
+/// One-way latencies measured by a [`LatencyProbe`], shared with the UI.
+#[derive(Clone, Default)]
+pub struct LatencyStats {
+    inner: Arc<Mutex<StatsInner>>,
+}
+
+impl LatencyStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&self) {
+        *self.inner.lock() = StatsInner::default();
+    }
+
+    fn sent(&self, at: Instant) {
+        self.inner.lock().sent.push_back(at);
+    }
+
+    /// Match an onset with the latest burst sent before it, earlier bursts were lost.
+    fn detected(&self, at: Instant) {
+        let mut inner = self.inner.lock();
+        let mut matched = None;
+        while inner.sent.front().is_some_and(|sent| *sent <= at) {
+            if matched.is_some() {
+                inner.lost += 1;
+            }
+            matched = inner.sent.pop_front();
+        }
+
+        if let Some(sent) = matched {
+            inner.latencies.push_back(at - sent);
+            if inner.latencies.len() > MAX_MEASUREMENTS {
+                inner.latencies.pop_front();
+            }
+        }
+    }
+
+    pub fn report(&self) -> LatencyReport {
+        let inner = self.inner.lock();
+        let mut latencies: Vec<Duration> = inner.latencies.iter().copied().collect();
+        latencies.sort();
+        LatencyReport {
+            latencies,
+            lost: inner.lost,
+        }
+    }
+}
+
+/// Snapshot of [`LatencyStats`], latencies are sorted.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyReport {
+    pub latencies: Vec<Duration>,
+    pub lost: u32,
+}
+
+impl LatencyReport {
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let index = ((self.latencies.len() - 1) as f64 * p / 100.0).round() as usize;
+        Some(self.latencies[index])
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        Some(self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32)
+    }
+
+    /// Measurement counts in buckets of `bucket` width, starting at zero.
+    pub fn histogram(&self, bucket: Duration) -> Vec<u32> {
+        let mut buckets = Vec::new();
+        for latency in &self.latencies {
+            let index = (latency.as_micros() / bucket.as_micros().max(1)) as usize;
+            if index >= buckets.len() {
+                buckets.resize(index + 1, 0);
+            }
+            buckets[index] += 1;
+        }
+        buckets
+    }
+}
+
+/// Tone bursts at a fixed interval, the time each burst is generated is recorded.
+struct BurstGenerator {
+    stats: LatencyStats,
+    position: u64,
+    phase: f64,
+}
+
+impl AudioGenerator for BurstGenerator {
+    fn generate(&mut self, samples: &mut [i16], num_channels: usize) -> bool {
+        let interval = SAMPLE_RATE as u64 * BURST_INTERVAL_MS / 1000;
+        let length = SAMPLE_RATE as u64 * BURST_LENGTH_MS / 1000;
+
+        for frame in samples.chunks_exact_mut(num_channels) {
+            let offset = self.position % interval;
+            if offset == 0 {
+                self.stats.sent(Instant::now());
+                self.phase = 0.0;
+            }
+
+            let val = if offset < length {
+                let val = BURST_AMPLITUDE * f64::sin(self.phase);
+                self.phase += 2.0 * PI * BURST_FREQ / SAMPLE_RATE as f64;
+                val
+            } else {
+                0.0
+            };
+            frame.fill((val * 32768.0) as i16);
+            self.position += 1;
+        }
+        true
+    }
+}
+
+/// Measures mouth-to-ear latency through the SFU.
+///
+/// Tone bursts are published from the room we are connected to, and a second participant
+/// connected from this process with its own token subscribes to them. Both ends share a
+/// clock, so the delay between generating a burst and detecting it after decoding is the
+/// one-way latency, including the jitter buffer and up to two frames of source queueing.
+pub struct LatencyProbe {
+    track: GeneratedTrack,
+    receiver: Arc<Room>,
+    task: JoinHandle<()>,
+    /// Spawned by the receiver task, aborting that task doesn't end it
+    detector: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl LatencyProbe {
+    pub async fn start(
+        room: Arc<Room>,
+        url: &str,
+        token: &str,
+        options: RoomOptions,
+        stats: LatencyStats,
+    ) -> RoomResult<Self> {
+        stats.reset();
+
+        // Processing would treat the bursts as noise, and mono keeps detection simple
+        let processing = AudioProcessing {
+            echo_cancellation: false,
+            noise_suppression: false,
+            auto_gain_control: false,
+            music_mode: false,
+        };
+        let mut track = GeneratedTrack::new(room.clone(), SAMPLE_RATE, 1, processing)
+            .with_source(TrackSource::Unknown)
+            .with_queue_size(PROBE_QUEUE_SIZE_MS);
+
+        let (receiver, events) = Room::connect(url, token, options).await?;
+        let receiver = Arc::new(receiver);
+        let detector = Arc::new(Mutex::new(None));
+        let task = tokio::spawn(receiver_task(
+            receiver.clone(),
+            room.local_participant().identity(),
+            events,
+            stats.clone(),
+            detector.clone(),
+        ));
+
+        let generator = BurstGenerator {
+            stats,
+            position: 0,
+            phase: 0.0,
+        };
+        if let Err(err) = track.publish(PROBE_TRACK_NAME, Box::new(generator)).await {
+            task.abort();
+            abort_detector(&detector);
+            let _ = receiver.close().await;
+            return Err(err);
+        }
+
+        Ok(Self {
+            track,
+            receiver,
+            task,
+            detector,
+        })
+    }
+
+    pub async fn stop(mut self) -> RoomResult<()> {
+        self.task.abort();
+        abort_detector(&self.detector);
+        self.track.unpublish().await?;
+        self.receiver.close().await
+    }
+}
+
+async fn receiver_task(
+    receiver: Arc<Room>,
+    sender: ParticipantIdentity,
+    mut events: mpsc::UnboundedReceiver<RoomEvent>,
+    stats: LatencyStats,
+    detector: Arc<Mutex<Option<JoinHandle<()>>>>,
+) {
+    let is_probe = |identity: &ParticipantIdentity, publication: &RemoteTrackPublication| {
+        *identity == sender && publication.name() == PROBE_TRACK_NAME
+    };
+
+    // The probe may have been published before we joined
+    for (identity, participant) in receiver.remote_participants() {
+        for (_, publication) in participant.track_publications() {
+            if is_probe(&identity, &publication) {
+                publication.set_subscribed(true);
+            }
+        }
+    }
+
+    while let Some(event) = events.recv().await {
+        match event {
+            RoomEvent::TrackPublished {
+                publication,
+                participant,
+            } if is_probe(&participant.identity(), &publication) => {
+                publication.set_subscribed(true);
+            }
+            RoomEvent::TrackSubscribed {
+                track: RemoteTrack::Audio(track),
+                publication,
+                participant,
+            } if is_probe(&participant.identity(), &publication) => {
+                let task = tokio::spawn(detect_task(track, stats.clone()));
+                if let Some(previous) = detector.lock().replace(task) {
+                    previous.abort();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    abort_detector(&detector);
+}
+
+fn abort_detector(detector: &Mutex<Option<JoinHandle<()>>>) {
+    if let Some(detector) = detector.lock().take() {
+        detector.abort();
+    }
+}
+
+async fn detect_task(track: RemoteAudioTrack, stats: LatencyStats) {
+    let mut stream = NativeAudioStream::new(track.rtc_track(), SAMPLE_RATE as i32, 1);
+    let threshold = (DETECT_THRESHOLD * 32768.0) as i16;
+    let holdoff = SAMPLE_RATE as u64 * DETECT_HOLDOFF_MS / 1000;
+    let mut quiet = 0u64;
+
+    while let Some(frame) = stream.next().await {
+        let received = Instant::now();
+        let len = frame.data.len();
+
+        for (i, sample) in frame.data.iter().enumerate() {
+            if sample.saturating_abs() < threshold {
+                quiet += 1;
+                continue;
+            }
+
+            if quiet >= holdoff {
+                // The frame was delivered once its last sample was decoded
+                let behind = (len - i) as u64 * 1_000_000 / SAMPLE_RATE as u64;
+                stats.detected(received - Duration::from_micros(behind));
+            }
+            quiet = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(latencies_ms: impl IntoIterator<Item = u64>) -> LatencyReport {
+        LatencyReport {
+            latencies: latencies_ms
+                .into_iter()
+                .map(Duration::from_millis)
+                .collect(),
+            lost: 0,
+        }
+    }
+
+    #[test]
+    fn percentiles_of_an_empty_report() {
+        let report = report([]);
+        assert_eq!(report.percentile(50.0), None);
+        assert_eq!(report.mean(), None);
+    }
+
+    #[test]
+    fn percentiles_pick_the_nearest_measurement() {
+        let report = report((1..=101).map(|i| i * 10));
+        assert_eq!(report.percentile(0.0), Some(Duration::from_millis(10)));
+        assert_eq!(report.percentile(50.0), Some(Duration::from_millis(510)));
+        assert_eq!(report.percentile(95.0), Some(Duration::from_millis(960)));
+        assert_eq!(report.percentile(99.0), Some(Duration::from_millis(1000)));
+        assert_eq!(report.percentile(100.0), Some(Duration::from_millis(1010)));
+        assert_eq!(report.mean(), Some(Duration::from_millis(510)));
+    }
+
+    #[test]
+    fn percentiles_of_a_single_measurement() {
+        let report = report([42]);
+        assert_eq!(report.percentile(0.0), Some(Duration::from_millis(42)));
+        assert_eq!(report.percentile(99.0), Some(Duration::from_millis(42)));
+    }
+
+    #[test]
+    fn histogram_counts_each_bucket() {
+        let report = report([3, 9, 10, 35]);
+        assert_eq!(report.histogram(Duration::from_millis(10)), [2, 1, 0, 1]);
+    }
+}
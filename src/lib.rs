@@ -1,10 +1,18 @@
 pub mod app;
 pub mod audio;
-pub mod logo_track;
+pub mod background;
+pub mod camera;
+pub mod latency_probe;
 pub mod pages;
+pub mod publish_profile;
+pub mod screen_share;
 //pub mod services;
 pub mod service;
-pub mod sine_track;
+pub mod signal_track;
+pub mod test_pattern;
+pub mod text;
+pub mod video_file;
 pub mod video_grid;
+pub mod video_processing;
 pub mod video_renderer;
 //pub mod video;
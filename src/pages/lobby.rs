@@ -0,0 +1,262 @@
+use super::rooms::{vu_meter, GridRoom};
+use crate::audio::{CaptureStream, LevelMeter, NUM_CHANNELS, SAMPLE_RATE};
+use crate::camera::{CameraError, CameraPreview, CameraSettings};
+use crate::video_processing::ProcessingSettings;
+use crate::video_renderer::VideoRenderer;
+use egui::CornerRadius;
+use std::sync::mpsc;
+use tokio::runtime::Handle;
+use verdant::livekit::TokenResponse;
+
+/// What to publish once the room is joined.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JoinOptions {
+    pub camera: bool,
+    pub microphone: bool,
+}
+
+/// Something the lobby needs the app to do.
+pub enum LobbyAction {
+    /// Play the test tone through the room's output device
+    TestTone,
+    Join {
+        ident: String,
+        response: TokenResponse,
+        options: JoinOptions,
+    },
+}
+
+enum CameraState {
+    Off,
+    /// Opened on another thread, nokhwa blocks until the camera streams
+    Opening(mpsc::Receiver<Result<CameraPreview, CameraError>>),
+    Live {
+        renderer: VideoRenderer,
+        // Dropping it closes the camera
+        _preview: CameraPreview,
+    },
+    Failed(String),
+}
+
+struct MicrophoneMeter {
+    meter: LevelMeter,
+    // Dropping it stops the capture and the task feeding the meter
+    _capture: CaptureStream,
+}
+
+/// Shown between login and the room to check the camera, microphone and speakers, and to
+/// pick which of them to join with.
+pub struct LobbyPage {
+    /// The token to join with, received on login
+    pending: Option<(String, TokenResponse)>,
+    options: JoinOptions,
+    camera_settings: CameraSettings,
+    processing: ProcessingSettings,
+    audio_input: Option<String>,
+    camera: CameraState,
+    microphone: Option<MicrophoneMeter>,
+    render_state: egui_wgpu::RenderState,
+    async_runtime_handle: Handle,
+}
+
+impl LobbyPage {
+    pub fn new(runtime: &tokio::runtime::Runtime, cc: &eframe::CreationContext<'_>) -> Self {
+        Self {
+            pending: None,
+            options: JoinOptions::default(),
+            camera_settings: CameraSettings::default(),
+            processing: ProcessingSettings::default(),
+            audio_input: None,
+            camera: CameraState::Off,
+            microphone: None,
+            render_state: cc.wgpu_render_state.clone().unwrap(),
+            async_runtime_handle: runtime.handle().clone(),
+        }
+    }
+
+    /// Wait in the lobby with a new token, devices are taken from the room's settings.
+    pub fn open(&mut self, ident: String, response: TokenResponse, room: &GridRoom) {
+        let settings = room.settings();
+        self.options = JoinOptions {
+            camera: settings.auto_publish,
+            microphone: settings.auto_publish,
+        };
+        self.camera_settings = settings.camera.clone();
+        self.processing = room.state().camera_processing().clone();
+        self.audio_input = room.audio_input();
+        self.pending = Some((ident, response));
+
+        // Start over with the devices picked in the settings
+        self.camera = CameraState::Off;
+        self.microphone = None;
+        self.apply_options();
+    }
+
+    pub fn update(
+        &mut self,
+        ctx: &egui::Context,
+        _frame: &mut eframe::Frame,
+    ) -> Option<LobbyAction> {
+        self.poll_camera();
+
+        let mut action = None;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                let room = self
+                    .pending
+                    .as_ref()
+                    .map(|(_, response)| response.room.clone())
+                    .unwrap_or_default();
+                ui.heading(format!("Join {}", room));
+                ui.add_space(10.0);
+
+                self.camera_preview(ui);
+                ui.add_space(10.0);
+
+                ui.allocate_ui(egui::vec2(480.0, 0.0), |ui| {
+                    ui.vertical(|ui| self.devices(ui, &mut action));
+                });
+            });
+        });
+
+        if self.microphone.is_some() || matches!(self.camera, CameraState::Opening(_)) {
+            ctx.request_repaint();
+        }
+
+        if matches!(action, Some(LobbyAction::Join { .. })) {
+            // Release the devices for the room
+            self.camera = CameraState::Off;
+            self.microphone = None;
+        }
+        action
+    }
+
+    fn devices(&mut self, ui: &mut egui::Ui, action: &mut Option<LobbyAction>) {
+        let mut changed = false;
+        changed |= ui
+            .checkbox(&mut self.options.camera, "Join with camera")
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.options.microphone, "Join with microphone")
+            .changed();
+        if changed {
+            self.apply_options();
+        }
+
+        if let Some(microphone) = &self.microphone {
+            vu_meter(ui, microphone.meter.level());
+        }
+
+        ui.add_space(10.0);
+        ui.horizontal(|ui| {
+            if ui.button("Test speakers").clicked() {
+                *action = Some(LobbyAction::TestTone);
+            }
+            if ui
+                .add_enabled(self.pending.is_some(), egui::Button::new("Join"))
+                .clicked()
+            {
+                let (ident, response) = self.pending.take().unwrap();
+                *action = Some(LobbyAction::Join {
+                    ident,
+                    response,
+                    options: self.options,
+                });
+            }
+        });
+    }
+
+    fn camera_preview(&self, ui: &mut egui::Ui) {
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(480.0, 270.0), egui::Sense::hover());
+        ui.painter()
+            .rect_filled(rect, CornerRadius::default(), egui::Color32::BLACK);
+
+        let message = match &self.camera {
+            CameraState::Off => "Camera off".to_string(),
+            CameraState::Opening(_) => "Opening camera…".to_string(),
+            CameraState::Failed(err) => err.clone(),
+            CameraState::Live { renderer, .. } => {
+                let (width, height) = renderer.resolution();
+                if width > 0 && height > 0 {
+                    // Letterbox the frame into the preview
+                    let size = egui::vec2(width as f32, height as f32);
+                    let scale = (rect.width() / size.x).min(rect.height() / size.y);
+                    renderer.paint(
+                        ui.painter(),
+                        egui::Rect::from_center_size(rect.center(), size * scale),
+                    );
+                }
+                return;
+            }
+        };
+        ui.painter().text(
+            rect.center(),
+            egui::Align2::CENTER_CENTER,
+            message,
+            egui::FontId::default(),
+            egui::Color32::WHITE,
+        );
+    }
+
+    /// Start or stop the previews to match the join options.
+    fn apply_options(&mut self) {
+        if !self.options.camera {
+            self.camera = CameraState::Off;
+        } else if matches!(self.camera, CameraState::Off | CameraState::Failed(_)) {
+            let (tx, rx) = mpsc::channel();
+            let settings = self.camera_settings.clone();
+            let processing = self.processing.clone();
+            std::thread::spawn(move || {
+                // Dropped right away when the camera was turned off in the meantime
+                let _ = tx.send(CameraPreview::new(&settings, processing));
+            });
+            self.camera = CameraState::Opening(rx);
+        }
+
+        if !self.options.microphone {
+            self.microphone = None;
+        } else if self.microphone.is_none() {
+            let meter = LevelMeter::new();
+            let (samples_tx, mut samples_rx) = tokio::sync::mpsc::unbounded_channel();
+            let capture = CaptureStream::new(
+                self.audio_input.clone(),
+                SAMPLE_RATE,
+                NUM_CHANNELS,
+                samples_tx,
+            );
+            self.async_runtime_handle.spawn({
+                let meter = meter.clone();
+                async move {
+                    while let Some(samples) = samples_rx.recv().await {
+                        meter.update(&samples);
+                    }
+                }
+            });
+            self.microphone = Some(MicrophoneMeter {
+                meter,
+                _capture: capture,
+            });
+        }
+    }
+
+    fn poll_camera(&mut self) {
+        let CameraState::Opening(rx) = &self.camera else {
+            return;
+        };
+        self.camera = match rx.try_recv() {
+            Ok(Ok(preview)) => CameraState::Live {
+                renderer: VideoRenderer::new(
+                    &self.async_runtime_handle,
+                    self.render_state.clone(),
+                    preview.rtc_track(),
+                ),
+                _preview: preview,
+            },
+            Ok(Err(err)) => CameraState::Failed(err.to_string()),
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                CameraState::Failed("camera thread exited".to_string())
+            }
+        };
+    }
+}
@@ -1,9 +1,9 @@
+use keycast::discovery::Discovery;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use tokio::sync::mpsc::UnboundedSender;
 use verdant::auth::LoginResult;
 use verdant::services::{VerdantCmd, VerdantService, VerdantUiCmd};
-use std::collections::HashMap;
-use keycast::discovery::Discovery;
 
 #[derive(Default, Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct LoginState {
@@ -49,7 +49,6 @@ impl LoginPage {
     }
 
     pub fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        
         egui::SidePanel::left("left_panel")
             .resizable(true)
             .width_range(200.0..=360.0)
@@ -71,7 +70,9 @@ impl LoginPage {
         let label = format!(
             "{} \n {}",
             discovery.name,
-            discovery.default_url().unwrap_or("unknown host".to_string())
+            discovery
+                .default_url()
+                .unwrap_or("unknown host".to_string())
         );
 
         // Make the entire row a button
@@ -150,10 +151,10 @@ impl LoginPage {
                             self.state.url = url;
                         }
                     }
-                }else{
+                } else {
                     println!("no default url could be found for: {:?}", discovery);
                 }
-            },
+            }
             VerdantUiCmd::LoginResult(result) => {
                 self.state.login_message = Some(match result {
                     LoginResult::Unauthorized => "incorrect username or password".to_string(),
@@ -1,15 +1,19 @@
 use serde::{Deserialize, Serialize};
 pub mod accounts;
+pub mod lobby;
 pub mod login;
 pub mod rooms;
 pub mod settings;
 
 pub use accounts::*;
+pub use lobby::*;
 pub use login::*;
 pub use rooms::*;
 pub use settings::*;
 
+use crate::audio::DeviceMonitor;
 use crate::service::{LkService, UiCmd};
+use std::sync::Arc;
 use tokio::sync::mpsc::UnboundedSender;
 use verdant::services::{VerdantCmd, VerdantService, VerdantUiCmd};
 
@@ -22,6 +26,8 @@ pub enum AppState {
 #[derive(Debug, Clone, Serialize, Deserialize, Copy, PartialEq, Eq)]
 pub enum ActivePage {
     Login,
+    /// Checking devices before joining
+    Lobby,
     Room,
     Account,
     Settings,
@@ -30,6 +36,7 @@ pub enum ActivePage {
 
 pub struct AppPage {
     login: LoginPage,
+    lobby: LobbyPage,
     room: GridRoom,
     account: AccountPage,
     settings: SettingsPage,
@@ -43,13 +50,16 @@ impl AppPage {
         cc: &eframe::CreationContext<'_>,
         service: VerdantService,
     ) -> Self {
+        let devices = Arc::new(DeviceMonitor::new());
         let login = LoginPage::new(runtime, cc, service.tx().clone(), "http://localhost");
-        let settings = SettingsPage::new(runtime, cc);
-        let room = GridRoom::new(runtime, cc, GeneralSettings::default());
+        let lobby = LobbyPage::new(runtime, cc);
+        let room = GridRoom::new(runtime, cc, GeneralSettings::default(), devices.clone());
+        let settings = SettingsPage::new(runtime, cc, room.settings().clone(), devices);
         let account = AccountPage::new(runtime, cc);
         let active = ActivePage::Login;
         Self {
             login,
+            lobby,
             settings,
             account,
             room,
@@ -63,9 +73,36 @@ impl AppPage {
             self.event(event);
         }
 
+        if matches!(self.active, ActivePage::Room | ActivePage::Settings) {
+            egui::TopBottomPanel::top("nav_panel").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.active, ActivePage::Room, "Room");
+                    ui.selectable_value(&mut self.active, ActivePage::Settings, "Settings");
+                });
+            });
+        }
+
         match self.active {
             ActivePage::Room => self.room.update(ctx, frame),
             ActivePage::Login => self.login.update(ctx, frame),
+            ActivePage::Lobby => match self.lobby.update(ctx, frame) {
+                Some(LobbyAction::TestTone) => self.room.play_test_tone(),
+                Some(LobbyAction::Join {
+                    ident,
+                    response,
+                    options,
+                }) => {
+                    self.room.initialize(&ident, &response, options);
+                    self.active = ActivePage::Room;
+                }
+                None => {}
+            },
+            ActivePage::Settings => {
+                self.settings.update(ctx, frame);
+                if self.settings.take_changed() {
+                    self.room.set_settings(self.settings.state().clone());
+                }
+            }
             _ => unimplemented!(),
         }
     }
@@ -77,22 +114,21 @@ impl AppPage {
     pub fn event(&mut self, cmd: VerdantUiCmd) {
         match cmd {
             VerdantUiCmd::LkToken(record) => {
-                let url = record.server;
-                let response = record.response;
-                self.room.initialize(&url, &response);
-                self.active = ActivePage::Room;
+                self.lobby.open(record.server, record.response, &self.room);
+                self.active = ActivePage::Lobby;
             }
-            _ => match self.active {
-                ActivePage::Login => self.login.event(cmd),
-                _ => unimplemented!(),
-            },
+            // Discoveries and login results can arrive after the login page was left, it keeps
+            // the servers for when it is shown again
+            _ => self.login.event(cmd),
         }
     }
 
     pub fn state(&self) -> AppState {
         match self.active {
-            ActivePage::Room => AppState::Room(self.room.state().clone()),
+            ActivePage::Room | ActivePage::Lobby => AppState::Room(self.room.state().clone()),
             ActivePage::Login => AppState::Login(self.login.state().clone()),
+            // settings are applied to the room as they are edited
+            ActivePage::Settings => AppState::Room(self.room.state().clone()),
             _ => unimplemented!(),
         }
     }
@@ -1,7 +1,19 @@
 use crate::{
-    pages::settings::*,
-    service::{AsyncCmd, LkService, UiCmd},
+    audio::{
+        test_tone, AudioLevel, AudioOutput, AudioProcessing, DeviceEvent, DeviceKind,
+        DeviceMonitor, MixerSettings, Recorder, RecordingFormat, SoundCue,
+    },
+    background::{BackgroundMode, BackgroundSettings},
+    latency_probe::LatencyReport,
+    pages::{lobby::JoinOptions, settings::*},
+    publish_profile::PublishProfiles,
+    screen_share::{x11, ScreenShareSource, X11Source},
+    service::{AsyncCmd, LkService, UiCmd, CHAT_TOPIC, RECORDING_TOPIC},
+    signal_track::{Signal, SignalParameters},
+    test_pattern::{TestPattern, TestPatternSettings},
+    video_file::VideoFileSettings,
     video_grid::VideoGrid,
+    video_processing::{ProcessingSettings, Processor, Rotation},
     video_renderer::VideoRenderer,
 };
 use uuid::Uuid;
@@ -9,16 +21,49 @@ use uuid::Uuid;
 use egui::{CornerRadius, Stroke};
 use keycast::discovery::Discovery;
 use livekit::{e2ee::EncryptionType, prelude::*, track::VideoQuality, SimulateScenario};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::runtime::Handle;
+use tokio::sync::broadcast;
 use verdant::livekit::TokenResponse;
 
+// Camera processing edits are sent once they stop for this long, a dragged slider rebuilds the
+// chain once instead of every frame
+const PROCESSING_DEBOUNCE: Duration = Duration::from_millis(150);
+
 #[derive(serde::Serialize, Debug, Clone, serde::Deserialize)]
 pub struct RoomState {
     room: Option<RoomSettings>,
     settings: GeneralSettings,
     connecting: bool,
     connection_failure: Option<String>,
+    #[serde(default)]
+    mixer: MixerSettings,
+    /// last file picked in "Publish audio file…"
+    #[serde(default)]
+    audio_file: String,
+    #[serde(default)]
+    audio_file_loop: bool,
+    #[serde(default)]
+    signal: SignalParameters,
+    #[serde(default)]
+    test_pattern: TestPatternSettings,
+    #[serde(default)]
+    camera_processing: ProcessingSettings,
+    #[serde(default)]
+    recording_path: String,
+    #[serde(default)]
+    recording_format: RecordingFormat,
+    #[serde(default)]
+    record_microphone: bool,
+    /// PipeWire node id last shared directly
+    #[serde(default)]
+    screen_share_node: String,
+    #[serde(default)]
+    screen_share_audio: bool,
+    #[serde(default)]
+    video_file: VideoFileSettings,
 }
 
 impl RoomState {
@@ -31,11 +76,28 @@ impl RoomState {
             settings,
             connecting: false,
             connection_failure: None,
+            mixer: MixerSettings::default(),
+            audio_file: String::new(),
+            audio_file_loop: false,
+            signal: SignalParameters::default(),
+            test_pattern: TestPatternSettings::default(),
+            camera_processing: ProcessingSettings::default(),
+            recording_path: String::new(),
+            recording_format: RecordingFormat::default(),
+            record_microphone: false,
+            screen_share_node: String::new(),
+            screen_share_audio: false,
+            video_file: VideoFileSettings::default(),
         }
     }
 
     pub fn url(&self) -> &str {
-        &self.room().as_ref().expect("room should be initialized by now").server.url
+        &self
+            .room()
+            .as_ref()
+            .expect("room should be initialized by now")
+            .server
+            .url
     }
 
     pub fn room(&self) -> &Option<RoomSettings> {
@@ -43,7 +105,10 @@ impl RoomState {
     }
 
     pub fn key(&self) -> &str {
-        self.room().as_ref().map(|v| v.key()).expect("key should be initialized by this point")
+        self.room()
+            .as_ref()
+            .map(|v| v.key())
+            .expect("key should be initialized by this point")
     }
 
     /*pub fn key_mut(&mut self) -> &mut String {
@@ -55,25 +120,92 @@ impl RoomState {
     }*/
 
     pub fn token(&self) -> &str {
-        self.room().as_ref().map(|v| &v.server.token).expect("room should be initialized by this point")
+        self.room()
+            .as_ref()
+            .map(|v| &v.server.token)
+            .expect("room should be initialized by this point")
     }
 
     pub fn settings(&self) -> &GeneralSettings {
         &self.settings
     }
+
+    pub fn camera_processing(&self) -> &ProcessingSettings {
+        &self.camera_processing
+    }
+
+    /// Processing for published audio, per server once a room is known.
+    pub fn audio_processing(&self) -> AudioProcessing {
+        match self.room() {
+            Some(room) => room.audio_processing(),
+            None => self.settings.audio_processing(),
+        }
+    }
+
+    /// Encoding of published video, per server once a room is known.
+    pub fn publish_profiles(&self) -> PublishProfiles {
+        match self.room() {
+            Some(room) => room.publish_profiles(),
+            None => self.settings.publish_profiles(),
+        }
+    }
 }
 
 pub struct GridRoom {
     state: RoomState,
     video_renderers: HashMap<(ParticipantIdentity, TrackSid), VideoRenderer>,
+    audio_output: AudioOutput,
+    devices: Arc<DeviceMonitor>,
+    device_events: broadcast::Receiver<DeviceEvent>,
+    /// mute toggled by the user, push to talk overrides it while enabled
+    mic_muted: bool,
+    /// last mute state sent to the service, `None` forces it to be sent again
+    sent_mute: Option<bool>,
+    show_signal_generator: bool,
+    show_test_pattern: bool,
+    show_camera_processing: bool,
+    /// Last camera processing edit not yet sent to the service
+    camera_processing_edited: Option<Instant>,
+    show_server_settings: bool,
+    /// renderer of our camera track, previewed next to its processing settings
+    camera_preview: Option<(ParticipantIdentity, TrackSid)>,
+    /// input the microphone is captured from, also used when recording it
+    audio_input: Option<String>,
+    recorder: Option<Recorder>,
+    recording_error: Option<String>,
+    /// participants that told us they are recording
+    remote_recording: HashSet<ParticipantIdentity>,
+    show_latency_probe: bool,
+    /// token for the second participant the latency probe joins as
+    probe_token: String,
+    probe_running: bool,
+    /// Waiting for the service to answer StartLatencyProbe
+    probe_starting: bool,
+    probe_error: Option<String>,
+    camera_muted: bool,
+    /// last failure to publish a local source, shown until dismissed
+    publish_error: Option<String>,
+    /// X11 monitors and windows offered in "Share screen", listed when first needed
+    x11_sources: Option<Result<Vec<X11Source>, String>>,
+    /// Listing X11 sources takes round trips to the X server, so it runs on a blocking task
+    x11_scan: Option<std::sync::mpsc::Receiver<Result<Vec<X11Source>, String>>>,
+    /// pans last sent to the mixer, recomputed from the video grid every frame
+    pans: HashMap<ParticipantIdentity, f32>,
     render_state: egui_wgpu::RenderState,
     service: LkService,
     async_runtime_handle: Handle,
 }
 
 impl GridRoom {
-    pub fn initialize(&mut self, ident: &str, response: &TokenResponse) {
-        let room = RoomSettings::from_response(&self.state.settings, ident, response);
+    /// Connect to the room, publishing what was picked in the lobby.
+    pub fn initialize(&mut self, ident: &str, response: &TokenResponse, options: JoinOptions) {
+        let mut room = RoomSettings::from_response(&self.state.settings, ident, response);
+        // Back on the same server, keep what was configured for it
+        if let Some(previous) = self.state.room() {
+            if previous.server.url == room.server.url {
+                room.server.settings = previous.server.settings.clone();
+            }
+        }
         self.state.set_room(room);
         println!("url: {}", self.state.url());
         let cmd = AsyncCmd::RoomConnect {
@@ -82,6 +214,13 @@ impl GridRoom {
             key: self.state.key().to_string(),
             enable_e2ee: self.state.settings().enable_e2ee(),
             auto_subscribe: self.state.settings().auto_subscribe(),
+            audio_processing: self.state.audio_processing(),
+            signal: self.state.signal.clone(),
+            test_pattern: self.state.test_pattern.clone(),
+            publish_profiles: self.state.publish_profiles(),
+            camera_processing: self.state.camera_processing.clone(),
+            publish_camera: options.camera.then(|| self.state.settings.camera.clone()),
+            publish_microphone: options.microphone,
         };
         self.service.send(cmd);
     }
@@ -92,6 +231,7 @@ impl GridRoom {
         runtime: &tokio::runtime::Runtime,
         cc: &eframe::CreationContext<'_>,
         settings: GeneralSettings,
+        devices: Arc<DeviceMonitor>,
     ) -> Self {
         let state = RoomState::new(settings);
         let state = cc
@@ -99,12 +239,290 @@ impl GridRoom {
             .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
             .unwrap_or_else(|| state);
 
-        Self {
+        let audio_output = AudioOutput::new(runtime.handle());
+        audio_output.mixer().set_settings(state.mixer.clone());
+        audio_output
+            .mixer()
+            .set_spatial(state.settings.spatial_audio);
+
+        let mut room = Self {
             service: LkService::new(runtime.handle()),
             state,
             video_renderers: HashMap::new(),
+            audio_output,
+            device_events: devices.subscribe(),
+            devices,
+            mic_muted: false,
+            sent_mute: None,
+            show_signal_generator: false,
+            show_test_pattern: false,
+            show_camera_processing: false,
+            camera_processing_edited: None,
+            show_server_settings: false,
+            camera_preview: None,
+            audio_input: None,
+            recorder: None,
+            recording_error: None,
+            remote_recording: HashSet::new(),
+            show_latency_probe: false,
+            probe_token: String::new(),
+            probe_running: false,
+            probe_starting: false,
+            probe_error: None,
+            camera_muted: false,
+            publish_error: None,
+            x11_sources: None,
+            x11_scan: None,
+            pans: HashMap::new(),
             render_state: cc.wgpu_render_state.clone().unwrap(),
             async_runtime_handle: runtime.handle().clone(),
+        };
+        room.apply_audio_devices();
+        room
+    }
+
+    pub fn set_settings(&mut self, settings: GeneralSettings) {
+        self.audio_output
+            .mixer()
+            .set_spatial(settings.spatial_audio);
+        self.state.settings = settings;
+        self.apply_audio_devices();
+
+        let _ = self.service.send(AsyncCmd::SetAudioProcessing {
+            processing: self.state.audio_processing(),
+        });
+        let _ = self.service.send(AsyncCmd::SetPublishProfiles {
+            profiles: self.state.publish_profiles(),
+        });
+    }
+
+    /// Route capture and playback to the preferred devices, falling back to the PipeWire
+    /// defaults while they are unplugged. The published microphone track is kept as is.
+    fn apply_audio_devices(&mut self) {
+        let available = |kind: DeviceKind, name: &Option<String>| {
+            name.clone().filter(|name| {
+                self.devices
+                    .devices(kind)
+                    .iter()
+                    .any(|device| &device.name == name)
+            })
+        };
+
+        let output = available(DeviceKind::Sink, &self.state.settings.audio_output);
+        let input = available(DeviceKind::Source, &self.state.settings.audio_input);
+
+        self.audio_output.set_target(output);
+        self.audio_input = input.clone();
+        let _ = self.service.send(AsyncCmd::SetAudioInput { target: input });
+    }
+
+    /// Audio processing and video publishing of the current server, the settings page only
+    /// holds what new servers start with.
+    fn server_settings(&mut self, ui: &mut egui::Ui) {
+        let Some(room) = self.state.room.as_mut() else {
+            ui.label("Not joined to a server yet.");
+            return;
+        };
+        let settings = &mut room.server.settings;
+        ui.heading(&room.server.name);
+
+        ui.separator();
+        ui.monospace("Audio processing");
+        ui.add_space(4.0);
+        if audio_processing_editor(ui, &mut settings.audio_processing) {
+            let _ = self.service.send(AsyncCmd::SetAudioProcessing {
+                processing: settings.audio_processing,
+            });
+        }
+
+        ui.separator();
+        ui.monospace("Video publishing");
+        ui.add_space(4.0);
+        if publish_profiles_editor(ui, &mut settings.publish_profiles) {
+            let _ = self.service.send(AsyncCmd::SetPublishProfiles {
+                profiles: settings.publish_profiles,
+            });
+        }
+        ui.label("Changes apply the next time a track is published.");
+    }
+
+    fn latency_probe(&mut self, ui: &mut egui::Ui) {
+        ui.label("Publishes tone bursts and receives them as a second participant.");
+        ui.horizontal(|ui| {
+            ui.label("Token: ");
+            ui.add_enabled(
+                !self.probe_running && !self.probe_starting,
+                egui::TextEdit::singleline(&mut self.probe_token),
+            )
+            .on_hover_text("Token for another identity in the same room");
+        });
+
+        let connected = self.service.room().is_some();
+        ui.horizontal(|ui| {
+            if self.probe_running {
+                if ui.button("Stop").clicked() {
+                    let _ = self.service.send(AsyncCmd::StopLatencyProbe);
+                    self.probe_running = false;
+                }
+            } else if ui
+                .add_enabled(
+                    connected && !self.probe_starting && !self.probe_token.trim().is_empty(),
+                    egui::Button::new("Start"),
+                )
+                .clicked()
+            {
+                let _ = self.service.send(AsyncCmd::StartLatencyProbe {
+                    url: self.state.url().to_string(),
+                    token: self.probe_token.trim().to_string(),
+                    enable_e2ee: self.state.settings().enable_e2ee(),
+                    key: self.state.key().to_string(),
+                });
+                self.probe_starting = true;
+                self.probe_error = None;
+            }
+            if self.probe_starting {
+                ui.spinner();
+            }
+        });
+        if let Some(error) = &self.probe_error {
+            ui.colored_label(egui::Color32::RED, format!("Failed to start: {}", error));
+        }
+        ui.separator();
+
+        let report = self.service.latency_report();
+        let ms = |latency: Option<std::time::Duration>| match latency {
+            Some(latency) => format!("{:.1} ms", latency.as_secs_f64() * 1000.0),
+            None => "-".to_string(),
+        };
+
+        egui::Grid::new("latency_stats").show(ui, |ui| {
+            ui.label("Bursts received");
+            ui.label(report.latencies.len().to_string());
+            ui.end_row();
+            ui.label("Bursts lost");
+            ui.label(report.lost.to_string());
+            ui.end_row();
+            ui.label("Min");
+            ui.label(ms(report.percentile(0.0)));
+            ui.end_row();
+            ui.label("Median");
+            ui.label(ms(report.percentile(50.0)));
+            ui.end_row();
+            ui.label("Mean");
+            ui.label(ms(report.mean()));
+            ui.end_row();
+            ui.label("95th percentile");
+            ui.label(ms(report.percentile(95.0)));
+            ui.end_row();
+            ui.label("Max");
+            ui.label(ms(report.percentile(100.0)));
+            ui.end_row();
+        });
+
+        latency_histogram(ui, &report);
+    }
+
+    /// Pick the root window, a monitor or a window of the X server to share.
+    fn x11_picker(&mut self, ui: &mut egui::Ui) {
+        let scanning = self.x11_scan.is_some();
+        let refresh = ui
+            .add_enabled(!scanning, egui::Button::new("Refresh"))
+            .clicked();
+        if refresh || (self.x11_sources.is_none() && !scanning) {
+            let (tx, rx) = std::sync::mpsc::channel();
+            self.async_runtime_handle.spawn_blocking(move || {
+                let _ = tx.send(x11::list_sources().map_err(|err| err.to_string()));
+            });
+            self.x11_scan = Some(rx);
+        }
+
+        if let Some(rx) = &self.x11_scan {
+            match rx.try_recv() {
+                Ok(sources) => {
+                    self.x11_sources = Some(sources);
+                    self.x11_scan = None;
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => {
+                    ui.spinner();
+                }
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    self.x11_sources = Some(Err("listing X11 sources panicked".to_string()));
+                    self.x11_scan = None;
+                }
+            }
+        }
+
+        match &self.x11_sources {
+            Some(Ok(sources)) => {
+                egui::ScrollArea::vertical()
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for source in sources {
+                            if ui.button(&source.name).clicked() {
+                                let _ = self.service.send(AsyncCmd::PublishScreenShare {
+                                    source: ScreenShareSource::X11(source.target),
+                                    system_audio: self.state.screen_share_audio,
+                                });
+                                ui.close_menu();
+                            }
+                        }
+                    });
+            }
+            Some(Err(err)) => {
+                ui.colored_label(egui::Color32::RED, err);
+            }
+            None => {}
+        }
+    }
+
+    fn play_cue(&self, cue: SoundCue) {
+        if self.state.settings.sound_cues.enabled(cue) {
+            self.audio_output.mixer().play(&cue.render());
+        }
+    }
+
+    fn start_recording(&mut self) {
+        let mut path = std::path::PathBuf::from(self.state.recording_path.trim());
+        if path.extension().is_none() {
+            path.set_extension(self.state.recording_format.extension());
+        }
+
+        let microphone = self
+            .state
+            .record_microphone
+            .then(|| self.audio_input.clone());
+
+        match Recorder::start(
+            path,
+            self.state.recording_format,
+            self.audio_output.mixer(),
+            microphone,
+        ) {
+            Ok(recorder) => {
+                self.recorder = Some(recorder);
+                self.recording_error = None;
+                let _ = self
+                    .service
+                    .send(AsyncCmd::SendRecordingNotice { recording: true });
+            }
+            Err(err) => {
+                log::error!("failed to start recording: {:?}", err);
+                self.recording_error = Some(err.to_string());
+            }
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        let Some(recorder) = self.recorder.take() else {
+            return;
+        };
+
+        let _ = self
+            .service
+            .send(AsyncCmd::SendRecordingNotice { recording: false });
+        if let Err(err) = recorder.stop() {
+            log::error!("failed to finish recording: {:?}", err);
+            self.recording_error = Some(err.to_string());
         }
     }
 
@@ -116,6 +534,14 @@ impl GridRoom {
                     self.state.connection_failure = Some(err.to_string());
                 }
             }
+            UiCmd::PublishFailed { source, error } => {
+                self.publish_error = Some(format!("{:?}: {}", source, error));
+            }
+            UiCmd::LatencyProbeStarted { result } => {
+                self.probe_starting = false;
+                self.probe_running = result.is_ok();
+                self.probe_error = result.err();
+            }
             UiCmd::RoomEvent { event } => {
                 log::info!("{:?}", event);
                 match event {
@@ -133,8 +559,11 @@ impl GridRoom {
                             );
                             self.video_renderers
                                 .insert((participant.identity(), track.sid()), video_renderer);
-                        } else if let RemoteTrack::Audio(_) = track {
-                            // TODO(theomonnom): Once we support media devices, we can play audio tracks here
+                        } else if let RemoteTrack::Audio(ref audio_track) = track {
+                            self.audio_output.add_track(
+                                (participant.identity(), track.sid()),
+                                audio_track.rtc_track(),
+                            );
                         }
                     }
                     RoomEvent::TrackUnsubscribed {
@@ -142,15 +571,23 @@ impl GridRoom {
                         publication: _,
                         participant,
                     } => {
-                        self.video_renderers
-                            .remove(&(participant.identity(), track.sid()));
+                        let key = (participant.identity(), track.sid());
+                        self.video_renderers.remove(&key);
+                        self.audio_output.remove_track(&key);
                     }
                     RoomEvent::LocalTrackPublished {
                         track,
                         publication: _,
                         participant,
                     } => {
+                        if let LocalTrack::Audio(_) = track {
+                            // Make sure new audio tracks follow the current mute state
+                            self.sent_mute = None;
+                        }
                         if let LocalTrack::Video(ref video_track) = track {
+                            if video_track.name() == "camera" {
+                                self.camera_preview = Some((participant.identity(), track.sid()));
+                            }
                             // Also create a new VideoRenderer for local tracks
                             let video_renderer = VideoRenderer::new(
                                 &self.async_runtime_handle,
@@ -165,11 +602,48 @@ impl GridRoom {
                         publication,
                         participant,
                     } => {
-                        self.video_renderers
-                            .remove(&(participant.identity(), publication.sid()));
+                        let key = (participant.identity(), publication.sid());
+                        if self.camera_preview.as_ref() == Some(&key) {
+                            self.camera_preview = None;
+                        }
+                        self.video_renderers.remove(&key);
+                    }
+                    RoomEvent::DataReceived {
+                        payload,
+                        topic,
+                        kind: _,
+                        participant: Some(participant),
+                    } if topic.as_deref() == Some(RECORDING_TOPIC) => {
+                        if payload.as_slice() == b"started" {
+                            self.remote_recording.insert(participant.identity());
+                            self.play_cue(SoundCue::RecordingStarted);
+                        } else {
+                            self.remote_recording.remove(&participant.identity());
+                        }
+                    }
+                    RoomEvent::DataReceived { topic, .. }
+                        if topic.as_deref() == Some(CHAT_TOPIC) =>
+                    {
+                        self.play_cue(SoundCue::ChatMessage);
+                    }
+                    RoomEvent::ParticipantConnected(_) => {
+                        self.play_cue(SoundCue::Join);
+                    }
+                    RoomEvent::ParticipantDisconnected(participant) => {
+                        self.remote_recording.remove(&participant.identity());
+                        self.play_cue(SoundCue::Leave);
                     }
-                    RoomEvent::Disconnected { reason: _ } => {
+                    RoomEvent::Disconnected { reason } => {
+                        if reason != DisconnectReason::ClientInitiated {
+                            self.play_cue(SoundCue::ConnectionLost);
+                        }
+                        self.stop_recording();
+                        self.remote_recording.clear();
+                        self.probe_running = false;
+                        self.probe_starting = false;
                         self.video_renderers.clear();
+                        self.camera_preview = None;
+                        self.audio_output.clear();
                     }
                     _ => {}
                 }
@@ -182,6 +656,19 @@ impl GridRoom {
             self.event(event);
         }
 
+        let mut devices_changed = false;
+        loop {
+            match self.device_events.try_recv() {
+                Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) => devices_changed = true,
+                Err(_) => break,
+            }
+        }
+        if devices_changed {
+            self.apply_audio_devices();
+        }
+
+        self.handle_mute_keys(ctx);
+
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             self.top_panel(ui);
         });
@@ -211,9 +698,112 @@ impl GridRoom {
             self.central_panel(ui);
         });
 
+        let mut show_signal_generator = self.show_signal_generator;
+        egui::Window::new("Signal generator")
+            .open(&mut show_signal_generator)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if signal_generator(ui, &mut self.state.signal) {
+                    let _ = self.service.send(AsyncCmd::SetSignalParameters {
+                        params: self.state.signal.clone(),
+                    });
+                }
+            });
+        self.show_signal_generator = show_signal_generator;
+
+        let mut show_test_pattern = self.show_test_pattern;
+        egui::Window::new("Test pattern")
+            .open(&mut show_test_pattern)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if test_pattern_settings(ui, &mut self.state.test_pattern) {
+                    let _ = self.service.send(AsyncCmd::SetTestPattern {
+                        settings: self.state.test_pattern.clone(),
+                    });
+                }
+            });
+        self.show_test_pattern = show_test_pattern;
+
+        let mut show_camera_processing = self.show_camera_processing;
+        egui::Window::new("Camera processing")
+            .open(&mut show_camera_processing)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let preview = self
+                    .camera_preview
+                    .as_ref()
+                    .and_then(|key| self.video_renderers.get(key));
+                camera_preview(ui, preview);
+                ui.separator();
+
+                let mut capture_background = false;
+                if camera_processing(
+                    ui,
+                    &mut self.state.camera_processing,
+                    &mut capture_background,
+                ) {
+                    self.camera_processing_edited = Some(Instant::now());
+                }
+                if capture_background {
+                    let _ = self.service.send(AsyncCmd::CaptureCameraBackground);
+                }
+            });
+        self.show_camera_processing = show_camera_processing;
+
+        if let Some(edited) = self.camera_processing_edited {
+            let wait = PROCESSING_DEBOUNCE.saturating_sub(edited.elapsed());
+            if wait.is_zero() {
+                self.camera_processing_edited = None;
+                let _ = self.service.send(AsyncCmd::SetCameraProcessing {
+                    processing: self.state.camera_processing.clone(),
+                });
+            } else {
+                ctx.request_repaint_after(wait);
+            }
+        }
+
+        let mut show_server_settings = self.show_server_settings;
+        egui::Window::new("Server settings")
+            .open(&mut show_server_settings)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.server_settings(ui);
+            });
+        self.show_server_settings = show_server_settings;
+
+        let mut show_latency_probe = self.show_latency_probe;
+        egui::Window::new("Latency probe")
+            .open(&mut show_latency_probe)
+            .show(ctx, |ui| {
+                self.latency_probe(ui);
+            });
+        self.show_latency_probe = show_latency_probe;
+
         ctx.request_repaint();
     }
 
+    fn handle_mute_keys(&mut self, ctx: &egui::Context) {
+        let bindings = &self.state.settings.key_bindings;
+
+        // Don't steal keys while the user is typing
+        if !ctx.wants_keyboard_input() {
+            if ctx.input(|i| i.key_pressed(bindings.toggle_mute)) {
+                self.mic_muted = !self.mic_muted;
+            }
+        }
+
+        let muted = if bindings.push_to_talk_enabled {
+            !(ctx.input(|i| i.key_down(bindings.push_to_talk)) && !ctx.wants_keyboard_input())
+        } else {
+            self.mic_muted
+        };
+
+        if self.sent_mute != Some(muted) {
+            self.sent_mute = Some(muted);
+            let _ = self.service.send(AsyncCmd::SetMicrophoneMuted { muted });
+        }
+    }
+
     fn top_panel(&mut self, ui: &mut egui::Ui) {
         egui::menu::bar(ui, |ui| {
             ui.menu_button("Simulate", |ui| {
@@ -235,15 +825,205 @@ impl GridRoom {
             });
 
             ui.menu_button("Publish", |ui| {
-                if ui.button("Logo").clicked() {
-                    let _ = self.service.send(AsyncCmd::ToggleLogo);
+                if ui.button("Test pattern").clicked() {
+                    let _ = self.service.send(AsyncCmd::ToggleTestPattern);
+                }
+                if ui.button("Test pattern settings…").clicked() {
+                    self.show_test_pattern = true;
+                    ui.close_menu();
+                }
+                if ui.button("Signal generator").clicked() {
+                    let _ = self.service.send(AsyncCmd::ToggleSignal);
                 }
-                if ui.button("SineWave").clicked() {
-                    let _ = self.service.send(AsyncCmd::ToggleSine);
+                if ui.button("Signal settings…").clicked() {
+                    self.show_signal_generator = true;
+                    ui.close_menu();
                 }
-                if ui.button("camera").clicked() {
-                    println!("camera button clicked");
-                    let _ = self.service.send(AsyncCmd::PublishCamera);
+                if ui.button("Microphone").clicked() {
+                    let _ = self.service.send(AsyncCmd::ToggleMicrophone);
+                }
+                ui.menu_button("Publish audio file…", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Path: ");
+                        ui.text_edit_singleline(&mut self.state.audio_file);
+                    });
+                    ui.checkbox(&mut self.state.audio_file_loop, "Loop");
+                    ui.label("WAV or Ogg/Opus");
+                    ui.horizontal(|ui| {
+                        let path = self.state.audio_file.trim();
+                        if ui
+                            .add_enabled(!path.is_empty(), egui::Button::new("Publish"))
+                            .clicked()
+                        {
+                            let _ = self.service.send(AsyncCmd::PublishAudioFile {
+                                path: path.into(),
+                                looping: self.state.audio_file_loop,
+                            });
+                            ui.close_menu();
+                        }
+                        if ui.button("Stop").clicked() {
+                            let _ = self.service.send(AsyncCmd::UnpublishAudioFile);
+                            ui.close_menu();
+                        }
+                    });
+                });
+                ui.menu_button("Publish video file…", |ui| {
+                    let video = &mut self.state.video_file;
+                    ui.horizontal(|ui| {
+                        ui.label("Path: ");
+                        ui.text_edit_singleline(&mut video.path);
+                    });
+                    ui.checkbox(&mut video.looping, "Loop");
+                    ui.horizontal(|ui| {
+                        ui.label("Image sequence fps: ");
+                        ui.add(egui::DragValue::new(&mut video.image_fps).range(1..=120));
+                    });
+                    ui.label("Y4M file or a directory of images");
+                    ui.horizontal(|ui| {
+                        let path = video.path.trim();
+                        if ui
+                            .add_enabled(!path.is_empty(), egui::Button::new("Publish"))
+                            .clicked()
+                        {
+                            let _ = self.service.send(AsyncCmd::PublishVideoFile {
+                                path: path.into(),
+                                looping: video.looping,
+                                image_fps: video.image_fps,
+                            });
+                            ui.close_menu();
+                        }
+                        if ui.button("Stop").clicked() {
+                            let _ = self.service.send(AsyncCmd::UnpublishVideoFile);
+                            ui.close_menu();
+                        }
+                    });
+                });
+                if ui.button("Camera").clicked() {
+                    // A newly published camera starts unmuted
+                    self.camera_muted = false;
+                    let _ = self.service.send(AsyncCmd::ToggleCamera {
+                        camera: self.state.settings.camera.clone(),
+                    });
+                }
+                if ui
+                    .checkbox(&mut self.camera_muted, "Camera muted")
+                    .changed()
+                {
+                    let _ = self.service.send(AsyncCmd::SetCameraMuted {
+                        muted: self.camera_muted,
+                    });
+                }
+                if ui.button("Camera processing…").clicked() {
+                    self.show_camera_processing = true;
+                    ui.close_menu();
+                }
+                if ui
+                    .add_enabled(
+                        self.state.room.is_some(),
+                        egui::Button::new("Server settings…"),
+                    )
+                    .clicked()
+                {
+                    self.show_server_settings = true;
+                    ui.close_menu();
+                }
+                ui.menu_button("Share screen", |ui| {
+                    ui.checkbox(&mut self.state.screen_share_audio, "Include system audio")
+                        .on_hover_text("What other applications play, without the call");
+                    ui.separator();
+                    if ui.button("Pick screen or window…").clicked() {
+                        let _ = self.service.send(AsyncCmd::PublishScreenShare {
+                            source: ScreenShareSource::Portal,
+                            system_audio: self.state.screen_share_audio,
+                        });
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    ui.horizontal(|ui| {
+                        ui.label("PipeWire node: ");
+                        ui.text_edit_singleline(&mut self.state.screen_share_node);
+                    });
+                    let node = self.state.screen_share_node.trim().parse::<u32>().ok();
+                    if ui
+                        .add_enabled(node.is_some(), egui::Button::new("Share node"))
+                        .clicked()
+                    {
+                        if let Some(node) = node {
+                            let _ = self.service.send(AsyncCmd::PublishScreenShare {
+                                source: ScreenShareSource::Node(node),
+                                system_audio: self.state.screen_share_audio,
+                            });
+                        }
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    ui.menu_button("X11", |ui| {
+                        self.x11_picker(ui);
+                    });
+                    ui.separator();
+                    if ui.button("Stop sharing").clicked() {
+                        let _ = self.service.send(AsyncCmd::UnpublishScreenShare);
+                        ui.close_menu();
+                    }
+                });
+            });
+
+            let bindings = &self.state.settings.key_bindings;
+            if bindings.push_to_talk_enabled {
+                // Only the key unmutes, so show what it does instead of a toggle
+                let label = if self.sent_mute == Some(false) {
+                    "Talking".to_string()
+                } else {
+                    format!("Hold {} to talk", bindings.push_to_talk.name())
+                };
+                ui.add_enabled(false, egui::Button::new(label));
+            } else {
+                let mute_label = if self.mic_muted { "Unmute" } else { "Mute" };
+                if ui.button(mute_label).clicked() {
+                    self.mic_muted = !self.mic_muted;
+                }
+            }
+
+            ui.menu_button("Record", |ui| {
+                let recording = self.recorder.is_some();
+                ui.add_enabled_ui(!recording, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Path: ");
+                        ui.text_edit_singleline(&mut self.state.recording_path);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.radio_value(
+                            &mut self.state.recording_format,
+                            RecordingFormat::Wav,
+                            "WAV",
+                        );
+                        ui.radio_value(
+                            &mut self.state.recording_format,
+                            RecordingFormat::OggOpus,
+                            "Ogg/Opus",
+                        );
+                    });
+                    ui.checkbox(&mut self.state.record_microphone, "Include my microphone");
+                });
+
+                if recording {
+                    if ui.button("Stop recording").clicked() {
+                        self.stop_recording();
+                        ui.close_menu();
+                    }
+                } else if ui
+                    .add_enabled(
+                        !self.state.recording_path.trim().is_empty(),
+                        egui::Button::new("Start recording"),
+                    )
+                    .clicked()
+                {
+                    self.start_recording();
+                    ui.close_menu();
+                }
+
+                if let Some(err) = &self.recording_error {
+                    ui.colored_label(egui::Color32::RED, err);
                 }
             });
 
@@ -251,7 +1031,39 @@ impl GridRoom {
                 if ui.button("Log stats").clicked() {
                     let _ = self.service.send(AsyncCmd::LogStats);
                 }
+                if ui.button("Latency probe…").clicked() {
+                    self.show_latency_probe = true;
+                    ui.close_menu();
+                }
             });
+
+            if let Some(error) = &self.publish_error {
+                ui.colored_label(egui::Color32::RED, error);
+                if ui.small_button("✖").clicked() {
+                    self.publish_error = None;
+                }
+            }
+
+            if let Some(recorder) = &self.recorder {
+                let secs = recorder.elapsed().as_secs();
+                ui.colored_label(
+                    egui::Color32::RED,
+                    format!("● REC {:02}:{:02}", secs / 60, secs % 60),
+                )
+                .on_hover_text(recorder.path().display().to_string());
+            }
+            if !self.remote_recording.is_empty() {
+                let mut names = self
+                    .remote_recording
+                    .iter()
+                    .map(|identity| identity.as_str())
+                    .collect::<Vec<_>>();
+                names.sort();
+                ui.colored_label(
+                    egui::Color32::RED,
+                    format!("● Recorded by {}", names.join(", ")),
+                );
+            }
         });
     }
 
@@ -259,6 +1071,16 @@ impl GridRoom {
         self.state.settings()
     }
 
+    /// Capture device the microphone uses, `None` while following the default source.
+    pub fn audio_input(&self) -> Option<String> {
+        self.audio_input.clone()
+    }
+
+    /// Play the test tone on the output device the room plays through.
+    pub fn play_test_tone(&self) {
+        self.audio_output.mixer().play(&test_tone());
+    }
+
     /// Connection form and room info
     fn left_panel(&mut self, ui: &mut egui::Ui) {
         let room = self.service.room();
@@ -295,12 +1117,20 @@ impl GridRoom {
                 if ui.button("Connect").clicked() {
                     self.state.connecting = true;
                     self.state.connection_failure = None;
+                    let auto_publish = self.settings().auto_publish();
                     let _ = self.service.send(AsyncCmd::RoomConnect {
                         url: self.state.url().to_string(),
                         token: self.state.token().to_string(),
                         auto_subscribe: self.settings().auto_subscribe(),
                         enable_e2ee: self.settings().enable_e2ee(),
                         key: self.state.key().to_string(),
+                        audio_processing: self.state.audio_processing(),
+                        signal: self.state.signal.clone(),
+                        test_pattern: self.state.test_pattern.clone(),
+                        publish_profiles: self.state.publish_profiles(),
+                        camera_processing: self.state.camera_processing.clone(),
+                        publish_camera: auto_publish.then(|| self.state.settings.camera.clone()),
+                        publish_microphone: auto_publish,
                     });
                 }
             });
@@ -340,11 +1170,46 @@ impl GridRoom {
         ui.separator();
     }
 
+    /// Local playback volume of a remote participant
+    fn participant_volume(&mut self, ui: &mut egui::Ui, identity: &ParticipantIdentity) {
+        let level = self.audio_output.mixer().participant_level(identity);
+        let mut volume = self.state.mixer.participant(identity);
+        let mut changed = false;
+
+        vu_meter(ui, level);
+        ui.horizontal(|ui| {
+            changed |= ui
+                .add(egui::Slider::new(&mut volume.gain, 0.0..=2.0).text("Volume"))
+                .changed();
+            changed |= ui.checkbox(&mut volume.muted, "Mute").changed();
+        });
+
+        if changed {
+            self.state.mixer.set_participant(identity, volume);
+            self.audio_output
+                .mixer()
+                .set_settings(self.state.mixer.clone());
+        }
+    }
+
     /// Show remote_participants and their tracks
-    fn right_panel(&self, ui: &mut egui::Ui) {
+    fn right_panel(&mut self, ui: &mut egui::Ui) {
         ui.label("Participants");
         ui.separator();
 
+        let master = ui.add(
+            egui::Slider::new(&mut self.state.mixer.master_volume, 0.0..=2.0).text("Master volume"),
+        );
+        if master.changed() {
+            self.audio_output
+                .mixer()
+                .set_settings(self.state.mixer.clone());
+        }
+
+        ui.label("Microphone");
+        vu_meter(ui, self.service.microphone_level());
+        ui.separator();
+
         let Some(room) = self.service.room() else {
             return;
         };
@@ -365,6 +1230,7 @@ impl GridRoom {
                 sorted_tracks.sort_by(|a, b| a.as_str().cmp(b.as_str()));
 
                 ui.monospace(&participant.identity().0);
+                self.participant_volume(ui, &psid);
                 for tsid in sorted_tracks {
                     let publication = tracks.get(&tsid).unwrap().clone();
 
@@ -444,12 +1310,17 @@ impl GridRoom {
         let show_videos = self.service.room().is_some();
 
         if show_videos && self.video_renderers.is_empty() {
+            if !self.pans.is_empty() {
+                self.pans.clear();
+                self.audio_output.mixer().set_pans(HashMap::new());
+            }
             ui.centered_and_justified(|ui| {
                 ui.label("No video tracks subscribed");
             });
             return;
         }
 
+        let mut pans: HashMap<ParticipantIdentity, Vec<f32>> = HashMap::new();
         egui::ScrollArea::vertical().show(ui, |ui| {
             VideoGrid::new("default_grid")
                 .max_columns(6)
@@ -457,27 +1328,44 @@ impl GridRoom {
                     if show_videos {
                         // Draw participant videos
                         for ((participant_sid, _), video_renderer) in &self.video_renderers {
-                            ui.video_frame(|ui| {
+                            let response = ui.video_frame(|ui| {
                                 let room = room.as_ref().unwrap().clone();
 
                                 if let Some(participant) =
                                     room.remote_participants().get(participant_sid)
                                 {
+                                    let muted =
+                                        participant.track_publications().values().any(|p| {
+                                            p.source() == TrackSource::Microphone && p.is_muted()
+                                        });
                                     draw_video(
                                         participant.name().as_str(),
                                         participant.is_speaking(),
+                                        muted,
+                                        self.audio_output
+                                            .mixer()
+                                            .participant_level(participant_sid),
                                         video_renderer,
                                         ui,
                                     );
                                 } else {
+                                    let local = room.local_participant();
+                                    let muted = local.track_publications().values().any(|p| {
+                                        p.source() == TrackSource::Microphone && p.is_muted()
+                                    });
                                     draw_video(
-                                        room.local_participant().name().as_str(),
-                                        room.local_participant().is_speaking(),
+                                        local.name().as_str(),
+                                        local.is_speaking(),
+                                        muted,
+                                        self.service.microphone_level(),
                                         video_renderer,
                                         ui,
                                     );
                                 }
                             });
+                            pans.entry(participant_sid.clone())
+                                .or_default()
+                                .push(ui.column_pan(response.rect));
                         }
                     } else {
                         // Draw video skeletons when we're not connected
@@ -493,11 +1381,31 @@ impl GridRoom {
                     }
                 })
         });
+
+        // Participants with several videos are placed between them
+        let pans = pans
+            .into_iter()
+            .map(|(identity, pans)| {
+                let pan = pans.iter().sum::<f32>() / pans.len() as f32;
+                (identity, pan)
+            })
+            .collect::<HashMap<_, _>>();
+        if pans != self.pans {
+            self.audio_output.mixer().set_pans(pans.clone());
+            self.pans = pans;
+        }
     }
 }
 
 /// Draw a wgpu texture to the VideoGrid
-fn draw_video(name: &str, speaking: bool, video_renderer: &VideoRenderer, ui: &mut egui::Ui) {
+fn draw_video(
+    name: &str,
+    speaking: bool,
+    muted: bool,
+    level: AudioLevel,
+    video_renderer: &VideoRenderer,
+    ui: &mut egui::Ui,
+) {
     let rect = ui.available_rect_before_wrap();
     let inner_rect = rect.shrink(1.0);
 
@@ -513,14 +1421,7 @@ fn draw_video(name: &str, speaking: bool, video_renderer: &VideoRenderer, ui: &m
 
     // Always draw a background in case we still didn't receive a frame
     let resolution = video_renderer.resolution();
-    if let Some(tex) = video_renderer.texture_id() {
-        ui.painter().image(
-            tex,
-            inner_rect,
-            egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
-            egui::Color32::WHITE,
-        );
-    }
+    video_renderer.paint(ui.painter(), inner_rect);
 
     ui.painter().text(
         egui::pos2(rect.min.x + 5.0, rect.max.y - 5.0),
@@ -529,4 +1430,427 @@ fn draw_video(name: &str, speaking: bool, video_renderer: &VideoRenderer, ui: &m
         egui::FontId::default(),
         egui::Color32::WHITE,
     );
+
+    // Audio level bar along the top edge of the tile
+    let rms = AudioLevel::meter_fraction(level.rms);
+    let peak = AudioLevel::meter_fraction(level.peak);
+    let bar = egui::Rect::from_min_size(inner_rect.min, egui::vec2(inner_rect.width(), 4.0));
+    ui.painter().rect_filled(
+        egui::Rect::from_min_size(bar.min, egui::vec2(bar.width() * rms, bar.height())),
+        CornerRadius::default(),
+        egui::Color32::GREEN,
+    );
+    if peak > 0.0 {
+        let x = bar.min.x + bar.width() * peak;
+        ui.painter()
+            .vline(x, bar.y_range(), Stroke::new(2.0, egui::Color32::YELLOW));
+    }
+
+    if muted {
+        ui.painter().text(
+            egui::pos2(rect.max.x - 5.0, rect.max.y - 5.0),
+            egui::Align2::RIGHT_BOTTOM,
+            "Muted",
+            egui::FontId::default(),
+            egui::Color32::RED,
+        );
+    }
+}
+
+/// Small horizontal VU meter showing RMS as a bar and the held peak as a tick
+pub(super) fn vu_meter(ui: &mut egui::Ui, level: AudioLevel) {
+    let size = egui::vec2(ui.available_width(), 6.0);
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::hover());
+
+    let rms = AudioLevel::meter_fraction(level.rms);
+    let peak = AudioLevel::meter_fraction(level.peak);
+    let color = if level.peak >= 1.0 {
+        egui::Color32::RED
+    } else {
+        egui::Color32::GREEN
+    };
+
+    let painter = ui.painter();
+    painter.rect_filled(
+        rect,
+        CornerRadius::default(),
+        ui.style().visuals.code_bg_color,
+    );
+    painter.rect_filled(
+        egui::Rect::from_min_size(rect.min, egui::vec2(rect.width() * rms, rect.height())),
+        CornerRadius::default(),
+        color,
+    );
+    if peak > 0.0 {
+        painter.vline(
+            rect.min.x + rect.width() * peak,
+            rect.y_range(),
+            Stroke::new(2.0, egui::Color32::YELLOW),
+        );
+    }
+
+    response.on_hover_text(format!(
+        "rms {:.1} dBFS, peak {:.1} dBFS",
+        level.rms_db(),
+        level.peak_db()
+    ));
+}
+
+/// Controls for the signal generator, returns true when a parameter changed.
+fn signal_generator(ui: &mut egui::Ui, params: &mut SignalParameters) -> bool {
+    let mut changed = false;
+
+    egui::ComboBox::from_label("Signal")
+        .selected_text(params.signal.name())
+        .show_ui(ui, |ui| {
+            for signal in Signal::all() {
+                let selected =
+                    std::mem::discriminant(&signal) == std::mem::discriminant(&params.signal);
+                if ui.selectable_label(selected, signal.name()).clicked() && !selected {
+                    params.signal = signal;
+                    changed = true;
+                }
+            }
+        });
+
+    changed |= ui
+        .add(egui::Slider::new(&mut params.amplitude, 0.0..=1.0).text("Amplitude"))
+        .changed();
+
+    match &mut params.signal {
+        Signal::Sine | Signal::ChannelId => {
+            changed |= ui
+                .add(
+                    egui::Slider::new(&mut params.freq, 20.0..=20000.0)
+                        .logarithmic(true)
+                        .suffix(" Hz")
+                        .text("Frequency"),
+                )
+                .changed();
+        }
+        Signal::Sweep {
+            start,
+            end,
+            seconds,
+            logarithmic,
+        } => {
+            changed |= ui
+                .add(
+                    egui::Slider::new(start, 20.0..=20000.0)
+                        .logarithmic(true)
+                        .suffix(" Hz")
+                        .text("From"),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    egui::Slider::new(end, 20.0..=20000.0)
+                        .logarithmic(true)
+                        .suffix(" Hz")
+                        .text("To"),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    egui::Slider::new(seconds, 0.1..=60.0)
+                        .suffix(" s")
+                        .text("Duration"),
+                )
+                .changed();
+            changed |= ui.checkbox(logarithmic, "Logarithmic").changed();
+        }
+        Signal::Dtmf {
+            digits,
+            tone_ms,
+            gap_ms,
+        } => {
+            ui.horizontal(|ui| {
+                ui.label("Digits: ");
+                changed |= ui.text_edit_singleline(digits).changed();
+            });
+            changed |= ui
+                .add(
+                    egui::Slider::new(tone_ms, 40..=1000)
+                        .suffix(" ms")
+                        .text("Tone"),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    egui::Slider::new(gap_ms, 40..=1000)
+                        .suffix(" ms")
+                        .text("Gap"),
+                )
+                .changed();
+        }
+        Signal::WhiteNoise | Signal::PinkNoise => {}
+    }
+
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.label("Channels: ");
+        changed |= ui
+            .radio_value(&mut params.num_channels, 1, "Mono")
+            .changed();
+        changed |= ui
+            .radio_value(&mut params.num_channels, 2, "Stereo")
+            .changed();
+    });
+    ui.label("Channel changes apply when the track is published again.");
+
+    changed
+}
+
+/// Controls for the test pattern, returns true when a setting changed.
+fn test_pattern_settings(ui: &mut egui::Ui, settings: &mut TestPatternSettings) -> bool {
+    let mut changed = false;
+
+    egui::ComboBox::from_label("Pattern")
+        .selected_text(settings.pattern.name())
+        .show_ui(ui, |ui| {
+            for pattern in TestPattern::ALL {
+                changed |= ui
+                    .selectable_value(&mut settings.pattern, pattern, pattern.name())
+                    .changed();
+            }
+        });
+
+    ui.horizontal(|ui| {
+        ui.label("Resolution: ");
+        for (width, height) in [(640, 360), (1280, 720), (1920, 1080)] {
+            let selected = settings.width == width && settings.height == height;
+            if ui
+                .selectable_label(selected, format!("{}p", height))
+                .clicked()
+                && !selected
+            {
+                settings.width = width;
+                settings.height = height;
+                changed = true;
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        changed |= ui
+            .add(egui::DragValue::new(&mut settings.width).range(16..=3840))
+            .changed();
+        ui.label("×");
+        changed |= ui
+            .add(egui::DragValue::new(&mut settings.height).range(16..=3840))
+            .changed();
+    });
+    changed |= ui
+        .add(
+            egui::Slider::new(&mut settings.fps, 1..=60)
+                .suffix(" fps")
+                .text("Frame rate"),
+        )
+        .changed();
+
+    ui.separator();
+    changed |= ui
+        .checkbox(&mut settings.frame_counter, "Frame counter")
+        .changed();
+    changed |= ui
+        .checkbox(&mut settings.timecode, "Timecode (UTC)")
+        .changed();
+    changed |= ui
+        .checkbox(&mut settings.name_label, "Participant name")
+        .changed();
+
+    changed
+}
+
+/// The published camera as others see it, after processing.
+fn camera_preview(ui: &mut egui::Ui, renderer: Option<&VideoRenderer>) {
+    let Some(renderer) = renderer else {
+        ui.label("Publish the camera to see a preview.");
+        return;
+    };
+
+    let (width, height) = renderer.resolution();
+    let aspect = if width > 0 && height > 0 {
+        height as f32 / width as f32
+    } else {
+        9.0 / 16.0
+    };
+    let size = egui::vec2(320.0, 320.0 * aspect);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    ui.painter()
+        .rect_filled(rect, CornerRadius::default(), egui::Color32::BLACK);
+    renderer.paint(ui.painter(), rect);
+}
+
+/// Camera processing steps in the order they run, returns true when a setting changed.
+///
+/// `capture_background` is set when the background plate should be learned again.
+fn camera_processing(
+    ui: &mut egui::Ui,
+    settings: &mut ProcessingSettings,
+    capture_background: &mut bool,
+) -> bool {
+    let mut changed = false;
+    settings.add_missing_steps();
+    let mut swap = None;
+    let count = settings.steps.len();
+
+    for (i, step) in settings.steps.iter_mut().enumerate() {
+        ui.push_id(i, |ui| {
+            ui.horizontal(|ui| {
+                if ui.add_enabled(i > 0, egui::Button::new("⬆")).clicked() {
+                    swap = Some((i - 1, i));
+                }
+                if ui
+                    .add_enabled(i + 1 < count, egui::Button::new("⬇"))
+                    .clicked()
+                {
+                    swap = Some((i, i + 1));
+                }
+                changed |= ui
+                    .checkbox(&mut step.enabled, step.processor.name())
+                    .changed();
+            });
+
+            ui.add_enabled_ui(step.enabled, |ui| match &mut step.processor {
+                Processor::Crop {
+                    left,
+                    top,
+                    right,
+                    bottom,
+                } => {
+                    for (value, label) in [
+                        (left, "Left"),
+                        (top, "Top"),
+                        (right, "Right"),
+                        (bottom, "Bottom"),
+                    ] {
+                        changed |= ui
+                            .add(egui::Slider::new(value, 0..=45).suffix(" %").text(label))
+                            .changed();
+                    }
+                }
+                Processor::Rotate(rotation) => {
+                    ui.horizontal(|ui| {
+                        for (value, label) in [
+                            (Rotation::Rotate90, "90°"),
+                            (Rotation::Rotate180, "180°"),
+                            (Rotation::Rotate270, "270°"),
+                        ] {
+                            changed |= ui.radio_value(rotation, value, label).changed();
+                        }
+                    });
+                }
+                Processor::Brightness(offset) => {
+                    changed |= ui.add(egui::Slider::new(offset, -100..=100)).changed();
+                }
+                Processor::Background(background) => {
+                    changed |= background_settings(ui, background, capture_background);
+                }
+                Processor::Mirror | Processor::Watermark => {}
+            });
+        });
+    }
+
+    if let Some((a, b)) = swap {
+        settings.steps.swap(a, b);
+        changed = true;
+    }
+
+    ui.separator();
+    if ui.button("Reset").clicked() {
+        *settings = ProcessingSettings::default();
+        changed = true;
+    }
+
+    changed
+}
+
+/// Controls for background blur and replacement, returns true when a setting changed.
+fn background_settings(
+    ui: &mut egui::Ui,
+    settings: &mut BackgroundSettings,
+    capture_background: &mut bool,
+) -> bool {
+    let mut changed = false;
+
+    ui.horizontal(|ui| {
+        changed |= ui
+            .radio_value(&mut settings.mode, BackgroundMode::Blur, "Blur")
+            .changed();
+        changed |= ui
+            .radio_value(&mut settings.mode, BackgroundMode::Image, "Image")
+            .changed();
+    });
+    match settings.mode {
+        BackgroundMode::Blur => {
+            changed |= ui
+                .add(egui::Slider::new(&mut settings.blur, 1..=10).text("Strength"))
+                .changed();
+        }
+        BackgroundMode::Image => {
+            ui.horizontal(|ui| {
+                ui.label("Image: ");
+                changed |= ui
+                    .text_edit_singleline(&mut settings.image)
+                    .on_hover_text("Blurs instead when the image can't be loaded")
+                    .lost_focus();
+            });
+        }
+    }
+    changed |= ui
+        .add(egui::Slider::new(&mut settings.sensitivity, 1..=100).text("Sensitivity"))
+        .on_hover_text("Raise it when parts of you are hidden, lower it when the room shows")
+        .changed();
+
+    ui.horizontal(|ui| {
+        if ui
+            .button("Capture background")
+            .on_hover_text("Until then a silhouette in the middle of the frame is kept")
+            .clicked()
+        {
+            *capture_background = true;
+        }
+        ui.label("Step out of the frame for a second first.");
+    });
+
+    changed
+}
+
+/// Bar chart of latencies in 10ms buckets.
+fn latency_histogram(ui: &mut egui::Ui, report: &LatencyReport) {
+    const BUCKET_MS: u64 = 10;
+
+    let buckets = report.histogram(std::time::Duration::from_millis(BUCKET_MS));
+    let size = egui::vec2(ui.available_width().max(240.0), 120.0);
+    let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(
+        rect,
+        CornerRadius::default(),
+        ui.style().visuals.code_bg_color,
+    );
+
+    let Some(highest) = buckets.iter().max().copied().filter(|count| *count > 0) else {
+        return;
+    };
+
+    let bar_width = rect.width() / buckets.len() as f32;
+    for (i, count) in buckets.iter().enumerate() {
+        let height = rect.height() * *count as f32 / highest as f32;
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(rect.left() + i as f32 * bar_width, rect.bottom() - height),
+            egui::pos2(
+                rect.left() + (i + 1) as f32 * bar_width - 1.0,
+                rect.bottom(),
+            ),
+        );
+        painter.rect_filled(bar, CornerRadius::default(), egui::Color32::LIGHT_BLUE);
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("0 ms");
+        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+            ui.label(format!("{} ms", buckets.len() as u64 * BUCKET_MS));
+        });
+    });
 }
@@ -1,5 +1,9 @@
+use crate::audio::{AudioDevice, AudioProcessing, DeviceKind, DeviceMonitor, SoundCue, SoundCues};
+use crate::camera::{CameraDevice, CameraList, CameraSettings};
+use crate::publish_profile::{Codec, PublishProfiles, VideoProfile};
 use keycast::discovery::Discovery;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 use base64::{engine::general_purpose, Engine as _};
 use rand::RngCore;
@@ -33,6 +37,14 @@ pub trait Settings {
     fn use_discovery(&self) -> bool {
         true
     }
+    /// processing applied to every audio track we publish
+    fn audio_processing(&self) -> AudioProcessing {
+        AudioProcessing::default()
+    }
+    /// codec and limits of each kind of video we publish
+    fn publish_profiles(&self) -> PublishProfiles {
+        PublishProfiles::default()
+    }
 }
 
 /// per server manual configuration.
@@ -65,7 +77,11 @@ impl ServerSettings {
             token: "".to_string(),
         }
     }
-    pub fn from_response(settings: &GeneralSettings, ident: &str, response: &TokenResponse) -> Self {
+    pub fn from_response(
+        settings: &GeneralSettings,
+        ident: &str,
+        response: &TokenResponse,
+    ) -> Self {
         Self {
             settings: settings.clone(),
             name: ident.to_string(),
@@ -91,6 +107,14 @@ impl Settings for ServerSettings {
     fn use_discovery(&self) -> bool {
         self.settings.use_discovery()
     }
+
+    fn audio_processing(&self) -> AudioProcessing {
+        self.settings.audio_processing()
+    }
+
+    fn publish_profiles(&self) -> PublishProfiles {
+        self.settings.publish_profiles()
+    }
 }
 
 /// per room manual configuration settings.
@@ -113,7 +137,11 @@ impl RoomSettings {
         self.server.set_url(url)
     }
 
-    pub fn from_response(settings: &GeneralSettings, ident: &str, response: &TokenResponse) -> Self {
+    pub fn from_response(
+        settings: &GeneralSettings,
+        ident: &str,
+        response: &TokenResponse,
+    ) -> Self {
         let server = ServerSettings::from_response(settings, ident, response);
         Self {
             id: response.room_id,
@@ -154,6 +182,33 @@ impl Settings for RoomSettings {
     fn use_discovery(&self) -> bool {
         self.server.use_discovery()
     }
+
+    fn audio_processing(&self) -> AudioProcessing {
+        self.server.audio_processing()
+    }
+
+    fn publish_profiles(&self) -> PublishProfiles {
+        self.server.publish_profiles()
+    }
+}
+
+/// Keyboard shortcuts for the local microphone.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct KeyBindings {
+    pub toggle_mute: egui::Key,
+    /// while enabled the microphone is only live when `push_to_talk` is held
+    pub push_to_talk_enabled: bool,
+    pub push_to_talk: egui::Key,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            toggle_mute: egui::Key::M,
+            push_to_talk_enabled: false,
+            push_to_talk: egui::Key::Space,
+        }
+    }
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
@@ -162,6 +217,25 @@ pub struct GeneralSettings {
     pub auto_publish: bool,
     pub enable_e2ee: bool,
     pub use_discovery: bool,
+    /// PipeWire node name of the preferred capture device, `None` follows the default source.
+    #[serde(default)]
+    pub audio_input: Option<String>,
+    /// PipeWire node name of the preferred playback device, `None` follows the default sink.
+    #[serde(default)]
+    pub audio_output: Option<String>,
+    #[serde(default)]
+    pub audio_processing: AudioProcessing,
+    #[serde(default)]
+    pub key_bindings: KeyBindings,
+    /// pan remote participants by the column of their video
+    #[serde(default)]
+    pub spatial_audio: bool,
+    #[serde(default)]
+    pub sound_cues: SoundCues,
+    #[serde(default)]
+    pub camera: CameraSettings,
+    #[serde(default)]
+    pub publish_profiles: PublishProfiles,
 }
 
 impl Default for GeneralSettings {
@@ -171,6 +245,14 @@ impl Default for GeneralSettings {
             auto_publish: false,
             enable_e2ee: false,
             use_discovery: true,
+            audio_input: None,
+            audio_output: None,
+            audio_processing: AudioProcessing::default(),
+            key_bindings: KeyBindings::default(),
+            spatial_audio: false,
+            sound_cues: SoundCues::default(),
+            camera: CameraSettings::default(),
+            publish_profiles: PublishProfiles::default(),
         }
     }
 }
@@ -191,19 +273,351 @@ impl Settings for GeneralSettings {
     fn use_discovery(&self) -> bool {
         self.use_discovery
     }
+
+    fn audio_processing(&self) -> AudioProcessing {
+        self.audio_processing
+    }
+
+    fn publish_profiles(&self) -> PublishProfiles {
+        self.publish_profiles
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SettingsPage {
     state: GeneralSettings,
     servers: Vec<ServerSettings>,
+    devices: Arc<DeviceMonitor>,
+    cameras: CameraList,
+    changed: bool,
 }
 
 impl SettingsPage {
-    pub fn new(runtime: &tokio::runtime::Runtime, cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(
+        runtime: &tokio::runtime::Runtime,
+        cc: &eframe::CreationContext<'_>,
+        state: GeneralSettings,
+        devices: Arc<DeviceMonitor>,
+    ) -> Self {
         Self {
-            state: GeneralSettings::default(),
+            state,
             servers: Vec::new(),
+            devices,
+            cameras: CameraList::new(),
+            changed: false,
+        }
+    }
+
+    pub fn state(&self) -> &GeneralSettings {
+        &self.state
+    }
+
+    /// Returns true once after the settings were edited.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    pub fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            self.central_panel(ui);
+        });
+    }
+
+    fn central_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Settings");
+        ui.add_space(8.0);
+
+        let mut changed = false;
+        changed |= ui
+            .checkbox(&mut self.state.auto_subscribe, "Auto Subscribe")
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.state.auto_publish, "Auto Publish")
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.state.enable_e2ee, "Enable E2ee")
+            .changed();
+        changed |= ui
+            .checkbox(&mut self.state.use_discovery, "Use Discovery")
+            .changed();
+
+        ui.separator();
+        ui.monospace("Audio devices");
+        ui.add_space(4.0);
+
+        let sources = self.devices.devices(DeviceKind::Source);
+        changed |= device_picker(ui, "Input", &sources, &mut self.state.audio_input);
+
+        let sinks = self.devices.devices(DeviceKind::Sink);
+        changed |= device_picker(ui, "Output", &sinks, &mut self.state.audio_output);
+        changed |= ui
+            .checkbox(&mut self.state.spatial_audio, "Spatial audio")
+            .on_hover_text("Hear participants from the side their video is on")
+            .changed();
+
+        ui.separator();
+        ui.monospace("Camera");
+        ui.add_space(4.0);
+
+        let cameras = self.cameras.cameras();
+        changed |= camera_picker(ui, &cameras, &mut self.state.camera);
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!self.cameras.is_scanning(), egui::Button::new("Refresh"))
+                .clicked()
+            {
+                self.cameras.refresh();
+            }
+            if self.cameras.is_scanning() {
+                ui.spinner();
+            }
+        });
+
+        ui.separator();
+        ui.monospace("Audio processing");
+        ui.add_space(4.0);
+
+        changed |= audio_processing_editor(ui, &mut self.state.audio_processing);
+
+        ui.separator();
+        ui.monospace("Video publishing");
+        ui.add_space(4.0);
+
+        changed |= publish_profiles_editor(ui, &mut self.state.publish_profiles);
+        ui.label("Audio processing and video publishing are copied to newly joined servers.");
+        ui.label("Change them for the current server from the room's Publish menu.");
+
+        ui.separator();
+        ui.monospace("Key bindings");
+        ui.add_space(4.0);
+
+        let bindings = &mut self.state.key_bindings;
+        changed |= key_picker(ui, "Toggle mute", &mut bindings.toggle_mute);
+        changed |= ui
+            .checkbox(&mut bindings.push_to_talk_enabled, "Push to talk")
+            .changed();
+        ui.add_enabled_ui(bindings.push_to_talk_enabled, |ui| {
+            changed |= key_picker(ui, "Push to talk key", &mut bindings.push_to_talk);
+        });
+
+        ui.separator();
+        ui.monospace("Sound cues");
+        ui.add_space(4.0);
+
+        for cue in SoundCue::ALL {
+            let enabled = self.state.sound_cues.enabled_mut(cue);
+            changed |= ui.checkbox(enabled, cue.name()).changed();
         }
+
+        self.changed |= changed;
+    }
+}
+
+/// Camera and capture mode combo boxes, returns true when the selection changed.
+fn camera_picker(
+    ui: &mut egui::Ui,
+    cameras: &[CameraDevice],
+    settings: &mut CameraSettings,
+) -> bool {
+    let mut changed = false;
+
+    ui.horizontal(|ui| {
+        ui.label("Device");
+        egui::ComboBox::from_id_salt("camera_device")
+            .selected_text(settings.device.as_deref().unwrap_or("Default"))
+            .show_ui(ui, |ui| {
+                if ui
+                    .selectable_label(settings.device.is_none(), "Default")
+                    .clicked()
+                {
+                    settings.device = None;
+                    settings.device_id = None;
+                    settings.format = None;
+                    changed = true;
+                }
+                for camera in cameras {
+                    let selected = settings.is_device(camera);
+                    if ui
+                        .selectable_label(selected, &camera.name)
+                        .on_hover_text(&camera.id)
+                        .clicked()
+                        && !selected
+                    {
+                        settings.device = Some(camera.name.clone());
+                        settings.device_id = Some(camera.id.clone());
+                        settings.format = None;
+                        changed = true;
+                    }
+                }
+            });
+    });
+
+    // The default camera is the first one, same as when publishing
+    let formats = match &settings.device {
+        Some(_) => cameras.iter().find(|camera| settings.is_device(camera)),
+        None => cameras.first(),
+    }
+    .map(|camera| camera.formats.as_slice())
+    .unwrap_or_default();
+
+    ui.horizontal(|ui| {
+        ui.label("Format");
+        let selected_text = settings
+            .format
+            .map(|format| format.to_string())
+            .unwrap_or_else(|| "Highest frame rate".to_string());
+        egui::ComboBox::from_id_salt("camera_format")
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                if ui
+                    .selectable_label(settings.format.is_none(), "Highest frame rate")
+                    .clicked()
+                {
+                    settings.format = None;
+                    changed = true;
+                }
+                for format in formats {
+                    let selected = settings.format == Some(*format);
+                    if ui.selectable_label(selected, format.to_string()).clicked() {
+                        settings.format = Some(*format);
+                        changed = true;
+                    }
+                }
+            });
+    });
+
+    changed
+}
+
+/// Music mode and the individual processing toggles, returns true when something changed.
+pub fn audio_processing_editor(ui: &mut egui::Ui, processing: &mut AudioProcessing) -> bool {
+    let mut changed = false;
+
+    let mut music_mode = processing.music_mode;
+    if ui.checkbox(&mut music_mode, "Music mode").changed() {
+        *processing = if music_mode {
+            AudioProcessing::music()
+        } else {
+            AudioProcessing::default()
+        };
+        changed = true;
+    }
+
+    ui.add_enabled_ui(!processing.music_mode, |ui| {
+        changed |= ui
+            .checkbox(&mut processing.echo_cancellation, "Echo cancellation")
+            .changed();
+        changed |= ui
+            .checkbox(&mut processing.noise_suppression, "Noise suppression")
+            .changed();
+        changed |= ui
+            .checkbox(&mut processing.auto_gain_control, "Auto gain control")
+            .changed();
+    });
+
+    changed
+}
+
+/// One collapsible editor per kind of video, returns true when something changed.
+pub fn publish_profiles_editor(ui: &mut egui::Ui, profiles: &mut PublishProfiles) -> bool {
+    let mut changed = false;
+    for (name, profile) in profiles.iter_mut() {
+        changed |= profile_editor(ui, name, profile);
     }
+    changed
+}
+
+/// Codec, simulcast and limits of one kind of video, returns true when something changed.
+fn profile_editor(ui: &mut egui::Ui, name: &str, profile: &mut VideoProfile) -> bool {
+    let mut changed = false;
+
+    ui.collapsing(name, |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Codec");
+            egui::ComboBox::from_id_salt((name, "codec"))
+                .selected_text(profile.codec.name())
+                .show_ui(ui, |ui| {
+                    for codec in Codec::ALL {
+                        changed |= ui
+                            .selectable_value(&mut profile.codec, codec, codec.name())
+                            .changed();
+                    }
+                });
+        });
+        changed |= ui
+            .checkbox(&mut profile.simulcast, "Simulcast")
+            .on_hover_text("Up to three layers, depending on the resolution")
+            .changed();
+        ui.horizontal(|ui| {
+            ui.label("Max bitrate");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut profile.max_bitrate)
+                        .range(0..=50_000)
+                        .suffix(" kbps"),
+                )
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Max frame rate");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut profile.max_framerate)
+                        .range(0..=120)
+                        .suffix(" fps"),
+                )
+                .changed();
+        });
+        ui.label("0 picks LiveKit's preset for the resolution.");
+    });
+
+    changed
+}
+
+/// Combo box listing every egui key, returns true when the selection changed.
+fn key_picker(ui: &mut egui::Ui, label: &str, selected: &mut egui::Key) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label(label);
+        egui::ComboBox::from_id_salt(label)
+            .selected_text(selected.name())
+            .show_ui(ui, |ui| {
+                for key in egui::Key::ALL {
+                    changed |= ui.selectable_value(selected, *key, key.name()).changed();
+                }
+            });
+    });
+    changed
+}
+
+/// Combo box listing PipeWire devices by description, returns true when the selection changed.
+fn device_picker(
+    ui: &mut egui::Ui,
+    label: &str,
+    devices: &[AudioDevice],
+    selected: &mut Option<String>,
+) -> bool {
+    let selected_text = match selected.as_deref() {
+        None => "Default".to_string(),
+        Some(name) => devices
+            .iter()
+            .find(|device| device.name == name)
+            .map(|device| device.description.clone())
+            .unwrap_or_else(|| format!("{} (unavailable)", name)),
+    };
+
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label(label);
+        egui::ComboBox::from_id_salt(label)
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                changed |= ui.selectable_value(selected, None, "Default").changed();
+                for device in devices {
+                    changed |= ui
+                        .selectable_value(selected, Some(device.name.clone()), &device.description)
+                        .changed();
+                }
+            });
+    });
+    changed
 }
@@ -0,0 +1,141 @@
+use livekit::options::{self, TrackPublishOptions, VideoCodec, VideoEncoding};
+use livekit::prelude::*;
+use livekit::webrtc::video_source::VideoResolution;
+use serde::{Deserialize, Serialize};
+
+/// Video codecs we can publish with, saved by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Codec {
+    Vp8,
+    Vp9,
+    Av1,
+    H264,
+    H265,
+}
+
+impl Codec {
+    pub const ALL: [Codec; 5] = [Codec::Vp8, Codec::Vp9, Codec::Av1, Codec::H264, Codec::H265];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::Vp8 => "VP8",
+            Codec::Vp9 => "VP9",
+            Codec::Av1 => "AV1",
+            Codec::H264 => "H.264",
+            Codec::H265 => "H.265",
+        }
+    }
+
+    pub fn video_codec(&self) -> VideoCodec {
+        match self {
+            Codec::Vp8 => VideoCodec::VP8,
+            Codec::Vp9 => VideoCodec::VP9,
+            Codec::Av1 => VideoCodec::AV1,
+            Codec::H264 => VideoCodec::H264,
+            Codec::H265 => VideoCodec::H265,
+        }
+    }
+}
+
+/// How a video track is encoded.
+///
+/// With simulcast LiveKit sends up to three layers, picked from the resolution: all three
+/// from 960 pixels on the long side, two from 480. The limits below apply to the top layer,
+/// lower layers use LiveKit's presets. SVC scalability modes for VP9 and AV1 are not exposed
+/// by the SDK, those codecs are sent as plain simulcast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoProfile {
+    pub codec: Codec,
+    pub simulcast: bool,
+    /// In kbps, 0 picks LiveKit's preset for the resolution
+    pub max_bitrate: u32,
+    /// 0 picks LiveKit's preset for the resolution
+    pub max_framerate: u32,
+}
+
+impl Default for VideoProfile {
+    fn default() -> Self {
+        Self {
+            codec: Codec::Vp8,
+            simulcast: true,
+            max_bitrate: 0,
+            max_framerate: 0,
+        }
+    }
+}
+
+impl VideoProfile {
+    pub fn publish_options(
+        &self,
+        source: TrackSource,
+        resolution: VideoResolution,
+    ) -> TrackPublishOptions {
+        let video_codec = self.codec.video_codec();
+
+        // LiveKit only takes both limits at once, the unset one comes from its presets
+        let video_encoding = (self.max_bitrate > 0 || self.max_framerate > 0).then(|| {
+            let preset = options::compute_appropriate_encoding(
+                source == TrackSource::Screenshare,
+                resolution.width,
+                resolution.height,
+                video_codec,
+            );
+            VideoEncoding {
+                max_bitrate: match self.max_bitrate {
+                    0 => preset.max_bitrate,
+                    kbps => kbps as u64 * 1000,
+                },
+                max_framerate: match self.max_framerate {
+                    0 => preset.max_framerate,
+                    fps => fps as f64,
+                },
+            }
+        });
+
+        TrackPublishOptions {
+            source,
+            video_codec,
+            simulcast: self.simulcast,
+            video_encoding,
+            ..Default::default()
+        }
+    }
+}
+
+/// A [`VideoProfile`] for each kind of video we publish.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PublishProfiles {
+    pub camera: VideoProfile,
+    pub screen_share: VideoProfile,
+    pub test_pattern: VideoProfile,
+    pub video_file: VideoProfile,
+}
+
+impl Default for PublishProfiles {
+    fn default() -> Self {
+        Self {
+            camera: VideoProfile::default(),
+            screen_share: VideoProfile::default(),
+            // Kept from when the test pattern was only used to try out H.265
+            test_pattern: VideoProfile {
+                codec: Codec::H265,
+                ..Default::default()
+            },
+            video_file: VideoProfile::default(),
+        }
+    }
+}
+
+impl PublishProfiles {
+    /// Each profile with its name, in the order they are shown in the settings.
+    pub fn iter_mut(&mut self) -> [(&'static str, &mut VideoProfile); 4] {
+        [
+            ("Camera", &mut self.camera),
+            ("Screen share", &mut self.screen_share),
+            ("Test pattern", &mut self.test_pattern),
+            ("Video file", &mut self.video_file),
+        ]
+    }
+}
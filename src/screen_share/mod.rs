@@ -0,0 +1,250 @@
+//! Screen sharing from PipeWire.
+//!
+//! On Wayland the compositor only hands out screen content through the xdg-desktop-portal
+//! ScreenCast interface, which gives us a [`PortalSession`] and a PipeWire node to read
+//! frames from. Any other PipeWire video node can be shared directly by its id, for example
+//! `gst-launch-1.0 videotestsrc is-live=true ! video/x-raw,format=BGRx ! pipewiresink`,
+//! which needs no compositor at all. Frames are read by a [`VideoStream`].
+//!
+//! X11 sessions have no portal, there [`X11Capture`] reads the root window, a monitor or a
+//! single window straight from the X server.
+//!
+//! What other applications play can be shared along with the video as a second track, it
+//! is published and unpublished together with the screen.
+pub mod portal;
+pub mod stream;
+pub mod x11;
+
+pub use portal::PortalSession;
+pub use stream::{StreamError, VideoStream};
+pub use x11::{X11Capture, X11Error, X11Source, X11Target};
+
+use crate::audio::{
+    AudioProcessing, GeneratedTrack, SystemAudioGenerator, NUM_CHANNELS, SAMPLE_RATE,
+};
+use crate::publish_profile::VideoProfile;
+use livekit::prelude::*;
+use livekit::webrtc::video_source::native::NativeVideoSource;
+use livekit::webrtc::video_source::{RtcVideoSource, VideoResolution};
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use thiserror::Error;
+
+// Used until the stream negotiates its real size
+const DEFAULT_WIDTH: u32 = 1920;
+const DEFAULT_HEIGHT: u32 = 1080;
+// X11 has no frame clock to follow, this is plenty for text and slides
+const X11_FRAME_RATE: u32 = 15;
+// Keeps system audio roughly in step with the video, which is sent as soon as it is captured
+const SYSTEM_AUDIO_QUEUE_MS: u32 = 50;
+
+/// What to share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenShareSource {
+    /// Let the user pick a monitor or window through xdg-desktop-portal
+    Portal,
+    /// A video node on the default PipeWire remote
+    Node(u32),
+    /// Capture from the X server of `DISPLAY`
+    X11(X11Target),
+}
+
+#[derive(Debug, Error)]
+pub enum ScreenShareError {
+    #[error("screen cast portal failed: {0}")]
+    Portal(#[from] ashpd::Error),
+    #[error("the screen cast portal returned no stream")]
+    NoStream,
+    #[error("failed to capture X11 screen: {0}")]
+    X11(#[from] X11Error),
+    #[error("failed to publish screen share: {0}")]
+    Room(#[from] RoomError),
+    #[error("failed to publish screen share audio: {0}")]
+    Audio(RoomError),
+    #[error("nothing is shared to add audio to")]
+    NotShared,
+}
+
+type FailedCallback = Arc<dyn Fn(String) + Send + Sync>;
+
+struct TrackHandle {
+    track: LocalVideoTrack,
+    // Dropping the capture stops reading frames
+    _capture: Box<dyn Any + Send>,
+    session: Option<PortalSession>,
+    audio: Option<GeneratedTrack>,
+    /// Set once the capture stopped on its own
+    failed: Arc<AtomicBool>,
+}
+
+/// A shared screen, window or PipeWire node, published and unpublished on demand.
+pub struct ScreenShareTrack {
+    room: Arc<Room>,
+    on_failed: Option<FailedCallback>,
+    handle: Option<TrackHandle>,
+}
+
+impl ScreenShareTrack {
+    pub fn new(room: Arc<Room>) -> Self {
+        Self {
+            room,
+            on_failed: None,
+            handle: None,
+        }
+    }
+
+    /// Called with the error when capturing stops on its own, for example when a shared X11
+    /// window is closed or the PipeWire stream fails. The track stays published until it is
+    /// unpublished.
+    pub fn on_failed(mut self, on_failed: impl Fn(String) + Send + Sync + 'static) -> Self {
+        self.on_failed = Some(Arc::new(on_failed));
+        self
+    }
+
+    pub fn is_published(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    /// Whether the current share stopped capturing, a share started since is not.
+    pub fn has_failed(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_some_and(|handle| handle.failed.load(Ordering::Acquire))
+    }
+
+    /// Start sharing `source`, replacing the current share.
+    pub async fn publish(
+        &mut self,
+        source: ScreenShareSource,
+        profile: VideoProfile,
+    ) -> Result<(), ScreenShareError> {
+        self.unpublish().await?;
+
+        // The portal dialog comes first, it tells the size of what the user picked
+        let portal = match source {
+            ScreenShareSource::Portal => Some(PortalSession::open().await?),
+            _ => None,
+        };
+
+        let (width, height) = match source {
+            ScreenShareSource::X11(X11Target::Monitor { width, height, .. }) => {
+                (width as u32, height as u32)
+            }
+            _ => portal
+                .as_ref()
+                .and_then(|(session, _)| session.size())
+                .unwrap_or((DEFAULT_WIDTH, DEFAULT_HEIGHT)),
+        };
+        let resolution = VideoResolution { width, height };
+        let rtc_source = NativeVideoSource::new(resolution.clone());
+        let track = LocalVideoTrack::create_video_track(
+            "screen_share",
+            RtcVideoSource::Native(rtc_source.clone()),
+        );
+
+        let failed = Arc::new(AtomicBool::new(false));
+        let on_error = {
+            let failed = failed.clone();
+            let on_failed = self.on_failed.clone();
+            move |error: String| {
+                failed.store(true, Ordering::Release);
+                if let Some(on_failed) = on_failed {
+                    on_failed(error);
+                }
+            }
+        };
+        let (capture, session): (Box<dyn Any + Send>, _) = match (source, portal) {
+            (_, Some((session, remote))) => {
+                let stream =
+                    VideoStream::new(Some(remote), session.node_id(), rtc_source, move |err| {
+                        on_error(err.to_string())
+                    });
+                (Box::new(stream), Some(session))
+            }
+            (ScreenShareSource::Node(node_id), None) => {
+                let stream = VideoStream::new(None, node_id, rtc_source, move |err| {
+                    on_error(err.to_string())
+                });
+                (Box::new(stream), None)
+            }
+            (ScreenShareSource::X11(target), None) => {
+                let on_error = move |err: X11Error| on_error(err.to_string());
+                let capture = X11Capture::new(target, X11_FRAME_RATE, rtc_source, on_error)?;
+                (Box::new(capture), None)
+            }
+            (ScreenShareSource::Portal, None) => unreachable!("the portal was opened above"),
+        };
+
+        let res = self
+            .room
+            .local_participant()
+            .publish_track(
+                LocalTrack::Video(track.clone()),
+                profile.publish_options(TrackSource::Screenshare, resolution),
+            )
+            .await;
+
+        if let Err(err) = res {
+            drop(capture);
+            if let Some(session) = session {
+                session.close().await;
+            }
+            return Err(err.into());
+        }
+
+        self.handle = Some(TrackHandle {
+            track,
+            _capture: capture,
+            session,
+            audio: None,
+            failed,
+        });
+
+        Ok(())
+    }
+
+    /// Publish what other applications play along with the current share, it is unpublished
+    /// together with the screen.
+    pub async fn publish_audio(&mut self) -> Result<(), ScreenShareError> {
+        let handle = self.handle.as_mut().ok_or(ScreenShareError::NotShared)?;
+        if let Some(mut audio) = handle.audio.take() {
+            audio.unpublish().await.map_err(ScreenShareError::Audio)?;
+        }
+
+        let mut audio = GeneratedTrack::new(
+            self.room.clone(),
+            SAMPLE_RATE,
+            NUM_CHANNELS,
+            AudioProcessing::music(),
+        )
+        .with_source(TrackSource::ScreenshareAudio)
+        .with_queue_size(SYSTEM_AUDIO_QUEUE_MS);
+        audio
+            .publish("screen_share_audio", Box::new(SystemAudioGenerator::new()))
+            .await
+            .map_err(ScreenShareError::Audio)?;
+
+        handle.audio = Some(audio);
+        Ok(())
+    }
+
+    pub async fn unpublish(&mut self) -> Result<(), ScreenShareError> {
+        if let Some(handle) = self.handle.take() {
+            // Keep tearing down, the session and the video track would leak otherwise
+            if let Some(mut audio) = handle.audio {
+                if let Err(err) = audio.unpublish().await {
+                    log::error!("failed to unpublish screen share audio: {:?}", err);
+                }
+            }
+            if let Some(session) = handle.session {
+                session.close().await;
+            }
+            self.room
+                .local_participant()
+                .unpublish_track(&handle.track.sid())
+                .await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use ashpd::desktop::screencast::{CursorMode, Screencast, SourceType};
+use ashpd::desktop::{PersistMode, Session};
+use std::os::fd::OwnedFd;
+
+use super::ScreenShareError;
+
+/// A screen cast granted by the user through xdg-desktop-portal.
+///
+/// The compositor keeps producing frames until the session is closed.
+pub struct PortalSession {
+    session: Session<'static, Screencast<'static>>,
+    node_id: u32,
+    size: Option<(u32, u32)>,
+}
+
+impl PortalSession {
+    /// Let the user pick a monitor or a window in the compositor's dialog.
+    ///
+    /// Returns the session with the PipeWire remote its stream lives on.
+    pub async fn open() -> Result<(Self, OwnedFd), ScreenShareError> {
+        let proxy = Screencast::new().await?;
+        let session = proxy.create_session().await?;
+        proxy
+            .select_sources(
+                &session,
+                CursorMode::Embedded,
+                SourceType::Monitor | SourceType::Window,
+                false,
+                None,
+                PersistMode::DoNot,
+            )
+            .await?;
+
+        let response = proxy.start(&session, None).await?.response()?;
+        let Some(stream) = response.streams().first() else {
+            let _ = session.close().await;
+            return Err(ScreenShareError::NoStream);
+        };
+        let node_id = stream.pipe_wire_node_id();
+        let size = stream
+            .size()
+            .map(|(width, height)| (width as u32, height as u32));
+
+        let remote = proxy.open_pipe_wire_remote(&session).await?;
+
+        Ok((
+            Self {
+                session,
+                node_id,
+                size,
+            },
+            remote,
+        ))
+    }
+
+    pub fn node_id(&self) -> u32 {
+        self.node_id
+    }
+
+    /// Size of the shared monitor or window, when the portal reports it.
+    pub fn size(&self) -> Option<(u32, u32)> {
+        self.size
+    }
+
+    pub async fn close(self) {
+        if let Err(err) = self.session.close().await {
+            log::error!("failed to close screen cast session: {:?}", err);
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use livekit::webrtc::{
+    native::yuv_helper,
+    video_frame::{I420Buffer, VideoFrame, VideoRotation},
+    video_source::native::NativeVideoSource,
+};
+use pipewire as pw;
+use pw::properties::properties;
+use pw::spa;
+use pw::spa::param::video::{VideoFormat, VideoInfoRaw};
+use std::cell::RefCell;
+use std::os::fd::OwnedFd;
+use std::rc::Rc;
+use thiserror::Error;
+
+use crate::audio::{param_pod, PwCmd};
+
+const PIXEL_SIZE: usize = 4;
+// Largest frame we accept, anything bigger is scaled down by the producer
+const MAX_SIZE: u32 = 4096;
+
+#[derive(Debug, Error)]
+pub enum StreamError {
+    #[error("PipeWire error: {0}")]
+    PipeWire(#[from] pw::Error),
+    #[error("screen share stream failed: {0}")]
+    Stream(String),
+}
+
+struct StreamData {
+    format: VideoInfoRaw,
+    rtc_source: NativeVideoSource,
+    /// Reused between frames, reallocated when the negotiated size changes
+    frame: Option<VideoFrame<I420Buffer>>,
+}
+
+/// A PipeWire video stream running on its own thread.
+///
+/// Frames are negotiated as packed 32-bit RGB, which screen casts and `videotestsrc` both
+/// produce, converted to I420 and captured by the [`NativeVideoSource`] as they arrive.
+pub struct VideoStream {
+    pw_tx: pw::channel::Sender<PwCmd>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl VideoStream {
+    /// Start streaming from the node `node_id`.
+    ///
+    /// `remote` is the PipeWire connection handed out by the screen cast portal, which is
+    /// the only one the node is visible on. Without it the default remote is used.
+    ///
+    /// When the stream can't be connected or fails later on, for example because the node
+    /// does not exist or went away, the thread ends and passes the error to `on_error`.
+    pub fn new(
+        remote: Option<OwnedFd>,
+        node_id: u32,
+        rtc_source: NativeVideoSource,
+        on_error: impl FnOnce(StreamError) + Send + 'static,
+    ) -> Self {
+        let (pw_tx, pw_rx) = pw::channel::channel();
+
+        let thread = std::thread::spawn(move || {
+            if let Err(err) = stream_thread(remote, node_id, rtc_source, pw_rx) {
+                log::error!("video capture failed: {}", err);
+                on_error(err);
+            }
+        });
+
+        Self {
+            pw_tx,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for VideoStream {
+    fn drop(&mut self) {
+        let _ = self.pw_tx.send(PwCmd::Terminate);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn stream_thread(
+    remote: Option<OwnedFd>,
+    node_id: u32,
+    rtc_source: NativeVideoSource,
+    pw_rx: pw::channel::Receiver<PwCmd>,
+) -> Result<(), StreamError> {
+    let mainloop = pw::main_loop::MainLoopRc::new(None)?;
+    let context = pw::context::ContextRc::new(&mainloop, None)?;
+    let core = match remote {
+        Some(fd) => context.connect_fd_rc(fd, None)?,
+        None => context.connect_rc(None)?,
+    };
+
+    let props = properties! {
+        *pw::keys::MEDIA_TYPE => "Video",
+        *pw::keys::MEDIA_CATEGORY => "Capture",
+        *pw::keys::MEDIA_ROLE => "Screen",
+    };
+
+    let stream = pw::stream::StreamBox::new(&core, "verdant-screen-share", props)?;
+
+    let data = StreamData {
+        format: Default::default(),
+        rtc_source,
+        frame: None,
+    };
+
+    // Set when the stream fails, which ends the main loop
+    let failure = Rc::new(RefCell::new(None));
+
+    let _listener = stream
+        .add_local_listener_with_user_data(data)
+        .state_changed({
+            let mainloop = mainloop.clone();
+            let failure = failure.clone();
+            move |_, _, _, new| {
+                let err = match new {
+                    pw::stream::StreamState::Error(err) => err,
+                    // The node went away, for example when the shared window was closed
+                    pw::stream::StreamState::Unconnected => "disconnected".to_string(),
+                    _ => return,
+                };
+                *failure.borrow_mut() = Some(err);
+                mainloop.quit();
+            }
+        })
+        .param_changed(|_, data, id, param| {
+            let Some(param) = param else {
+                return;
+            };
+            if id != spa::param::ParamType::Format.as_raw() {
+                return;
+            }
+
+            if let Err(err) = data.format.parse(param) {
+                log::error!("failed to parse video format: {:?}", err);
+                return;
+            }
+
+            let size = data.format.size();
+            log::info!(
+                "screen share format: {:?} {}x{}",
+                data.format.format(),
+                size.width,
+                size.height
+            );
+            data.frame = Some(VideoFrame {
+                rotation: VideoRotation::VideoRotation0,
+                buffer: I420Buffer::new(size.width, size.height),
+                timestamp_us: 0,
+            });
+        })
+        .process(|stream, data| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+
+            let datas = buffer.datas_mut();
+            if datas.is_empty() {
+                return;
+            }
+            let Some(frame) = data.frame.as_mut() else {
+                return;
+            };
+
+            let size = data.format.size();
+            let chunk = datas[0].chunk();
+            let offset = chunk.offset() as usize;
+            let stride = match chunk.stride() {
+                stride if stride > 0 => stride as usize,
+                _ => size.width as usize * PIXEL_SIZE,
+            };
+
+            let Some(slice) = datas[0].data() else {
+                return;
+            };
+            let end = offset + stride * size.height as usize;
+            if slice.len() < end {
+                // Damage-only buffers carry no image, keep the last frame
+                return;
+            }
+
+            if convert_to_i420(
+                data.format.format(),
+                &slice[offset..end],
+                stride as u32,
+                &mut frame.buffer,
+                size.width as i32,
+                size.height as i32,
+            ) {
+                data.rtc_source.capture_frame(frame);
+            }
+        })
+        .register()?;
+
+    let values = video_format_param();
+    let mut params = [param_pod(&values)];
+
+    // Conversion is too slow for the realtime thread, frames are processed on the main loop
+    stream.connect(
+        spa::utils::Direction::Input,
+        Some(node_id),
+        pw::stream::StreamFlags::AUTOCONNECT | pw::stream::StreamFlags::MAP_BUFFERS,
+        &mut params,
+    )?;
+
+    let _receiver = pw_rx.attach(mainloop.loop_(), {
+        let mainloop = mainloop.clone();
+        move |cmd| match cmd {
+            PwCmd::Terminate => mainloop.quit(),
+        }
+    });
+
+    mainloop.run();
+    match failure.take() {
+        Some(err) => Err(StreamError::Stream(err)),
+        None => Ok(()),
+    }
+}
+
+/// Convert a packed frame into `buffer`, returns false for formats we did not ask for.
+fn convert_to_i420(
+    format: VideoFormat,
+    src: &[u8],
+    src_stride: u32,
+    buffer: &mut I420Buffer,
+    width: i32,
+    height: i32,
+) -> bool {
+    let (stride_y, stride_u, stride_v) = buffer.strides();
+    let (data_y, data_u, data_v) = buffer.data_mut();
+
+    // libyuv names formats by their little-endian word order, so BGRx bytes are "ARGB"
+    let convert = match format {
+        VideoFormat::BGRx | VideoFormat::BGRA => yuv_helper::argb_to_i420,
+        VideoFormat::RGBx | VideoFormat::RGBA => yuv_helper::abgr_to_i420,
+        _ => return false,
+    };
+
+    convert(
+        src, src_stride, data_y, stride_y, data_u, stride_u, data_v, stride_v, width, height,
+    );
+    true
+}
+
+/// Serialize an `EnumFormat` param accepting packed 32-bit RGB of any size and rate.
+fn video_format_param() -> Vec<u8> {
+    let obj = spa::pod::object!(
+        spa::utils::SpaTypes::ObjectParamFormat,
+        spa::param::ParamType::EnumFormat,
+        spa::pod::property!(
+            spa::param::format::FormatProperties::MediaType,
+            Id,
+            spa::param::format::MediaType::Video
+        ),
+        spa::pod::property!(
+            spa::param::format::FormatProperties::MediaSubtype,
+            Id,
+            spa::param::format::MediaSubtype::Raw
+        ),
+        spa::pod::property!(
+            spa::param::format::FormatProperties::VideoFormat,
+            Choice,
+            Enum,
+            Id,
+            VideoFormat::BGRx,
+            VideoFormat::BGRx,
+            VideoFormat::BGRA,
+            VideoFormat::RGBx,
+            VideoFormat::RGBA
+        ),
+        spa::pod::property!(
+            spa::param::format::FormatProperties::VideoSize,
+            Choice,
+            Range,
+            Rectangle,
+            spa::utils::Rectangle {
+                width: 1920,
+                height: 1080
+            },
+            spa::utils::Rectangle {
+                width: 1,
+                height: 1
+            },
+            spa::utils::Rectangle {
+                width: MAX_SIZE,
+                height: MAX_SIZE
+            }
+        ),
+        spa::pod::property!(
+            spa::param::format::FormatProperties::VideoFramerate,
+            Choice,
+            Range,
+            Fraction,
+            spa::utils::Fraction { num: 30, denom: 1 },
+            spa::utils::Fraction { num: 0, denom: 1 },
+            spa::utils::Fraction { num: 60, denom: 1 }
+        ),
+    );
+
+    spa::pod::serialize::PodSerializer::serialize(
+        std::io::Cursor::new(Vec::new()),
+        &spa::pod::Value::Object(obj),
+    )
+    .unwrap()
+    .0
+    .into_inner()
+}
@@ -0,0 +1,707 @@
+use livekit::webrtc::{
+    native::yuv_helper,
+    video_frame::{I420Buffer, VideoBuffer, VideoFrame, VideoRotation},
+    video_source::native::NativeVideoSource,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use xcb::{composite, randr, shm, x, Xid, XidNew};
+
+const PIXEL_SIZE: usize = 4;
+
+#[derive(Debug, Error)]
+pub enum X11Error {
+    #[error("X11 error: {0}")]
+    Xcb(#[from] xcb::Error),
+    #[error("failed to connect to the X server: {0}")]
+    Connection(#[from] xcb::ConnError),
+    #[error("failed to create shared memory segment: {0}")]
+    Shm(#[from] std::io::Error),
+    #[error("unsupported screen depth {0}, only 24 and 32 bit screens can be captured")]
+    Depth(u8),
+    #[error("the X server has no screen {0}")]
+    NoScreen(i32),
+    #[error("X11 capture thread exited during setup")]
+    Setup,
+}
+
+impl From<xcb::ProtocolError> for X11Error {
+    fn from(err: xcb::ProtocolError) -> Self {
+        X11Error::Xcb(err.into())
+    }
+}
+
+/// Part of the X11 screen to capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum X11Target {
+    /// The whole root window, spanning every monitor
+    Root,
+    /// A rectangle of the root window, as reported by RandR
+    Monitor {
+        x: i16,
+        y: i16,
+        width: u16,
+        height: u16,
+    },
+    /// A single top-level window, by resource id
+    Window(u32),
+}
+
+/// Something that can be captured, with a name to show in the picker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct X11Source {
+    pub name: String,
+    pub target: X11Target,
+}
+
+/// List the root window, the monitors and the top-level windows of the default display.
+pub fn list_sources() -> Result<Vec<X11Source>, X11Error> {
+    let (conn, screen_num) =
+        xcb::Connection::connect_with_extensions(None, &[], &[xcb::Extension::RandR])?;
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen_num as usize)
+        .ok_or(X11Error::NoScreen(screen_num))?
+        .root();
+
+    let mut sources = vec![X11Source {
+        name: "Whole screen".to_string(),
+        target: X11Target::Root,
+    }];
+
+    if conn
+        .active_extensions()
+        .any(|ext| ext == xcb::Extension::RandR)
+    {
+        let cookie = conn.send_request(&randr::GetMonitors {
+            window: root,
+            get_active: true,
+        });
+        for monitor in conn.wait_for_reply(cookie)?.monitors() {
+            let cookie = conn.send_request(&x::GetAtomName {
+                atom: monitor.name(),
+            });
+            let name = conn.wait_for_reply(cookie)?.name().to_utf8().into_owned();
+            sources.push(X11Source {
+                name: format!(
+                    "Monitor {} ({}x{})",
+                    name,
+                    monitor.width(),
+                    monitor.height()
+                ),
+                target: X11Target::Monitor {
+                    x: monitor.x(),
+                    y: monitor.y(),
+                    width: monitor.width(),
+                    height: monitor.height(),
+                },
+            });
+        }
+    }
+
+    for window in top_level_windows(&conn, root)? {
+        let title = window_title(&conn, window)?;
+        if title.is_empty() {
+            continue;
+        }
+        sources.push(X11Source {
+            name: title,
+            target: X11Target::Window(window.resource_id()),
+        });
+    }
+
+    Ok(sources)
+}
+
+/// Windows managed by the window manager, or the mapped children of the root window when
+/// there is none, as under a bare Xvfb.
+fn top_level_windows(conn: &xcb::Connection, root: x::Window) -> Result<Vec<x::Window>, X11Error> {
+    let client_list = intern_atom(conn, "_NET_CLIENT_LIST")?;
+    if client_list != x::ATOM_NONE {
+        let cookie = conn.send_request(&x::GetProperty {
+            delete: false,
+            window: root,
+            property: client_list,
+            r#type: x::ATOM_WINDOW,
+            long_offset: 0,
+            long_length: u32::MAX / 4,
+        });
+        let windows = conn.wait_for_reply(cookie)?.value::<x::Window>().to_vec();
+        if !windows.is_empty() {
+            return Ok(windows);
+        }
+    }
+
+    let cookie = conn.send_request(&x::QueryTree { window: root });
+    let children = conn.wait_for_reply(cookie)?.children().to_vec();
+    let mut windows = Vec::new();
+    for window in children {
+        let cookie = conn.send_request(&x::GetWindowAttributes { window });
+        let attributes = conn.wait_for_reply(cookie)?;
+        if attributes.map_state() == x::MapState::Viewable && !attributes.override_redirect() {
+            windows.push(window);
+        }
+    }
+    Ok(windows)
+}
+
+fn window_title(conn: &xcb::Connection, window: x::Window) -> Result<String, X11Error> {
+    let utf8_string = intern_atom(conn, "UTF8_STRING")?;
+    let net_wm_name = intern_atom(conn, "_NET_WM_NAME")?;
+
+    for (property, r#type) in [
+        (net_wm_name, utf8_string),
+        (x::ATOM_WM_NAME, x::ATOM_STRING),
+    ] {
+        if property == x::ATOM_NONE {
+            continue;
+        }
+        let cookie = conn.send_request(&x::GetProperty {
+            delete: false,
+            window,
+            property,
+            r#type,
+            long_offset: 0,
+            long_length: 1024,
+        });
+        let reply = conn.wait_for_reply(cookie)?;
+        let title = String::from_utf8_lossy(reply.value::<u8>());
+        if !title.is_empty() {
+            return Ok(title.into_owned());
+        }
+    }
+    Ok(String::new())
+}
+
+fn intern_atom(conn: &xcb::Connection, name: &str) -> Result<x::Atom, X11Error> {
+    let cookie = conn.send_request(&x::InternAtom {
+        only_if_exists: true,
+        name: name.as_bytes(),
+    });
+    Ok(conn.wait_for_reply(cookie)?.atom())
+}
+
+/// A System V shared memory segment attached to the X server with MIT-SHM.
+struct ShmImage {
+    seg: shm::Seg,
+    addr: *mut libc::c_void,
+    size: usize,
+}
+
+impl ShmImage {
+    fn new(conn: &xcb::Connection, size: usize) -> Result<Self, X11Error> {
+        let id = unsafe { libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600) };
+        if id < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let addr = unsafe { libc::shmat(id, std::ptr::null(), 0) };
+        if addr as isize == -1 {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut()) };
+            return Err(err.into());
+        }
+
+        let seg = conn.generate_id();
+        let res = conn.send_and_check_request(&shm::Attach {
+            shmseg: seg,
+            shmid: id as u32,
+            read_only: false,
+        });
+        // Both sides are attached now, the segment is freed once they detach
+        unsafe { libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut()) };
+        if let Err(err) = res {
+            unsafe { libc::shmdt(addr) };
+            return Err(err.into());
+        }
+
+        Ok(Self { seg, addr, size })
+    }
+
+    fn data(&self, len: usize) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.addr as *const u8, len.min(self.size)) }
+    }
+
+    fn detach(self, conn: &xcb::Connection) {
+        let _ = conn.send_and_check_request(&shm::Detach { shmseg: self.seg });
+        unsafe { libc::shmdt(self.addr) };
+    }
+}
+
+/// Captures part of an X11 screen at a fixed rate on its own thread.
+///
+/// Frames are read with MIT-SHM when the server supports it, which avoids copying them
+/// through the socket, and with a plain `GetImage` otherwise, as with remote displays.
+pub struct X11Capture {
+    stop: Arc<AtomicBool>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl X11Capture {
+    /// Connect to the default display and start capturing `target`, connection errors are
+    /// returned before the thread starts producing frames.
+    ///
+    /// `on_error` is called from the capture thread when capturing stops on its own, for
+    /// example because the window was closed or the X server went away.
+    pub fn new(
+        target: X11Target,
+        frame_rate: u32,
+        rtc_source: NativeVideoSource,
+        on_error: impl FnOnce(X11Error) + Send + 'static,
+    ) -> Result<Self, X11Error> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let thread = std::thread::spawn({
+            let stop = stop.clone();
+            move || {
+                if let Err(err) = capture_thread(target, frame_rate, rtc_source, stop, ready_tx) {
+                    log::error!("X11 capture failed: {}", err);
+                    on_error(err);
+                }
+            }
+        });
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self {
+                stop,
+                thread: Some(thread),
+            }),
+            Ok(Err(err)) => {
+                let _ = thread.join();
+                Err(err)
+            }
+            Err(_) => {
+                let _ = thread.join();
+                Err(X11Error::Setup)
+            }
+        }
+    }
+}
+
+impl Drop for X11Capture {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Capturer {
+    conn: xcb::Connection,
+    root: x::Window,
+    screen_size: (u16, u16),
+    shm: Option<ShmImage>,
+    composite: bool,
+}
+
+impl Capturer {
+    fn connect() -> Result<Self, X11Error> {
+        let (conn, screen_num) = xcb::Connection::connect_with_extensions(
+            None,
+            &[],
+            &[xcb::Extension::Shm, xcb::Extension::Composite],
+        )?;
+        let screen = conn
+            .get_setup()
+            .roots()
+            .nth(screen_num as usize)
+            .ok_or(X11Error::NoScreen(screen_num))?;
+        let root = screen.root();
+        let screen_size = (screen.width_in_pixels(), screen.height_in_pixels());
+        if screen.root_depth() != 24 && screen.root_depth() != 32 {
+            return Err(X11Error::Depth(screen.root_depth()));
+        }
+
+        // Windows are clipped to the screen, so a segment the size of the screen fits all
+        let shm = if conn
+            .active_extensions()
+            .any(|ext| ext == xcb::Extension::Shm)
+        {
+            let size = screen_size.0 as usize * screen_size.1 as usize * PIXEL_SIZE;
+            match ShmImage::new(&conn, size) {
+                Ok(shm) => Some(shm),
+                Err(err) => {
+                    log::warn!("MIT-SHM unavailable, capturing without it: {}", err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let composite = conn
+            .active_extensions()
+            .any(|ext| ext == xcb::Extension::Composite);
+
+        Ok(Self {
+            conn,
+            root,
+            screen_size,
+            shm,
+            composite,
+        })
+    }
+
+    /// Keep the contents of a shared window off screen with Composite, so reading it returns
+    /// the window itself rather than whatever covers it. The window is painted to the screen
+    /// as before and goes back to normal when we disconnect.
+    fn redirect(&self, target: X11Target) {
+        let X11Target::Window(id) = target else {
+            return;
+        };
+        if !self.composite {
+            log::warn!("no Composite extension, windows covering the shared one are captured");
+            return;
+        }
+        let window = unsafe { x::Window::new(id) };
+        let res = self
+            .conn
+            .send_and_check_request(&composite::RedirectWindow {
+                window,
+                update: composite::Redirect::Automatic,
+            });
+        if let Err(err) = res {
+            // A compositing manager already redirects every window, which is just as good
+            log::debug!("failed to redirect window {:#x}: {}", id, err);
+        }
+    }
+
+    /// Drawable and rectangle of it to read for `target`, `None` while nothing of it is on
+    /// screen.
+    ///
+    /// Windows are read from their own contents, limited to the part that is on screen:
+    /// reading a window that is partly off screen fails, and windows can be moved and resized
+    /// at any time.
+    fn area(&self, target: X11Target) -> Result<Option<(x::Drawable, Area)>, X11Error> {
+        let root = x::Drawable::Window(self.root);
+        match target {
+            X11Target::Root => Ok(Some((root, (0, 0, self.screen_size.0, self.screen_size.1)))),
+            X11Target::Monitor {
+                x,
+                y,
+                width,
+                height,
+            } => Ok(clip_to_screen(x, y, width, height, self.screen_size).map(|area| (root, area))),
+            X11Target::Window(id) => {
+                let window = unsafe { x::Window::new(id) };
+                let attributes = self.conn.send_request(&x::GetWindowAttributes { window });
+                let geometry = self.conn.send_request(&x::GetGeometry {
+                    drawable: x::Drawable::Window(window),
+                });
+                let position = self.conn.send_request(&x::TranslateCoordinates {
+                    src_window: window,
+                    dst_window: self.root,
+                    src_x: 0,
+                    src_y: 0,
+                });
+                let viewable =
+                    self.conn.wait_for_reply(attributes)?.map_state() == x::MapState::Viewable;
+                let geometry = self.conn.wait_for_reply(geometry)?;
+                let position = self.conn.wait_for_reply(position)?;
+                if !viewable {
+                    // Minimized or on another desktop, shared again once it is back
+                    return Ok(None);
+                }
+                let origin = (position.dst_x(), position.dst_y());
+                let area = clip_to_screen(
+                    origin.0,
+                    origin.1,
+                    geometry.width(),
+                    geometry.height(),
+                    self.screen_size,
+                );
+                Ok(area.map(|area| (x::Drawable::Window(window), to_window(area, origin))))
+            }
+        }
+    }
+
+    /// Read an area into `frame`, returns false when it has no pixels.
+    fn capture(
+        &self,
+        target: X11Target,
+        frame: &mut VideoFrame<I420Buffer>,
+    ) -> Result<bool, X11Error> {
+        let Some((drawable, (x, y, width, height))) = self.area(target)? else {
+            return Ok(false);
+        };
+        // I420 needs even dimensions
+        let (width, height) = (width & !1, height & !1);
+        if width == 0 || height == 0 {
+            return Ok(false);
+        }
+
+        if frame.buffer.width() != width as u32 || frame.buffer.height() != height as u32 {
+            frame.buffer = I420Buffer::new(width as u32, height as u32);
+        }
+
+        let len = width as usize * height as usize * PIXEL_SIZE;
+        match &self.shm {
+            Some(shm) => {
+                let cookie = self.conn.send_request(&shm::GetImage {
+                    drawable,
+                    x,
+                    y,
+                    width,
+                    height,
+                    plane_mask: u32::MAX,
+                    format: x::ImageFormat::ZPixmap as u8,
+                    shmseg: shm.seg,
+                    offset: 0,
+                });
+                self.conn.wait_for_reply(cookie)?;
+                convert_to_i420(shm.data(len), width, height, &mut frame.buffer);
+            }
+            None => {
+                let cookie = self.conn.send_request(&x::GetImage {
+                    format: x::ImageFormat::ZPixmap,
+                    drawable,
+                    x,
+                    y,
+                    width,
+                    height,
+                    plane_mask: u32::MAX,
+                });
+                let reply = self.conn.wait_for_reply(cookie)?;
+                convert_to_i420(reply.data(), width, height, &mut frame.buffer);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl Drop for Capturer {
+    fn drop(&mut self) {
+        if let Some(shm) = self.shm.take() {
+            shm.detach(&self.conn);
+        }
+    }
+}
+
+fn capture_thread(
+    target: X11Target,
+    frame_rate: u32,
+    rtc_source: NativeVideoSource,
+    stop: Arc<AtomicBool>,
+    ready_tx: mpsc::Sender<Result<(), X11Error>>,
+) -> Result<(), X11Error> {
+    let capturer = match Capturer::connect() {
+        Ok(capturer) => capturer,
+        Err(err) => {
+            let _ = ready_tx.send(Err(err));
+            return Ok(());
+        }
+    };
+    capturer.redirect(target);
+    let _ = ready_tx.send(Ok(()));
+
+    let mut frame = VideoFrame {
+        rotation: VideoRotation::VideoRotation0,
+        buffer: I420Buffer::new(2, 2),
+        timestamp_us: 0,
+    };
+    let interval = Duration::from_secs(1) / frame_rate.max(1);
+    let mut next = Instant::now();
+
+    while !stop.load(Ordering::Relaxed) {
+        // A closed window or a lost connection ends the capture with an error
+        if capturer.capture(target, &mut frame)? {
+            rtc_source.capture_frame(&frame);
+        }
+
+        next += interval;
+        let now = Instant::now();
+        if next > now {
+            std::thread::sleep(next - now);
+        } else {
+            // Too slow to keep up, skip frames rather than trying to catch up
+            next = now;
+        }
+    }
+    Ok(())
+}
+
+/// A rectangle as x, y, width and height.
+type Area = (i16, i16, u16, u16);
+
+/// The part of a rectangle that is on a screen of `screen_size`, `None` when it is all off.
+fn clip_to_screen(
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+    screen_size: (u16, u16),
+) -> Option<Area> {
+    let left = (x as i32).max(0);
+    let top = (y as i32).max(0);
+    let right = (x as i32 + width as i32).min(screen_size.0 as i32);
+    let bottom = (y as i32 + height as i32).min(screen_size.1 as i32);
+    if right <= left || bottom <= top {
+        return None;
+    }
+    Some((
+        left as i16,
+        top as i16,
+        (right - left) as u16,
+        (bottom - top) as u16,
+    ))
+}
+
+/// Move a rectangle of the root window into the coordinates of a window at `origin`.
+fn to_window((x, y, width, height): Area, origin: (i16, i16)) -> Area {
+    (x - origin.0, y - origin.1, width, height)
+}
+
+/// X11 ZPixmaps of 24 and 32 bit screens are BGRx in memory, which libyuv calls ARGB.
+fn convert_to_i420(src: &[u8], width: u16, height: u16, buffer: &mut I420Buffer) {
+    let (stride_y, stride_u, stride_v) = buffer.strides();
+    let (data_y, data_u, data_v) = buffer.data_mut();
+    yuv_helper::argb_to_i420(
+        src,
+        width as u32 * PIXEL_SIZE as u32,
+        data_y,
+        stride_y,
+        data_u,
+        stride_u,
+        data_v,
+        stride_v,
+        width as i32,
+        height as i32,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clips_areas_to_the_screen() {
+        let screen = (1920, 1080);
+        assert_eq!(
+            clip_to_screen(100, 50, 640, 480, screen),
+            Some((100, 50, 640, 480))
+        );
+        assert_eq!(
+            clip_to_screen(-100, -50, 640, 480, screen),
+            Some((0, 0, 540, 430))
+        );
+        assert_eq!(
+            clip_to_screen(1600, 900, 640, 480, screen),
+            Some((1600, 900, 320, 180))
+        );
+        assert_eq!(clip_to_screen(-700, 0, 640, 480, screen), None);
+        assert_eq!(clip_to_screen(1920, 0, 640, 480, screen), None);
+    }
+
+    /// Needs an X server, for example `Xvfb :99 -screen 0 1280x720x24 & DISPLAY=:99 cargo
+    /// test`. Skipped when `DISPLAY` is not set.
+    #[test]
+    fn captures_the_root_window() {
+        if std::env::var_os("DISPLAY").is_none() {
+            eprintln!("DISPLAY is not set, skipping");
+            return;
+        }
+
+        let sources = list_sources().unwrap();
+        assert_eq!(sources[0].target, X11Target::Root);
+
+        let capturer = Capturer::connect().unwrap();
+        let mut frame = VideoFrame {
+            rotation: VideoRotation::VideoRotation0,
+            buffer: I420Buffer::new(2, 2),
+            timestamp_us: 0,
+        };
+        assert!(capturer.capture(X11Target::Root, &mut frame).unwrap());
+
+        let (width, height) = capturer.screen_size;
+        assert_eq!(frame.buffer.width(), (width & !1) as u32);
+        assert_eq!(frame.buffer.height(), (height & !1) as u32);
+    }
+
+    fn create_window(
+        conn: &xcb::Connection,
+        root: x::Window,
+        x: i16,
+        y: i16,
+        color: u32,
+    ) -> x::Window {
+        let window = conn.generate_id();
+        conn.send_and_check_request(&x::CreateWindow {
+            depth: x::COPY_FROM_PARENT as u8,
+            wid: window,
+            parent: root,
+            x,
+            y,
+            width: 200,
+            height: 200,
+            border_width: 0,
+            class: x::WindowClass::InputOutput,
+            visual: x::COPY_FROM_PARENT,
+            value_list: &[x::Cw::BackPixel(color)],
+        })
+        .unwrap();
+        window
+    }
+
+    /// Needs an X server with Composite, which Xvfb has. Skipped when `DISPLAY` is not set.
+    #[test]
+    fn captures_a_window_under_another() {
+        if std::env::var_os("DISPLAY").is_none() {
+            eprintln!("DISPLAY is not set, skipping");
+            return;
+        }
+
+        let (conn, screen_num) = xcb::Connection::connect(None).unwrap();
+        let root = conn
+            .get_setup()
+            .roots()
+            .nth(screen_num as usize)
+            .unwrap()
+            .root();
+        let shared = create_window(&conn, root, 0, 0, 0xff0000);
+        let covering = create_window(&conn, root, 100, 100, 0x0000ff);
+        let target = X11Target::Window(shared.resource_id());
+
+        let capturer = Capturer::connect().unwrap();
+        capturer.redirect(target);
+        // Mapped once redirected, so the shared window is painted off screen from the start
+        for window in [shared, covering] {
+            conn.send_and_check_request(&x::MapWindow { window })
+                .unwrap();
+        }
+
+        let mut frame = VideoFrame {
+            rotation: VideoRotation::VideoRotation0,
+            buffer: I420Buffer::new(2, 2),
+            timestamp_us: 0,
+        };
+        assert!(capturer.capture(target, &mut frame).unwrap());
+        assert_eq!((frame.buffer.width(), frame.buffer.height()), (200, 200));
+
+        // Red has a high V and a low U, blue the other way around. The second point is under
+        // the covering window.
+        let (_, data_u, data_v) = frame.buffer.data();
+        let (_, stride_u, stride_v) = frame.buffer.strides();
+        for (x, y) in [(10, 10), (90, 90)] {
+            let u = data_u[y * stride_u as usize + x];
+            let v = data_v[y * stride_v as usize + x];
+            assert!(
+                u < 128 && v > 200,
+                "chroma at ({}, {}) is not red: {} {}",
+                x,
+                y,
+                u,
+                v
+            );
+        }
+
+        for window in [shared, covering] {
+            conn.send_and_check_request(&x::DestroyWindow { window })
+                .unwrap();
+        }
+    }
+}
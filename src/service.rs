@@ -1,8 +1,17 @@
 use crate::{
-    logo_track::LogoTrack,
-    sine_track::{SineParameters, SineTrack},
+    audio::{
+        AudioFile, AudioLevel, AudioProcessing, FileGenerator, GeneratedTrack, LevelMeter,
+        MicrophoneParameters, MicrophoneTrack, NUM_CHANNELS, SAMPLE_RATE,
+    },
+    camera::{CameraSettings, CameraTrack},
+    latency_probe::{LatencyProbe, LatencyReport, LatencyStats},
+    publish_profile::PublishProfiles,
+    screen_share::{ScreenShareSource, ScreenShareTrack},
+    signal_track::{SignalParameters, SignalTrack},
+    test_pattern::{TestPatternSettings, TestPatternTrack},
+    video_file::VideoFileTrack,
+    video_processing::ProcessingSettings,
 };
-use livekit::options::TrackPublishOptions;
 use livekit::webrtc::video_source::native::NativeVideoSource;
 use livekit::{
     e2ee::{key_provider::*, E2eeOptions, EncryptionType},
@@ -11,29 +20,108 @@ use livekit::{
     webrtc::prelude::*,
     SimulateScenario,
 };
-use livekit_sources::create_local_track;
-use livekit_sources::nokhwa::NokhwaSource;
 use parking_lot::Mutex;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::runtime::Handle;
 use tokio::sync::mpsc::{self, error::SendError};
 
+/// Data channel topic used to tell other participants that we started or stopped recording.
+pub const RECORDING_TOPIC: &str = "recording";
+/// Data channel topic of chat messages, shared with the LiveKit web components.
+pub const CHAT_TOPIC: &str = "lk-chat-topic";
+
 #[derive(Debug)]
 pub enum AsyncCmd {
-    PublishCamera,
+    PublishCamera {
+        camera: CameraSettings,
+    },
+    UnpublishCamera,
+    ToggleCamera {
+        camera: CameraSettings,
+    },
+    SetCameraMuted {
+        muted: bool,
+    },
+    /// Applies to the live camera right away
+    SetCameraProcessing {
+        processing: ProcessingSettings,
+    },
+    /// Learn the room behind the camera again, the user should be out of the frame
+    CaptureCameraBackground,
+    PublishScreenShare {
+        source: ScreenShareSource,
+        /// also share what other applications play
+        system_audio: bool,
+    },
+    UnpublishScreenShare,
+    /// Sent by the screen share when capturing stopped on its own
+    ScreenShareFailed {
+        error: String,
+    },
+    /// Sent by the camera when capturing stopped on its own
+    CameraFailed {
+        error: String,
+    },
+    /// Sent by the video file when reading it failed during playback
+    VideoFileFailed {
+        error: String,
+    },
     RoomConnect {
         url: String,
         token: String,
         auto_subscribe: bool,
         enable_e2ee: bool,
         key: String,
+        audio_processing: AudioProcessing,
+        signal: SignalParameters,
+        test_pattern: TestPatternSettings,
+        publish_profiles: PublishProfiles,
+        camera_processing: ProcessingSettings,
+        /// Camera to publish once connected
+        publish_camera: Option<CameraSettings>,
+        publish_microphone: bool,
     },
     RoomDisconnect,
     SimulateScenario {
         scenario: SimulateScenario,
     },
-    ToggleLogo,
-    ToggleSine,
+    ToggleTestPattern,
+    SetTestPattern {
+        settings: TestPatternSettings,
+    },
+    ToggleSignal,
+    SetSignalParameters {
+        params: SignalParameters,
+    },
+    ToggleMicrophone,
+    PublishAudioFile {
+        path: PathBuf,
+        looping: bool,
+    },
+    UnpublishAudioFile,
+    /// Sent by the file track once a file that doesn't loop was played to the end
+    AudioFileEnded,
+    /// Publish a Y4M file or a directory of images
+    PublishVideoFile {
+        path: PathBuf,
+        looping: bool,
+        image_fps: u32,
+    },
+    UnpublishVideoFile,
+    SetAudioInput {
+        target: Option<String>,
+    },
+    SetAudioProcessing {
+        processing: AudioProcessing,
+    },
+    /// Applies to video published from now on
+    SetPublishProfiles {
+        profiles: PublishProfiles,
+    },
+    SetMicrophoneMuted {
+        muted: bool,
+    },
     SubscribeTrack {
         publication: RemoteTrackPublication,
     },
@@ -44,14 +132,49 @@ pub enum AsyncCmd {
         publication: RemoteTrackPublication,
         quality: VideoQuality,
     },
+    SendRecordingNotice {
+        recording: bool,
+    },
     E2eeKeyRatchet,
     LogStats,
+    /// Join as a second participant with `token` and measure latency to it, answered with
+    /// [`UiCmd::LatencyProbeStarted`]
+    StartLatencyProbe {
+        url: String,
+        token: String,
+        enable_e2ee: bool,
+        key: String,
+    },
+    StopLatencyProbe,
+}
+
+/// A local source as the UI names it, several publish with the same [`TrackSource`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalSource {
+    Microphone,
+    Camera,
+    Screenshare,
+    ScreenshareAudio,
+    VideoFile,
+    TestPattern,
 }
 
 #[derive(Debug)]
 pub enum UiCmd {
-    ConnectResult { result: RoomResult<()> },
-    RoomEvent { event: RoomEvent },
+    ConnectResult {
+        result: RoomResult<()>,
+    },
+    RoomEvent {
+        event: RoomEvent,
+    },
+    /// Publishing or unpublishing a local source failed
+    PublishFailed {
+        source: LocalSource,
+        error: String,
+    },
+    LatencyProbeStarted {
+        result: Result<(), String>,
+    },
 }
 
 /// AppService is the "asynchronous" part of our application, where we connect to a room and
@@ -67,6 +190,18 @@ pub struct LkService {
 struct ServiceInner {
     ui_tx: mpsc::UnboundedSender<UiCmd>,
     room: Mutex<Option<Arc<Room>>>,
+    microphone_meter: LevelMeter,
+    latency_stats: LatencyStats,
+}
+
+impl ServiceInner {
+    fn publish_failed(&self, source: LocalSource, err: impl std::fmt::Display) {
+        log::error!("failed to publish {:?}: {}", source, err);
+        let _ = self.ui_tx.send(UiCmd::PublishFailed {
+            source,
+            error: err.to_string(),
+        });
+    }
 }
 
 impl LkService {
@@ -78,8 +213,11 @@ impl LkService {
         let inner = Arc::new(ServiceInner {
             ui_tx,
             room: Default::default(),
+            microphone_meter: LevelMeter::new(),
+            latency_stats: LatencyStats::new(),
         });
-        let handle = async_handle.spawn(service_task(inner.clone(), cmd_rx, async_handle.clone()));
+        // Weak so dropping `cmd_tx` still ends the service task
+        let handle = async_handle.spawn(service_task(inner.clone(), cmd_tx.downgrade(), cmd_rx));
 
         Self {
             cmd_tx,
@@ -94,6 +232,16 @@ impl LkService {
         self.inner.room.lock().clone()
     }
 
+    /// Level of the published microphone, before it is muted.
+    pub fn microphone_level(&self) -> AudioLevel {
+        self.inner.microphone_meter.level()
+    }
+
+    /// Latencies measured by the running or last latency probe.
+    pub fn latency_report(&self) -> LatencyReport {
+        self.inner.latency_stats.report()
+    }
+
     pub fn send(&self, cmd: AsyncCmd) -> Result<(), SendError<AsyncCmd>> {
         self.cmd_tx.send(cmd)
     }
@@ -111,16 +259,25 @@ impl LkService {
 
 async fn service_task(
     inner: Arc<ServiceInner>,
+    cmd_tx: mpsc::WeakUnboundedSender<AsyncCmd>,
     mut cmd_rx: mpsc::UnboundedReceiver<AsyncCmd>,
-    handle: Handle,
 ) {
     struct RunningState {
         room: Arc<Room>,
-        logo_track: LogoTrack,
-        sine_track: SineTrack,
+        test_pattern: TestPatternTrack,
+        camera_track: CameraTrack,
+        screen_share: ScreenShareTrack,
+        signal_track: SignalTrack,
+        file_track: GeneratedTrack,
+        video_file: VideoFileTrack,
+        microphone_track: MicrophoneTrack,
+        latency_probe: Option<LatencyProbe>,
     }
 
     let mut running_state = None;
+    // Kept outside of the room so the choice survives reconnects
+    let mut audio_input = None;
+    let mut profiles = PublishProfiles::default();
 
     while let Some(event) = cmd_rx.recv().await {
         match event {
@@ -130,20 +287,18 @@ async fn service_task(
                 auto_subscribe,
                 enable_e2ee,
                 key,
+                audio_processing,
+                signal,
+                test_pattern,
+                publish_profiles,
+                camera_processing,
+                publish_camera,
+                publish_microphone,
             } => {
                 log::info!("connecting to room: {}", url);
+                profiles = publish_profiles;
 
-                let key_provider =
-                    KeyProvider::with_shared_key(KeyProviderOptions::default(), key.into_bytes());
-                let e2ee = enable_e2ee.then_some(E2eeOptions {
-                    encryption_type: EncryptionType::Gcm,
-                    key_provider,
-                });
-
-                let mut options = RoomOptions::default();
-                options.auto_subscribe = auto_subscribe;
-                options.e2ee = e2ee;
-
+                let options = room_options(auto_subscribe, enable_e2ee, key);
                 let res = Room::connect(&url, &token, options).await;
 
                 if let Ok((new_room, events)) = res {
@@ -153,8 +308,57 @@ async fn service_task(
                     let new_room = Arc::new(new_room);
                     running_state = Some(RunningState {
                         room: new_room.clone(),
-                        logo_track: LogoTrack::new(new_room.clone()),
-                        sine_track: SineTrack::new(new_room.clone(), SineParameters::default()),
+                        test_pattern: TestPatternTrack::new(new_room.clone(), test_pattern),
+                        camera_track: CameraTrack::new(new_room.clone(), camera_processing)
+                            .on_failed({
+                                let cmd_tx = cmd_tx.clone();
+                                move |error| {
+                                    if let Some(cmd_tx) = cmd_tx.upgrade() {
+                                        let _ = cmd_tx.send(AsyncCmd::CameraFailed { error });
+                                    }
+                                }
+                            }),
+                        screen_share: ScreenShareTrack::new(new_room.clone()).on_failed({
+                            let cmd_tx = cmd_tx.clone();
+                            move |error| {
+                                if let Some(cmd_tx) = cmd_tx.upgrade() {
+                                    let _ = cmd_tx.send(AsyncCmd::ScreenShareFailed { error });
+                                }
+                            }
+                        }),
+                        signal_track: SignalTrack::new(new_room.clone(), signal, audio_processing),
+                        file_track: GeneratedTrack::new(
+                            new_room.clone(),
+                            SAMPLE_RATE,
+                            NUM_CHANNELS,
+                            audio_processing,
+                        )
+                        .on_ended({
+                            let cmd_tx = cmd_tx.clone();
+                            move || {
+                                if let Some(cmd_tx) = cmd_tx.upgrade() {
+                                    let _ = cmd_tx.send(AsyncCmd::AudioFileEnded);
+                                }
+                            }
+                        }),
+                        video_file: VideoFileTrack::new(new_room.clone()).on_failed({
+                            let cmd_tx = cmd_tx.clone();
+                            move |error| {
+                                if let Some(cmd_tx) = cmd_tx.upgrade() {
+                                    let _ = cmd_tx.send(AsyncCmd::VideoFileFailed { error });
+                                }
+                            }
+                        }),
+                        microphone_track: MicrophoneTrack::new(
+                            new_room.clone(),
+                            MicrophoneParameters {
+                                target: audio_input.clone(),
+                                ..Default::default()
+                            },
+                            audio_processing,
+                            inner.microphone_meter.clone(),
+                        ),
+                        latency_probe: None,
                     });
 
                     println!("joined room: {}", new_room.name());
@@ -162,13 +366,32 @@ async fn service_task(
                     // Allow direct access to the room from the UI (Used for sync access)
                     inner.room.lock().replace(new_room);
                     let _ = inner.ui_tx.send(UiCmd::ConnectResult { result: Ok(()) });
+
+                    // Picked in the lobby, or everything with auto publish on reconnect
+                    let state = running_state.as_mut().unwrap();
+                    if publish_microphone {
+                        if let Err(err) = state.microphone_track.publish().await {
+                            inner.publish_failed(LocalSource::Microphone, err);
+                        }
+                    }
+                    if let Some(camera) = publish_camera {
+                        if let Err(err) = state.camera_track.publish(&camera, profiles.camera).await
+                        {
+                            inner.publish_failed(LocalSource::Camera, err);
+                        }
+                    }
                 } else if let Err(err) = res {
                     log::error!("failed to connect to room: {:?}", err);
                     let _ = inner.ui_tx.send(UiCmd::ConnectResult { result: Err(err) });
                 }
             }
             AsyncCmd::RoomDisconnect => {
-                if let Some(state) = running_state.take() {
+                if let Some(mut state) = running_state.take() {
+                    if let Some(probe) = state.latency_probe.take() {
+                        let _ = probe.stop().await;
+                    }
+                    // Ends the portal session, which would otherwise keep the compositor casting
+                    let _ = state.screen_share.unpublish().await;
                     *inner.room.lock() = None;
                     if let Err(err) = state.room.close().await {
                         log::error!("failed to disconnect from room: {:?}", err);
@@ -182,28 +405,241 @@ async fn service_task(
                     }
                 }
             }
-            AsyncCmd::PublishCamera => {
+            AsyncCmd::PublishCamera { camera } => {
                 if let Some(state) = running_state.as_mut() {
-                    publish_camera(handle.clone(), &state.room.clone())
-                        .await
-                        .unwrap()
+                    if let Err(err) = state.camera_track.publish(&camera, profiles.camera).await {
+                        inner.publish_failed(LocalSource::Camera, err);
+                    }
                 }
             }
-            AsyncCmd::ToggleLogo => {
+            AsyncCmd::UnpublishCamera => {
                 if let Some(state) = running_state.as_mut() {
-                    if state.logo_track.is_published() {
-                        state.logo_track.unpublish().await.unwrap();
+                    if let Err(err) = state.camera_track.unpublish().await {
+                        inner.publish_failed(LocalSource::Camera, err);
+                    }
+                }
+            }
+            AsyncCmd::ToggleCamera { camera } => {
+                if let Some(state) = running_state.as_mut() {
+                    let res = if state.camera_track.is_published() {
+                        state.camera_track.unpublish().await
                     } else {
-                        state.logo_track.publish().await.unwrap();
+                        state.camera_track.publish(&camera, profiles.camera).await
+                    };
+                    if let Err(err) = res {
+                        inner.publish_failed(LocalSource::Camera, err);
                     }
                 }
             }
-            AsyncCmd::ToggleSine => {
+            AsyncCmd::SetCameraMuted { muted } => {
+                if let Some(state) = running_state.as_ref() {
+                    state.camera_track.set_muted(muted);
+                }
+            }
+            AsyncCmd::SetCameraProcessing { processing } => {
                 if let Some(state) = running_state.as_mut() {
-                    if state.sine_track.is_published() {
-                        state.sine_track.unpublish().await.unwrap();
+                    state.camera_track.set_processing(processing);
+                }
+            }
+            AsyncCmd::CaptureCameraBackground => {
+                if let Some(state) = running_state.as_ref() {
+                    state.camera_track.capture_background();
+                }
+            }
+            AsyncCmd::PublishScreenShare {
+                source,
+                system_audio,
+            } => {
+                if let Some(state) = running_state.as_mut() {
+                    let res = state
+                        .screen_share
+                        .publish(source, profiles.screen_share)
+                        .await;
+                    if let Err(err) = res {
+                        inner.publish_failed(LocalSource::Screenshare, err);
+                    } else if system_audio {
+                        // The screen stays shared when only the audio fails
+                        if let Err(err) = state.screen_share.publish_audio().await {
+                            inner.publish_failed(LocalSource::ScreenshareAudio, err);
+                        }
+                    }
+                }
+            }
+            AsyncCmd::UnpublishScreenShare => {
+                if let Some(state) = running_state.as_mut() {
+                    if let Err(err) = state.screen_share.unpublish().await {
+                        inner.publish_failed(LocalSource::Screenshare, err);
+                    }
+                }
+            }
+            AsyncCmd::ScreenShareFailed { error } => {
+                if let Some(state) = running_state.as_mut() {
+                    // Nothing to do when the failed share was already replaced
+                    if state.screen_share.has_failed() {
+                        if let Err(err) = state.screen_share.unpublish().await {
+                            log::error!("failed to unpublish screen share: {:?}", err);
+                        }
+                        inner.publish_failed(LocalSource::Screenshare, error);
+                    }
+                }
+            }
+            AsyncCmd::CameraFailed { error } => {
+                if let Some(state) = running_state.as_mut() {
+                    // Nothing to do when the failed camera was already replaced
+                    if state.camera_track.has_failed() {
+                        if let Err(err) = state.camera_track.unpublish().await {
+                            log::error!("failed to unpublish camera: {:?}", err);
+                        }
+                        inner.publish_failed(LocalSource::Camera, error);
+                    }
+                }
+            }
+            AsyncCmd::ToggleTestPattern => {
+                if let Some(state) = running_state.as_mut() {
+                    let res = if state.test_pattern.is_published() {
+                        state.test_pattern.unpublish().await
                     } else {
-                        state.sine_track.publish().await.unwrap();
+                        state.test_pattern.publish(profiles.test_pattern).await
+                    };
+                    if let Err(err) = res {
+                        inner.publish_failed(LocalSource::TestPattern, err);
+                    }
+                }
+            }
+            AsyncCmd::SetTestPattern { settings } => {
+                if let Some(state) = running_state.as_mut() {
+                    state.test_pattern.set_settings(settings);
+                }
+            }
+            AsyncCmd::ToggleSignal => {
+                if let Some(state) = running_state.as_mut() {
+                    let res = if state.signal_track.is_published() {
+                        state.signal_track.unpublish().await
+                    } else {
+                        state.signal_track.publish().await
+                    };
+                    if let Err(err) = res {
+                        inner.publish_failed(LocalSource::Microphone, err);
+                    }
+                }
+            }
+            AsyncCmd::SetSignalParameters { params } => {
+                if let Some(state) = running_state.as_mut() {
+                    state.signal_track.set_params(params);
+                }
+            }
+            AsyncCmd::ToggleMicrophone => {
+                if let Some(state) = running_state.as_mut() {
+                    let res = if state.microphone_track.is_published() {
+                        state.microphone_track.unpublish().await
+                    } else {
+                        state.microphone_track.publish().await
+                    };
+                    if let Err(err) = res {
+                        inner.publish_failed(LocalSource::Microphone, err);
+                    }
+                }
+            }
+            AsyncCmd::PublishAudioFile { path, looping } => {
+                if let Some(state) = running_state.as_mut() {
+                    let sample_rate = state.file_track.sample_rate();
+                    let file =
+                        tokio::task::spawn_blocking(move || AudioFile::open(&path, sample_rate))
+                            .await;
+
+                    let res = match file {
+                        Ok(Ok(file)) => {
+                            let generator = FileGenerator::new(file, looping);
+                            state
+                                .file_track
+                                .publish("audio_file", Box::new(generator))
+                                .await
+                                .map_err(|err| err.to_string())
+                        }
+                        Ok(Err(err)) => Err(err.to_string()),
+                        Err(err) => Err(format!("failed to open audio file: {}", err)),
+                    };
+                    if let Err(err) = res {
+                        inner.publish_failed(LocalSource::Microphone, err);
+                    }
+                }
+            }
+            AsyncCmd::UnpublishAudioFile => {
+                if let Some(state) = running_state.as_mut() {
+                    if let Err(err) = state.file_track.unpublish().await {
+                        inner.publish_failed(LocalSource::Microphone, err);
+                    }
+                }
+            }
+            AsyncCmd::AudioFileEnded => {
+                if let Some(state) = running_state.as_mut() {
+                    if let Err(err) = state.file_track.unpublish_ended().await {
+                        log::error!("failed to unpublish audio file: {:?}", err);
+                    }
+                }
+            }
+            AsyncCmd::PublishVideoFile {
+                path,
+                looping,
+                image_fps,
+            } => {
+                if let Some(state) = running_state.as_mut() {
+                    let res = state
+                        .video_file
+                        .publish(path, looping, image_fps, profiles.video_file)
+                        .await;
+                    if let Err(err) = res {
+                        inner.publish_failed(LocalSource::VideoFile, err);
+                    }
+                }
+            }
+            AsyncCmd::UnpublishVideoFile => {
+                if let Some(state) = running_state.as_mut() {
+                    if let Err(err) = state.video_file.unpublish().await {
+                        inner.publish_failed(LocalSource::VideoFile, err);
+                    }
+                }
+            }
+            AsyncCmd::VideoFileFailed { error } => {
+                if let Some(state) = running_state.as_mut() {
+                    // Nothing to do when the failed video was already replaced
+                    if state.video_file.has_failed() {
+                        if let Err(err) = state.video_file.unpublish().await {
+                            log::error!("failed to unpublish video file: {:?}", err);
+                        }
+                        inner.publish_failed(LocalSource::VideoFile, error);
+                    }
+                }
+            }
+            AsyncCmd::SetAudioInput { target } => {
+                if let Some(state) = running_state.as_mut() {
+                    state.microphone_track.set_target(target.clone());
+                }
+                audio_input = target;
+            }
+            AsyncCmd::SetAudioProcessing { processing } => {
+                if let Some(state) = running_state.as_mut() {
+                    state.signal_track.set_processing(processing);
+                    state.file_track.set_processing(processing);
+                    state.microphone_track.set_processing(processing);
+                }
+            }
+            AsyncCmd::SetPublishProfiles {
+                profiles: new_profiles,
+            } => {
+                profiles = new_profiles;
+            }
+            AsyncCmd::SetMicrophoneMuted { muted } => {
+                if let Some(state) = running_state.as_ref() {
+                    for (_, publication) in state.room.local_participant().track_publications() {
+                        if publication.source() != TrackSource::Microphone {
+                            continue;
+                        }
+                        if muted {
+                            publication.mute();
+                        } else {
+                            publication.unmute();
+                        }
                     }
                 }
             }
@@ -219,6 +655,64 @@ async fn service_task(
             } => {
                 publication.set_video_quality(quality);
             }
+            AsyncCmd::SendRecordingNotice { recording } => {
+                if let Some(state) = running_state.as_ref() {
+                    let payload = if recording { "started" } else { "stopped" };
+                    let packet = DataPacket {
+                        payload: payload.as_bytes().to_vec(),
+                        topic: Some(RECORDING_TOPIC.to_string()),
+                        reliable: true,
+                        ..Default::default()
+                    };
+                    if let Err(err) = state.room.local_participant().publish_data(packet).await {
+                        log::error!("failed to send recording notice: {:?}", err);
+                    }
+                }
+            }
+            AsyncCmd::StartLatencyProbe {
+                url,
+                token,
+                enable_e2ee,
+                key,
+            } => {
+                if let Some(state) = running_state.as_mut() {
+                    if let Some(probe) = state.latency_probe.take() {
+                        let _ = probe.stop().await;
+                    }
+
+                    let options = room_options(false, enable_e2ee, key);
+                    let result = match LatencyProbe::start(
+                        state.room.clone(),
+                        &url,
+                        &token,
+                        options,
+                        inner.latency_stats.clone(),
+                    )
+                    .await
+                    {
+                        Ok(probe) => {
+                            state.latency_probe = Some(probe);
+                            Ok(())
+                        }
+                        Err(err) => {
+                            log::error!("failed to start latency probe: {:?}", err);
+                            Err(err.to_string())
+                        }
+                    };
+                    let _ = inner.ui_tx.send(UiCmd::LatencyProbeStarted { result });
+                } else {
+                    let _ = inner.ui_tx.send(UiCmd::LatencyProbeStarted {
+                        result: Err("not connected to a room".to_string()),
+                    });
+                }
+            }
+            AsyncCmd::StopLatencyProbe => {
+                if let Some(probe) = running_state.as_mut().and_then(|s| s.latency_probe.take()) {
+                    if let Err(err) = probe.stop().await {
+                        log::error!("failed to stop latency probe: {:?}", err);
+                    }
+                }
+            }
             AsyncCmd::E2eeKeyRatchet => {
                 if let Some(state) = running_state.as_ref() {
                     let e2ee_manager = state.room.e2ee_manager();
@@ -257,25 +751,18 @@ async fn service_task(
     }
 }
 
-async fn publish_camera(
-    handle: Handle,
-    room: &Arc<Room>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let nokhwa = NokhwaSource::default();
-    let (local_track, handle) = create_local_track(nokhwa, handle, room.clone())
-        .await
-        .unwrap();
-
-    // Publish it to the room
-    room.local_participant()
-        .publish_track(
-            LocalTrack::Video(local_track),
-            TrackPublishOptions::default(),
-        )
-        .await?;
+fn room_options(auto_subscribe: bool, enable_e2ee: bool, key: String) -> RoomOptions {
+    let key_provider =
+        KeyProvider::with_shared_key(KeyProviderOptions::default(), key.into_bytes());
+    let e2ee = enable_e2ee.then_some(E2eeOptions {
+        encryption_type: EncryptionType::Gcm,
+        key_provider,
+    });
 
-    println!("📹 Camera stream published!");
-    Ok(())
+    let mut options = RoomOptions::default();
+    options.auto_subscribe = auto_subscribe;
+    options.e2ee = e2ee;
+    options
 }
 
 /// Task basically used to forward room events to the UI.
@@ -0,0 +1,319 @@
+use crate::audio::{AudioGenerator, AudioProcessing, GeneratedTrack};
+use livekit::prelude::*;
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+// Each channel of the identification signal beeps for this long, then stays quiet for as long
+const CHANNEL_ID_BEEP_SECONDS: f64 = 0.5;
+
+/// Waveform produced by the [`SignalTrack`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Signal {
+    /// Steady tone at [`SignalParameters::freq`].
+    Sine,
+    /// Tone gliding from `start` to `end` Hz over `seconds`, then starting over.
+    Sweep {
+        start: f64,
+        end: f64,
+        seconds: f64,
+        logarithmic: bool,
+    },
+    WhiteNoise,
+    PinkNoise,
+    /// Dials `digits` (0-9, *, #, A-D) in a loop, other characters are played as silence.
+    Dtmf {
+        digits: String,
+        tone_ms: u32,
+        gap_ms: u32,
+    },
+    /// Beeps on one channel at a time, channel `n` at `(n + 1) * freq` Hz, so each output
+    /// can be told apart by ear.
+    ChannelId,
+}
+
+impl Signal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::Sine => "Sine",
+            Signal::Sweep { .. } => "Sweep",
+            Signal::WhiteNoise => "White noise",
+            Signal::PinkNoise => "Pink noise",
+            Signal::Dtmf { .. } => "DTMF",
+            Signal::ChannelId => "Channel identification",
+        }
+    }
+
+    /// One of each signal with sensible defaults, in the order they are offered in the UI.
+    pub fn all() -> [Signal; 6] {
+        [
+            Signal::Sine,
+            Signal::Sweep {
+                start: 20.0,
+                end: 20000.0,
+                seconds: 10.0,
+                logarithmic: true,
+            },
+            Signal::WhiteNoise,
+            Signal::PinkNoise,
+            Signal::Dtmf {
+                digits: "0123456789*#".to_string(),
+                tone_ms: 100,
+                gap_ms: 100,
+            },
+            Signal::ChannelId,
+        ]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignalParameters {
+    pub signal: Signal,
+    pub sample_rate: u32,
+    pub freq: f64,
+    pub amplitude: f64,
+    pub num_channels: u32,
+}
+
+impl Default for SignalParameters {
+    fn default() -> Self {
+        Self {
+            signal: Signal::Sine,
+            sample_rate: 48000,
+            freq: 440.0,
+            amplitude: 1.0,
+            num_channels: 2,
+        }
+    }
+}
+
+fn dtmf_freqs(digit: char) -> Option<(f64, f64)> {
+    const ROWS: [f64; 4] = [697.0, 770.0, 852.0, 941.0];
+    const COLS: [f64; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+    const KEYS: [&str; 4] = ["123A", "456B", "789C", "*0#D"];
+
+    let digit = digit.to_ascii_uppercase();
+    KEYS.iter().enumerate().find_map(|(row, keys)| {
+        keys.chars()
+            .position(|key| key == digit)
+            .map(|col| (ROWS[row], COLS[col]))
+    })
+}
+
+/// Renders [`SignalParameters`] shared with the [`SignalTrack`], so changes are heard on the
+/// next 10ms frame.
+pub struct SignalGenerator {
+    params: Arc<Mutex<SignalParameters>>,
+    signal: Signal,
+    /// tone pair of each DTMF digit, worked out when the signal changes
+    dtmf: Vec<Option<(f64, f64)>>,
+    /// samples since the current signal was selected, drives sweeps and sequences
+    position: u64,
+    phase: [f64; 2],
+    pink: [f64; 7],
+    rng: StdRng,
+}
+
+impl SignalGenerator {
+    pub fn new(params: Arc<Mutex<SignalParameters>>) -> Self {
+        let signal = params.lock().signal.clone();
+        Self {
+            params,
+            dtmf: Self::dtmf_sequence(&signal),
+            signal,
+            position: 0,
+            phase: [0.0; 2],
+            pink: [0.0; 7],
+            rng: StdRng::from_os_rng(),
+        }
+    }
+
+    fn dtmf_sequence(signal: &Signal) -> Vec<Option<(f64, f64)>> {
+        match signal {
+            Signal::Dtmf { digits, .. } => digits.chars().map(dtmf_freqs).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn tone(phase: &mut f64, freq: f64, sample_rate: f64) -> f64 {
+        let val = f64::sin(*phase);
+        *phase = (*phase + 2.0 * PI * freq / sample_rate) % (2.0 * PI);
+        val
+    }
+
+    // Paul Kellet's refined pink noise filter, accurate to 0.05dB above 9.2Hz at 44.1kHz
+    fn pink_noise(&mut self) -> f64 {
+        let white: f64 = self.rng.random_range(-1.0..1.0);
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.1538520;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let val = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        // The filter has a gain of roughly 9dB
+        val * 0.11
+    }
+
+    /// Value of every channel for the next sample, channels share one value unless the
+    /// signal is per channel.
+    fn next(&mut self, params: &SignalParameters, frame: &mut [f64]) {
+        let sample_rate = params.sample_rate as f64;
+        let t = self.position as f64 / sample_rate;
+
+        match &params.signal {
+            Signal::Sine => frame.fill(Self::tone(&mut self.phase[0], params.freq, sample_rate)),
+            Signal::Sweep {
+                start,
+                end,
+                seconds,
+                logarithmic,
+            } => {
+                let progress = (t / seconds.max(0.1)).fract();
+                let freq = if *logarithmic && *start > 0.0 && *end > 0.0 {
+                    start * (end / start).powf(progress)
+                } else {
+                    start + (end - start) * progress
+                };
+                frame.fill(Self::tone(&mut self.phase[0], freq, sample_rate));
+            }
+            Signal::WhiteNoise => frame.fill(self.rng.random_range(-1.0..1.0)),
+            Signal::PinkNoise => frame.fill(self.pink_noise()),
+            Signal::Dtmf {
+                tone_ms, gap_ms, ..
+            } => {
+                let step = (*tone_ms + *gap_ms).max(1) as u64 * params.sample_rate as u64 / 1000;
+                let tone = *tone_ms as u64 * params.sample_rate as u64 / 1000;
+                let index = (self.position / step) as usize;
+                let freqs = self
+                    .dtmf
+                    .get(index % self.dtmf.len().max(1))
+                    .filter(|_| self.position % step < tone)
+                    .copied()
+                    .flatten();
+
+                let val = match freqs {
+                    Some((low, high)) => {
+                        let low = Self::tone(&mut self.phase[0], low, sample_rate);
+                        let high = Self::tone(&mut self.phase[1], high, sample_rate);
+                        (low + high) / 2.0
+                    }
+                    None => {
+                        self.phase = [0.0; 2];
+                        0.0
+                    }
+                };
+                frame.fill(val);
+            }
+            Signal::ChannelId => {
+                let slot = t / (2.0 * CHANNEL_ID_BEEP_SECONDS);
+                let channel = slot as usize % frame.len();
+                let val = if slot.fract() < 0.5 {
+                    let freq = params.freq * (channel + 1) as f64;
+                    Self::tone(&mut self.phase[0], freq, sample_rate)
+                } else {
+                    self.phase[0] = 0.0;
+                    0.0
+                };
+                frame.fill(0.0);
+                frame[channel] = val;
+            }
+        }
+
+        self.position += 1;
+    }
+}
+
+impl AudioGenerator for SignalGenerator {
+    fn generate(&mut self, samples: &mut [i16], num_channels: usize) -> bool {
+        let params = self.params.lock().clone();
+        if params.signal != self.signal {
+            self.signal = params.signal.clone();
+            self.dtmf = Self::dtmf_sequence(&self.signal);
+            self.position = 0;
+        }
+
+        let mut frame = vec![0.0; num_channels];
+        for out in samples.chunks_exact_mut(num_channels) {
+            self.next(&params, &mut frame);
+            for (sample, val) in out.iter_mut().zip(&frame) {
+                // WebRTC uses 16-bit signed PCM
+                *sample = (params.amplitude * val * 32768.0) as i16;
+            }
+        }
+        true
+    }
+}
+
+pub struct SignalTrack {
+    track: GeneratedTrack,
+    params: Arc<Mutex<SignalParameters>>,
+}
+
+impl SignalTrack {
+    pub fn new(room: Arc<Room>, params: SignalParameters, processing: AudioProcessing) -> Self {
+        Self {
+            track: GeneratedTrack::new(room, params.sample_rate, params.num_channels, processing),
+            params: Arc::new(Mutex::new(params)),
+        }
+    }
+
+    /// Applies to a live track right away, except the channel count which is picked up on the
+    /// next publish. The sample rate is fixed when the track is created.
+    pub fn set_params(&mut self, params: SignalParameters) {
+        self.track.set_num_channels(params.num_channels);
+        *self.params.lock() = SignalParameters {
+            sample_rate: self.track.sample_rate(),
+            ..params
+        };
+    }
+
+    /// Processing changes apply right away, channel count and encoding on the next publish.
+    pub fn set_processing(&mut self, processing: AudioProcessing) {
+        self.track.set_processing(processing);
+    }
+
+    pub fn is_published(&self) -> bool {
+        self.track.is_published()
+    }
+
+    pub async fn publish(&mut self) -> Result<(), RoomError> {
+        let generator = SignalGenerator::new(self.params.clone());
+        self.track
+            .publish("signal_generator", Box::new(generator))
+            .await
+    }
+
+    pub async fn unpublish(&mut self) -> Result<(), RoomError> {
+        self.track.unpublish().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dtmf_freqs_follow_the_keypad() {
+        assert_eq!(dtmf_freqs('1'), Some((697.0, 1209.0)));
+        assert_eq!(dtmf_freqs('5'), Some((770.0, 1336.0)));
+        assert_eq!(dtmf_freqs('9'), Some((852.0, 1477.0)));
+        assert_eq!(dtmf_freqs('0'), Some((941.0, 1336.0)));
+        assert_eq!(dtmf_freqs('*'), Some((941.0, 1209.0)));
+        assert_eq!(dtmf_freqs('#'), Some((941.0, 1477.0)));
+        assert_eq!(dtmf_freqs('A'), Some((697.0, 1633.0)));
+        assert_eq!(dtmf_freqs('d'), Some((941.0, 1633.0)));
+    }
+
+    #[test]
+    fn dtmf_freqs_leave_other_characters_silent() {
+        assert_eq!(dtmf_freqs(' '), None);
+        assert_eq!(dtmf_freqs('E'), None);
+        assert_eq!(dtmf_freqs(','), None);
+    }
+}
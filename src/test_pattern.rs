@@ -0,0 +1,545 @@
+use crate::publish_profile::VideoProfile;
+use crate::text::TextRenderer;
+use image::ImageFormat;
+use image::RgbaImage;
+use livekit::prelude::*;
+use livekit::webrtc::video_source::RtcVideoSource;
+use livekit::webrtc::video_source::VideoResolution;
+use livekit::webrtc::{
+    native::yuv_helper,
+    video_frame::{I420Buffer, VideoBuffer, VideoFrame, VideoRotation},
+    video_source::native::NativeVideoSource,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+const PIXEL_SIZE: usize = 4;
+const MOVE_SPEED: i32 = 16;
+// Gradient phase advance per frame, a full cycle is 512
+const GRADIENT_SPEED: usize = 4;
+// Text height relative to the frame height
+const TEXT_SCALE: f32 = 1.0 / 18.0;
+const MIN_SIZE: u32 = 16;
+const MAX_SIZE: u32 = 3840;
+
+// 75% bars, left to right: grey, yellow, cyan, green, magenta, red, blue
+const BARS: [[u8; 3]; 7] = [
+    [191, 191, 191],
+    [191, 191, 0],
+    [0, 191, 191],
+    [0, 191, 0],
+    [191, 0, 191],
+    [191, 0, 0],
+    [0, 0, 191],
+];
+// Reverse blue bars under the main ones, for adjusting chroma by eye
+const CASTELLATIONS: [[u8; 3]; 7] = [
+    [0, 0, 191],
+    [19, 19, 19],
+    [191, 0, 191],
+    [19, 19, 19],
+    [0, 191, 191],
+    [19, 19, 19],
+    [191, 191, 191],
+];
+const MINUS_I: [u8; 3] = [0, 33, 76];
+const PLUS_Q: [u8; 3] = [50, 0, 106];
+const WHITE: [u8; 3] = [255, 255, 255];
+const BLACK: [u8; 3] = [19, 19, 19];
+// Below black can't be expressed in RGB, the PLUGE steps sit around it instead
+const PLUGE: [[u8; 3]; 3] = [[9, 9, 9], [19, 19, 19], [29, 29, 29]];
+
+/// Picture drawn under the overlays of a [`TestPatternTrack`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TestPattern {
+    /// The LiveKit logo bouncing around a black frame
+    Logo,
+    /// SMPTE colour bars with PLUGE
+    SmpteBars,
+    /// Colours sliding diagonally across the frame
+    Gradient,
+}
+
+impl TestPattern {
+    pub const ALL: [TestPattern; 3] = [
+        TestPattern::Logo,
+        TestPattern::SmpteBars,
+        TestPattern::Gradient,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TestPattern::Logo => "Logo",
+            TestPattern::SmpteBars => "SMPTE colour bars",
+            TestPattern::Gradient => "Moving gradient",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TestPatternSettings {
+    pub pattern: TestPattern,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    /// Number of frames sent since the track was published, top left
+    pub frame_counter: bool,
+    /// Wall clock UTC time with milliseconds, top right
+    pub timecode: bool,
+    /// Name of the local participant, bottom left
+    pub name_label: bool,
+}
+
+impl Default for TestPatternSettings {
+    fn default() -> Self {
+        Self {
+            pattern: TestPattern::SmpteBars,
+            width: 1280,
+            height: 720,
+            fps: 30,
+            frame_counter: true,
+            timecode: true,
+            name_label: true,
+        }
+    }
+}
+
+impl TestPatternSettings {
+    /// I420 needs even dimensions, odd sizes are rounded down.
+    pub fn resolution(&self) -> VideoResolution {
+        VideoResolution {
+            width: self.width.clamp(MIN_SIZE, MAX_SIZE) & !1,
+            height: self.height.clamp(MIN_SIZE, MAX_SIZE) & !1,
+        }
+    }
+
+    pub fn frame_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.fps.clamp(1, 120) as f64)
+    }
+}
+
+/// An RGBA framebuffer to draw the pattern into.
+struct Canvas<'a> {
+    data: &'a mut [u8],
+    width: usize,
+    height: usize,
+}
+
+impl Canvas<'_> {
+    /// Fill the rectangle from `(x0, y0)` up to `(x1, y1)`, clipped to the canvas.
+    fn fill(&mut self, x0: usize, y0: usize, x1: usize, y1: usize, color: [u8; 3]) {
+        let (x1, y1) = (x1.min(self.width), y1.min(self.height));
+        if x0 >= x1 {
+            return;
+        }
+        let pixel = [color[0], color[1], color[2], 255];
+        for y in y0..y1 {
+            let row = &mut self.data
+                [(y * self.width + x0) * PIXEL_SIZE..(y * self.width + x1) * PIXEL_SIZE];
+            for px in row.chunks_exact_mut(PIXEL_SIZE) {
+                px.copy_from_slice(&pixel);
+            }
+        }
+    }
+
+    /// Darken the rectangle to two thirds black, so text stays readable on any pattern.
+    fn shade(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        let (x0, x1) = (x0.max(0) as usize, (x1.max(0) as usize).min(self.width));
+        let (y0, y1) = (y0.max(0) as usize, (y1.max(0) as usize).min(self.height));
+        if x0 >= x1 {
+            return;
+        }
+        for y in y0..y1 {
+            let row = &mut self.data
+                [(y * self.width + x0) * PIXEL_SIZE..(y * self.width + x1) * PIXEL_SIZE];
+            for px in row.chunks_exact_mut(PIXEL_SIZE) {
+                for c in &mut px[..3] {
+                    *c /= 3;
+                }
+            }
+        }
+    }
+
+    /// Copy `image` with its top left corner at `(x, y)`, clipped to the canvas.
+    fn blit(&mut self, image: &RgbaImage, x: usize, y: usize) {
+        let width = (image.width() as usize).min(self.width.saturating_sub(x));
+        let height = (image.height() as usize).min(self.height.saturating_sub(y));
+        let image_stride = image.width() as usize * PIXEL_SIZE;
+        for i in 0..height {
+            let src = &image.as_raw()[i * image_stride..i * image_stride + width * PIXEL_SIZE];
+            let start = ((y + i) * self.width + x) * PIXEL_SIZE;
+            self.data[start..start + width * PIXEL_SIZE].copy_from_slice(src);
+        }
+    }
+
+    /// Draw `text` in white on a shaded box. `(x, y)` is the top left corner of the box,
+    /// negative values are measured from the right and bottom edges instead.
+    fn label(&mut self, text_renderer: &TextRenderer, size: f32, x: i32, y: i32, text: &str) {
+        let padding = (size / 4.0).ceil() as i32;
+        let (text_width, text_height) = text_renderer.measure(size, text);
+        let box_width = text_width + 2 * padding;
+        let box_height = text_height + 2 * padding;
+
+        let x = if x < 0 {
+            self.width as i32 + x - box_width
+        } else {
+            x
+        };
+        let y = if y < 0 {
+            self.height as i32 + y - box_height
+        } else {
+            y
+        };
+        self.shade(x, y, x + box_width, y + box_height);
+
+        text_renderer.draw(size, x + padding, y + padding, text, |px, py, coverage| {
+            if px < 0 || py < 0 || px >= self.width as i32 || py >= self.height as i32 {
+                return;
+            }
+            let i = (py as usize * self.width + px as usize) * PIXEL_SIZE;
+            for c in &mut self.data[i..i + 3] {
+                *c += ((255 - *c) as f32 * coverage) as u8;
+            }
+        });
+    }
+}
+
+/// Draws frames of a test pattern, keeping what moves from one frame to the next.
+struct Renderer {
+    logo: RgbaImage,
+    text_renderer: TextRenderer,
+    /// Name of the local participant
+    name: String,
+    framebuffer: Vec<u8>,
+    video_frame: VideoFrame<I420Buffer>,
+    frame_count: u64,
+    pos: (i32, i32),
+    direction: (i32, i32),
+}
+
+impl Renderer {
+    fn new(name: String) -> Self {
+        let logo = image::load_from_memory_with_format(
+            include_bytes!("moving-logo.png"),
+            ImageFormat::Png,
+        )
+        .unwrap()
+        .to_rgba8();
+
+        Self {
+            logo,
+            text_renderer: TextRenderer::new(),
+            name,
+            framebuffer: Vec::new(),
+            video_frame: VideoFrame {
+                rotation: VideoRotation::VideoRotation0,
+                buffer: I420Buffer::new(MIN_SIZE, MIN_SIZE),
+                timestamp_us: 0,
+            },
+            frame_count: 0,
+            pos: (0, 0),
+            direction: (1, 1),
+        }
+    }
+
+    /// Draw the next frame at the current settings and convert it to I420.
+    fn render(&mut self, settings: &TestPatternSettings) -> &VideoFrame<I420Buffer> {
+        let VideoResolution { width, height } = settings.resolution();
+        if self.video_frame.buffer.width() != width || self.video_frame.buffer.height() != height {
+            self.video_frame.buffer = I420Buffer::new(width, height);
+        }
+        let (width, height) = (width as usize, height as usize);
+        self.framebuffer.resize(width * height * PIXEL_SIZE, 0);
+
+        let mut canvas = Canvas {
+            data: &mut self.framebuffer,
+            width,
+            height,
+        };
+
+        match settings.pattern {
+            TestPattern::Logo => {
+                // Bounce within the frame, a logo bigger than the frame stays in the corner
+                let max_x = width.saturating_sub(self.logo.width() as usize) as i32;
+                let max_y = height.saturating_sub(self.logo.height() as usize) as i32;
+
+                self.pos.0 = (self.pos.0 + self.direction.0 * MOVE_SPEED).clamp(0, max_x);
+                self.pos.1 = (self.pos.1 + self.direction.1 * MOVE_SPEED).clamp(0, max_y);
+
+                if self.pos.0 >= max_x {
+                    self.direction.0 = -1;
+                } else if self.pos.0 <= 0 {
+                    self.direction.0 = 1;
+                }
+
+                if self.pos.1 >= max_y {
+                    self.direction.1 = -1;
+                } else if self.pos.1 <= 0 {
+                    self.direction.1 = 1;
+                }
+
+                canvas.data.fill(0);
+                canvas.blit(&self.logo, self.pos.0 as usize, self.pos.1 as usize);
+            }
+            TestPattern::SmpteBars => smpte_bars(&mut canvas),
+            TestPattern::Gradient => gradient(&mut canvas, self.frame_count),
+        }
+
+        let text_size = (height as f32 * TEXT_SCALE).max(8.0);
+        let margin = (text_size / 2.0) as i32;
+        if settings.frame_counter {
+            let text = format!("Frame {}", self.frame_count);
+            canvas.label(&self.text_renderer, text_size, margin, margin, &text);
+        }
+        if settings.timecode {
+            canvas.label(
+                &self.text_renderer,
+                text_size,
+                -margin,
+                margin,
+                &timecode(SystemTime::now()),
+            );
+        }
+        if settings.name_label && !self.name.is_empty() {
+            canvas.label(&self.text_renderer, text_size, margin, -margin, &self.name);
+        }
+
+        let i420_buffer = &mut self.video_frame.buffer;
+        let (stride_y, stride_u, stride_v) = i420_buffer.strides();
+        let (data_y, data_u, data_v) = i420_buffer.data_mut();
+        yuv_helper::abgr_to_i420(
+            &self.framebuffer,
+            (width * PIXEL_SIZE) as u32,
+            data_y,
+            stride_y,
+            data_u,
+            stride_u,
+            data_v,
+            stride_v,
+            width as i32,
+            height as i32,
+        );
+
+        self.frame_count += 1;
+        &self.video_frame
+    }
+}
+
+/// SMPTE EG 1 colour bars: the bars over two thirds of the height, a strip of reverse blue
+/// bars, then -I, white, +Q, black and PLUGE along the bottom quarter.
+fn smpte_bars(canvas: &mut Canvas) {
+    let (width, height) = (canvas.width, canvas.height);
+    let bar = |i: usize| i * width / 7;
+    let bars_end = height * 2 / 3;
+    let castellations_end = height * 3 / 4;
+
+    for (i, color) in BARS.iter().enumerate() {
+        canvas.fill(bar(i), 0, bar(i + 1), bars_end, *color);
+    }
+    for (i, color) in CASTELLATIONS.iter().enumerate() {
+        canvas.fill(bar(i), bars_end, bar(i + 1), castellations_end, *color);
+    }
+
+    // The first three blocks are each a bar and a quarter wide
+    let block = |i: usize| i * 5 * width / 28;
+    canvas.fill(0, castellations_end, block(1), height, MINUS_I);
+    canvas.fill(block(1), castellations_end, block(2), height, WHITE);
+    canvas.fill(block(2), castellations_end, block(3), height, PLUS_Q);
+    canvas.fill(block(3), castellations_end, bar(5), height, BLACK);
+    for (i, color) in PLUGE.iter().enumerate() {
+        let step = |i: usize| bar(5) + i * (bar(6) - bar(5)) / 3;
+        canvas.fill(step(i), castellations_end, step(i + 1), height, *color);
+    }
+    canvas.fill(bar(6), castellations_end, width, height, BLACK);
+}
+
+/// Red follows the columns, green the rows, both sliding with `frame` so motion and
+/// dropped frames are easy to spot.
+fn gradient(canvas: &mut Canvas, frame: u64) {
+    // Rises from 0 to 255 and falls back over a period of 512, without a hard edge
+    let triangle = |v: usize| {
+        let v = v % 512;
+        if v < 256 {
+            v as u8
+        } else {
+            (511 - v) as u8
+        }
+    };
+
+    let phase = frame as usize * GRADIENT_SPEED;
+    let columns = (0..canvas.width)
+        .map(|x| triangle(x * 512 / canvas.width + phase))
+        .collect::<Vec<_>>();
+
+    for (y, row) in canvas
+        .data
+        .chunks_exact_mut(canvas.width * PIXEL_SIZE)
+        .enumerate()
+    {
+        let green = triangle(y * 512 / canvas.height + phase / 2);
+        for (px, red) in row.chunks_exact_mut(PIXEL_SIZE).zip(&columns) {
+            px.copy_from_slice(&[*red, green, 255 - red / 2 - green / 2, 255]);
+        }
+    }
+}
+
+/// `HH:MM:SS.mmm UTC`, for comparing against the clock on the receiving end.
+fn timecode(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() % 86400;
+    format!(
+        "{:02}:{:02}:{:02}.{:03} UTC",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+struct TrackHandle {
+    close_tx: oneshot::Sender<()>,
+    track: LocalVideoTrack,
+    task: JoinHandle<()>,
+}
+
+/// Publishes a generated test pattern as a camera track.
+pub struct TestPatternTrack {
+    room: Arc<Room>,
+    settings: Arc<Mutex<TestPatternSettings>>,
+    handle: Option<TrackHandle>,
+}
+
+impl TestPatternTrack {
+    pub fn new(room: Arc<Room>, settings: TestPatternSettings) -> Self {
+        Self {
+            room,
+            settings: Arc::new(Mutex::new(settings)),
+            handle: None,
+        }
+    }
+
+    /// Applies to a live track from the next frame on.
+    pub fn set_settings(&mut self, settings: TestPatternSettings) {
+        *self.settings.lock() = settings;
+    }
+
+    pub fn is_published(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    pub async fn publish(&mut self, profile: VideoProfile) -> Result<(), RoomError> {
+        self.unpublish().await?;
+
+        let local = self.room.local_participant();
+        let name = match local.name() {
+            name if name.is_empty() => local.identity().to_string(),
+            name => name,
+        };
+
+        let resolution = self.settings.lock().resolution();
+        let rtc_source = NativeVideoSource::new(resolution.clone());
+        let (close_tx, close_rx) = oneshot::channel();
+        let track = LocalVideoTrack::create_video_track(
+            "test_pattern",
+            RtcVideoSource::Native(rtc_source.clone()),
+        );
+
+        let task = tokio::spawn(Self::track_task(
+            close_rx,
+            rtc_source,
+            self.settings.clone(),
+            name,
+        ));
+
+        local
+            .publish_track(
+                LocalTrack::Video(track.clone()),
+                profile.publish_options(TrackSource::Camera, resolution),
+            )
+            .await?;
+
+        let handle = TrackHandle {
+            close_tx,
+            task,
+            track,
+        };
+
+        self.handle = Some(handle);
+        Ok(())
+    }
+
+    pub async fn unpublish(&mut self) -> Result<(), RoomError> {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.close_tx.send(());
+            let _ = handle.task.await;
+
+            self.room
+                .local_participant()
+                .unpublish_track(&handle.track.sid())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn track_task(
+        mut close_rx: oneshot::Receiver<()>,
+        rtc_source: NativeVideoSource,
+        settings: Arc<Mutex<TestPatternSettings>>,
+        name: String,
+    ) {
+        let mut frame_interval = settings.lock().frame_interval();
+        let mut interval = tokio::time::interval(frame_interval);
+
+        let renderer = match tokio::task::spawn_blocking(move || Renderer::new(name)).await {
+            Ok(renderer) => renderer,
+            Err(err) => {
+                log::error!("failed to create test pattern renderer: {}", err);
+                return;
+            }
+        };
+        let renderer = Arc::new(Mutex::new(renderer));
+
+        loop {
+            tokio::select! {
+                _ = &mut close_rx => {
+                    break;
+                }
+                _ = interval.tick() => {}
+            }
+
+            let settings = settings.lock().clone();
+            if settings.frame_interval() != frame_interval {
+                frame_interval = settings.frame_interval();
+                interval = tokio::time::interval(frame_interval);
+            }
+
+            let res = tokio::task::spawn_blocking({
+                let renderer = renderer.clone();
+                let source = rtc_source.clone();
+                move || {
+                    let mut renderer = renderer.lock();
+                    source.capture_frame(renderer.render(&settings));
+                }
+            })
+            .await;
+
+            if let Err(err) = res {
+                log::error!("failed to render test pattern: {}", err);
+                break;
+            }
+        }
+    }
+}
+
+impl Drop for TestPatternTrack {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.close_tx.send(());
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Text burned into the video we publish.
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+
+/// Lays out and rasterises single lines of text in a monospace font.
+pub struct TextRenderer {
+    font: FontRef<'static>,
+}
+
+impl TextRenderer {
+    pub fn new() -> Self {
+        Self {
+            font: FontRef::try_from_slice(epaint_default_fonts::HACK_REGULAR).unwrap(),
+        }
+    }
+
+    /// Width and height of `text` at `size` pixels.
+    pub fn measure(&self, size: f32, text: &str) -> (i32, i32) {
+        let font = self.font.as_scaled(PxScale::from(size));
+        let width = text
+            .chars()
+            .map(|c| font.h_advance(font.glyph_id(c)))
+            .sum::<f32>();
+        (width.ceil() as i32, font.height().ceil() as i32)
+    }
+
+    /// Rasterise `text` with its top left corner at `(x, y)`. `plot` is called with the
+    /// coverage of each pixel from 0 to 1, pixels may lie outside of the frame.
+    pub fn draw(&self, size: f32, x: i32, y: i32, text: &str, mut plot: impl FnMut(i32, i32, f32)) {
+        let font = self.font.as_scaled(PxScale::from(size));
+        let mut caret = x as f32;
+        let baseline = y as f32 + font.ascent();
+        for c in text.chars() {
+            let mut glyph = font.scaled_glyph(c);
+            glyph.position = point(caret, baseline);
+            caret += font.h_advance(glyph.id);
+
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                plot(
+                    bounds.min.x as i32 + gx as i32,
+                    bounds.min.y as i32 + gy as i32,
+                    coverage.min(1.0),
+                );
+            });
+        }
+    }
+}
+
+impl Default for TextRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,411 @@
+use crate::publish_profile::VideoProfile;
+use livekit::prelude::*;
+use livekit::webrtc::video_source::RtcVideoSource;
+use livekit::webrtc::video_source::VideoResolution;
+use livekit::webrtc::{
+    native::yuv_helper,
+    video_frame::{I420Buffer, VideoBuffer, VideoFrame, VideoRotation},
+    video_source::native::NativeVideoSource,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+const PIXEL_SIZE: usize = 4;
+const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "bmp"];
+
+#[derive(Debug, Error)]
+pub enum VideoFileError {
+    #[error("failed to read video file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to decode y4m: {0}")]
+    Y4m(#[from] y4m::Error),
+    #[error("failed to decode image: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("unsupported video: {0}")]
+    Unsupported(String),
+    #[error("no images found in {0}")]
+    Empty(PathBuf),
+    #[error("failed to publish video file: {0}")]
+    Room(#[from] RoomError),
+    #[error("video file task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+/// Last video picked in the publish menu.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoFileSettings {
+    /// A `.y4m` file or a directory of images
+    pub path: String,
+    pub looping: bool,
+    /// Frame rate of image sequences, Y4M files carry their own
+    pub image_fps: u32,
+}
+
+impl Default for VideoFileSettings {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            looping: true,
+            image_fps: 30,
+        }
+    }
+}
+
+enum Frames {
+    /// 8-bit 4:2:0 frames read one at a time, the file is reopened to loop
+    Y4m(y4m::Decoder<BufReader<File>>),
+    /// Images sorted by name, decoded as they are shown
+    Images { paths: Vec<PathBuf>, next: usize },
+}
+
+/// Decodes a Y4M file or a directory of images into I420 frames.
+pub struct VideoReader {
+    path: PathBuf,
+    frames: Frames,
+    frame_rate: f64,
+    width: u32,
+    height: u32,
+}
+
+impl VideoReader {
+    /// Open a `.y4m` file, or a directory whose images are shown at `image_fps`.
+    pub fn open(path: &Path, image_fps: u32) -> Result<Self, VideoFileError> {
+        if path.is_dir() {
+            let mut paths = std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| is_image(path))
+                .collect::<Vec<_>>();
+            paths.sort();
+
+            let first = paths
+                .first()
+                .ok_or_else(|| VideoFileError::Empty(path.to_path_buf()))?;
+            let (width, height) = image::image_dimensions(first)?;
+
+            return Ok(Self {
+                path: path.to_path_buf(),
+                frames: Frames::Images { paths, next: 0 },
+                frame_rate: image_fps.max(1) as f64,
+                width,
+                height,
+            });
+        }
+
+        let decoder = Self::open_y4m(path)?;
+        let rate = decoder.get_framerate();
+        if rate.num == 0 || rate.den == 0 {
+            return Err(VideoFileError::Unsupported(format!("frame rate {}", rate)));
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            frame_rate: rate.num as f64 / rate.den as f64,
+            width: decoder.get_width() as u32,
+            height: decoder.get_height() as u32,
+            frames: Frames::Y4m(decoder),
+        })
+    }
+
+    fn open_y4m(path: &Path) -> Result<y4m::Decoder<BufReader<File>>, VideoFileError> {
+        let decoder = y4m::decode(BufReader::new(File::open(path)?))?;
+        match decoder.get_colorspace() {
+            y4m::Colorspace::C420
+            | y4m::Colorspace::C420jpeg
+            | y4m::Colorspace::C420paldv
+            | y4m::Colorspace::C420mpeg2 => Ok(decoder),
+            colorspace => Err(VideoFileError::Unsupported(format!(
+                "colorspace {:?}, only 8-bit 4:2:0 is supported",
+                colorspace
+            ))),
+        }
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        self.frame_rate
+    }
+
+    pub fn resolution(&self) -> VideoResolution {
+        VideoResolution {
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Decode the next frame into `frame`, starting over at the end when `looping`.
+    ///
+    /// Returns false once the video is over.
+    pub fn read_frame(
+        &mut self,
+        frame: &mut VideoFrame<I420Buffer>,
+        looping: bool,
+    ) -> Result<bool, VideoFileError> {
+        if self.read_next(frame)? {
+            return Ok(true);
+        }
+        if !looping {
+            return Ok(false);
+        }
+
+        match &mut self.frames {
+            Frames::Y4m(decoder) => *decoder = Self::open_y4m(&self.path)?,
+            Frames::Images { next, .. } => *next = 0,
+        }
+        self.read_next(frame)
+    }
+
+    fn read_next(&mut self, frame: &mut VideoFrame<I420Buffer>) -> Result<bool, VideoFileError> {
+        match &mut self.frames {
+            Frames::Y4m(decoder) => {
+                let (width, height) = (decoder.get_width() as u32, decoder.get_height() as u32);
+                let source = match decoder.read_frame() {
+                    Ok(source) => source,
+                    Err(y4m::Error::EOF) => return Ok(false),
+                    Err(err) => return Err(err.into()),
+                };
+
+                let buffer = resize_buffer(&mut frame.buffer, width, height);
+                let (stride_y, stride_u, stride_v) = buffer.strides();
+                let (data_y, data_u, data_v) = buffer.data_mut();
+                let chroma = width.div_ceil(2);
+                copy_plane(source.get_y_plane(), width, data_y, stride_y);
+                copy_plane(source.get_u_plane(), chroma, data_u, stride_u);
+                copy_plane(source.get_v_plane(), chroma, data_v, stride_v);
+                Ok(true)
+            }
+            Frames::Images { paths, next } => {
+                let Some(path) = paths.get(*next) else {
+                    return Ok(false);
+                };
+                *next += 1;
+
+                let image = image::open(path)?.to_rgba8();
+                let buffer = resize_buffer(&mut frame.buffer, image.width(), image.height());
+                let (stride_y, stride_u, stride_v) = buffer.strides();
+                let (data_y, data_u, data_v) = buffer.data_mut();
+                yuv_helper::abgr_to_i420(
+                    image.as_raw(),
+                    image.width() * PIXEL_SIZE as u32,
+                    data_y,
+                    stride_y,
+                    data_u,
+                    stride_u,
+                    data_v,
+                    stride_v,
+                    image.width() as i32,
+                    image.height() as i32,
+                );
+                Ok(true)
+            }
+        }
+    }
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Reallocate `buffer` when the frame size changes, image sequences can mix sizes.
+fn resize_buffer(buffer: &mut I420Buffer, width: u32, height: u32) -> &mut I420Buffer {
+    if buffer.width() != width || buffer.height() != height {
+        *buffer = I420Buffer::new(width, height);
+    }
+    buffer
+}
+
+/// Copy a tightly packed plane into a buffer plane with its own stride.
+fn copy_plane(src: &[u8], width: u32, dst: &mut [u8], dst_stride: u32) {
+    let width = width as usize;
+    for (src_row, dst_row) in src
+        .chunks_exact(width)
+        .zip(dst.chunks_mut(dst_stride as usize))
+    {
+        dst_row[..width].copy_from_slice(src_row);
+    }
+}
+
+type FailedCallback = Arc<dyn Fn(String) + Send + Sync>;
+
+struct TrackHandle {
+    close_tx: oneshot::Sender<()>,
+    track: LocalVideoTrack,
+    task: JoinHandle<()>,
+    /// Set once reading the video failed during playback
+    failed: Arc<AtomicBool>,
+}
+
+/// Publishes a pre-recorded video, paced to its frame rate.
+pub struct VideoFileTrack {
+    room: Arc<Room>,
+    on_failed: Option<FailedCallback>,
+    handle: Option<TrackHandle>,
+}
+
+impl VideoFileTrack {
+    pub fn new(room: Arc<Room>) -> Self {
+        Self {
+            room,
+            on_failed: None,
+            handle: None,
+        }
+    }
+
+    /// Called with the error when reading the video fails during playback. The track stays
+    /// published until it is unpublished.
+    pub fn on_failed(mut self, on_failed: impl Fn(String) + Send + Sync + 'static) -> Self {
+        self.on_failed = Some(Arc::new(on_failed));
+        self
+    }
+
+    pub fn is_published(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    /// Whether reading the current video failed, a video published since has not.
+    pub fn has_failed(&self) -> bool {
+        self.handle
+            .as_ref()
+            .is_some_and(|handle| handle.failed.load(Ordering::Acquire))
+    }
+
+    /// Publish `path`, see [`VideoReader::open`], replacing the video being played.
+    pub async fn publish(
+        &mut self,
+        path: PathBuf,
+        looping: bool,
+        image_fps: u32,
+        profile: VideoProfile,
+    ) -> Result<(), VideoFileError> {
+        self.unpublish().await?;
+
+        let reader =
+            tokio::task::spawn_blocking(move || VideoReader::open(&path, image_fps)).await??;
+
+        let resolution = reader.resolution();
+        let rtc_source = NativeVideoSource::new(resolution.clone());
+        let (close_tx, close_rx) = oneshot::channel();
+        let track = LocalVideoTrack::create_video_track(
+            "video_file",
+            RtcVideoSource::Native(rtc_source.clone()),
+        );
+
+        let failed = Arc::new(AtomicBool::new(false));
+        let on_error = {
+            let failed = failed.clone();
+            let on_failed = self.on_failed.clone();
+            move |err: VideoFileError| {
+                failed.store(true, Ordering::Release);
+                if let Some(on_failed) = on_failed {
+                    on_failed(err.to_string());
+                }
+            }
+        };
+        let task = tokio::spawn(Self::track_task(
+            close_rx, rtc_source, reader, looping, on_error,
+        ));
+
+        self.room
+            .local_participant()
+            .publish_track(
+                LocalTrack::Video(track.clone()),
+                profile.publish_options(TrackSource::Camera, resolution),
+            )
+            .await?;
+
+        self.handle = Some(TrackHandle {
+            close_tx,
+            track,
+            task,
+            failed,
+        });
+        Ok(())
+    }
+
+    pub async fn unpublish(&mut self) -> Result<(), VideoFileError> {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.close_tx.send(());
+            let _ = handle.task.await;
+
+            self.room
+                .local_participant()
+                .unpublish_track(&handle.track.sid())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn track_task(
+        mut close_rx: oneshot::Receiver<()>,
+        rtc_source: NativeVideoSource,
+        reader: VideoReader,
+        looping: bool,
+        on_error: impl FnOnce(VideoFileError) + Send + 'static,
+    ) {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs_f64(1.0 / reader.frame_rate()));
+        let resolution = reader.resolution();
+
+        let reader = Arc::new(Mutex::new(reader));
+        let video_frame = Arc::new(Mutex::new(VideoFrame {
+            rotation: VideoRotation::VideoRotation0,
+            buffer: I420Buffer::new(resolution.width, resolution.height),
+            timestamp_us: 0,
+        }));
+
+        loop {
+            tokio::select! {
+                _ = &mut close_rx => {
+                    break;
+                }
+                _ = interval.tick() => {}
+            }
+
+            let res = tokio::task::spawn_blocking({
+                let reader = reader.clone();
+                let video_frame = video_frame.clone();
+                let source = rtc_source.clone();
+                move || {
+                    let mut video_frame = video_frame.lock();
+                    let more = reader.lock().read_frame(&mut video_frame, looping)?;
+                    if more {
+                        source.capture_frame(&*video_frame);
+                    }
+                    Ok::<_, VideoFileError>(more)
+                }
+            })
+            .await
+            .map_err(VideoFileError::from)
+            .and_then(|res| res);
+
+            match res {
+                Ok(true) => {}
+                // The last frame stays up until the track is unpublished
+                Ok(false) => break,
+                Err(err) => {
+                    log::error!("failed to read video file: {}", err);
+                    on_error(err);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for VideoFileTrack {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.close_tx.send(());
+        }
+    }
+}
@@ -175,4 +175,15 @@ impl<'a> VideoGridContext<'a> {
 
         self.ui.allocate_rect(frame_rect, egui::Sense::hover())
     }
+
+    /// Horizontal position of a frame's column, from -1.0 (leftmost) to 1.0 (rightmost).
+    pub fn column_pan(&self, frame_rect: egui::Rect) -> f32 {
+        let available = self.layout.available_rect;
+        if !frame_rect.is_positive() || available.width() <= 0.0 {
+            return 0.0;
+        }
+
+        let x = (frame_rect.center().x - available.left()) / available.width();
+        (x * 2.0 - 1.0).clamp(-1.0, 1.0)
+    }
 }
@@ -0,0 +1,532 @@
+//! Processing applied to camera frames between capture and the published track.
+//!
+//! Frames go through a chain of [`FrameProcessor`]s in I420. The chain is described by
+//! [`ProcessingSettings`], an ordered list of steps that can each be turned off, and is
+//! rebuilt whenever the settings change, so it can be edited while the camera is live. The
+//! chain belongs to the capture thread, edits reach it as [`ChainUpdate`]s between frames.
+use crate::background::{Background, BackgroundImage, BackgroundModel, BackgroundSettings};
+use crate::text::TextRenderer;
+use livekit::webrtc::video_frame::{I420Buffer, VideoBuffer};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+// Watermark text height relative to the frame height
+const WATERMARK_SCALE: f32 = 1.0 / 20.0;
+
+/// A step of the processing chain.
+pub trait FrameProcessor: Send {
+    /// Returns `buffer` changed in place, or a new buffer when the size changes.
+    fn process(&mut self, buffer: I420Buffer) -> I420Buffer;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rotation {
+    /// Clockwise
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Processor {
+    /// Blur or replace the room behind the person
+    Background(BackgroundSettings),
+    /// Flip left to right
+    Mirror,
+    /// Percent of the width or height removed from each side
+    Crop {
+        left: u32,
+        top: u32,
+        right: u32,
+        bottom: u32,
+    },
+    Rotate(Rotation),
+    /// Added to the luma, from -100 to 100
+    Brightness(i32),
+    /// Name of the local participant in the bottom left corner
+    Watermark,
+}
+
+impl Processor {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Processor::Background(_) => "Background",
+            Processor::Mirror => "Mirror",
+            Processor::Crop { .. } => "Crop",
+            Processor::Rotate(_) => "Rotate",
+            Processor::Brightness(_) => "Brightness",
+            Processor::Watermark => "Name watermark",
+        }
+    }
+
+    /// One of each processor with default parameters, in their default order.
+    pub fn all() -> [Processor; 6] {
+        [
+            Processor::Background(BackgroundSettings::default()),
+            Processor::Mirror,
+            Processor::Crop {
+                left: 0,
+                top: 0,
+                right: 0,
+                bottom: 0,
+            },
+            Processor::Rotate(Rotation::Rotate90),
+            Processor::Brightness(0),
+            Processor::Watermark,
+        ]
+    }
+
+    fn build(
+        &self,
+        name: &str,
+        background: &Arc<Mutex<BackgroundModel>>,
+        background_image: &BackgroundImage,
+    ) -> Box<dyn FrameProcessor> {
+        match self {
+            Processor::Background(settings) => Box::new(Background::new(
+                settings.clone(),
+                background.clone(),
+                background_image.clone(),
+            )),
+            Processor::Mirror => Box::new(Mirror),
+            Processor::Crop {
+                left,
+                top,
+                right,
+                bottom,
+            } => Box::new(Crop {
+                left: *left,
+                top: *top,
+                right: *right,
+                bottom: *bottom,
+            }),
+            Processor::Rotate(rotation) => Box::new(Rotate(*rotation)),
+            Processor::Brightness(offset) => Box::new(Brightness::new(*offset)),
+            Processor::Watermark => Box::new(Watermark {
+                text_renderer: TextRenderer::new(),
+                name: name.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessingStep {
+    pub enabled: bool,
+    pub processor: Processor,
+}
+
+/// Steps applied to each camera frame, first to last.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessingSettings {
+    pub steps: Vec<ProcessingStep>,
+}
+
+impl Default for ProcessingSettings {
+    fn default() -> Self {
+        Self {
+            steps: Processor::all()
+                .into_iter()
+                .map(|processor| ProcessingStep {
+                    enabled: false,
+                    processor,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ProcessingSettings {
+    /// Append the processors added since the settings were saved, turned off.
+    pub fn add_missing_steps(&mut self) {
+        for processor in Processor::all() {
+            let kind = std::mem::discriminant(&processor);
+            if !self
+                .steps
+                .iter()
+                .any(|step| std::mem::discriminant(&step.processor) == kind)
+            {
+                self.steps.push(ProcessingStep {
+                    enabled: false,
+                    processor,
+                });
+            }
+        }
+    }
+}
+
+/// A change to a running [`ProcessingChain`].
+#[derive(Debug, Clone)]
+pub enum ChainUpdate {
+    Settings(ProcessingSettings),
+    Name(String),
+    /// See [`ProcessingChain::capture_background`]
+    CaptureBackground,
+}
+
+/// The enabled steps of [`ProcessingSettings`], ready to run.
+pub struct ProcessingChain {
+    settings: ProcessingSettings,
+    /// Name of the local participant, for the watermark
+    name: String,
+    processors: Vec<Box<dyn FrameProcessor>>,
+    /// Outlive the processors so the background plate and image survive edits
+    background: Arc<Mutex<BackgroundModel>>,
+    background_image: BackgroundImage,
+}
+
+impl ProcessingChain {
+    pub fn new(settings: ProcessingSettings) -> Self {
+        Self::with_background(settings, Arc::new(Mutex::new(BackgroundModel::new())))
+    }
+
+    /// Use a background plate learned by an earlier chain.
+    pub fn with_background(
+        settings: ProcessingSettings,
+        background: Arc<Mutex<BackgroundModel>>,
+    ) -> Self {
+        let mut chain = Self {
+            settings,
+            name: String::new(),
+            processors: Vec::new(),
+            background,
+            background_image: BackgroundImage::default(),
+        };
+        chain.rebuild();
+        chain
+    }
+
+    pub fn set_settings(&mut self, settings: ProcessingSettings) {
+        self.settings = settings;
+        self.rebuild();
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+        self.rebuild();
+    }
+
+    /// Learn the background plate, for when the room is empty.
+    pub fn capture_background(&mut self) {
+        self.background.lock().reset();
+    }
+
+    pub fn apply(&mut self, update: ChainUpdate) {
+        match update {
+            ChainUpdate::Settings(settings) => self.set_settings(settings),
+            ChainUpdate::Name(name) => self.set_name(name),
+            ChainUpdate::CaptureBackground => self.capture_background(),
+        }
+    }
+
+    fn rebuild(&mut self) {
+        self.processors = self
+            .settings
+            .steps
+            .iter()
+            .filter(|step| step.enabled)
+            .map(|step| {
+                step.processor
+                    .build(&self.name, &self.background, &self.background_image)
+            })
+            .collect();
+    }
+
+    pub fn process(&mut self, buffer: I420Buffer) -> I420Buffer {
+        self.processors
+            .iter_mut()
+            .fold(buffer, |buffer, processor| processor.process(buffer))
+    }
+}
+
+/// Each plane of `buffer` with its width, height and stride.
+pub(crate) fn planes(buffer: &I420Buffer) -> [(&[u8], usize, usize, usize); 3] {
+    let (width, height) = (buffer.width() as usize, buffer.height() as usize);
+    let (chroma_width, chroma_height) = (
+        buffer.chroma_width() as usize,
+        buffer.chroma_height() as usize,
+    );
+    let (stride_y, stride_u, stride_v) = buffer.strides();
+    let (data_y, data_u, data_v) = buffer.data();
+    [
+        (data_y, width, height, stride_y as usize),
+        (data_u, chroma_width, chroma_height, stride_u as usize),
+        (data_v, chroma_width, chroma_height, stride_v as usize),
+    ]
+}
+
+/// Mutable version of [`planes`].
+pub(crate) fn planes_mut(buffer: &mut I420Buffer) -> [(&mut [u8], usize, usize, usize); 3] {
+    let (width, height) = (buffer.width() as usize, buffer.height() as usize);
+    let (chroma_width, chroma_height) = (
+        buffer.chroma_width() as usize,
+        buffer.chroma_height() as usize,
+    );
+    let (stride_y, stride_u, stride_v) = buffer.strides();
+    let (data_y, data_u, data_v) = buffer.data_mut();
+    [
+        (data_y, width, height, stride_y as usize),
+        (data_u, chroma_width, chroma_height, stride_u as usize),
+        (data_v, chroma_width, chroma_height, stride_v as usize),
+    ]
+}
+
+struct Mirror;
+
+impl FrameProcessor for Mirror {
+    fn process(&mut self, mut buffer: I420Buffer) -> I420Buffer {
+        for (data, width, height, stride) in planes_mut(&mut buffer) {
+            for row in data.chunks_mut(stride).take(height) {
+                row[..width].reverse();
+            }
+        }
+        buffer
+    }
+}
+
+struct Crop {
+    left: u32,
+    top: u32,
+    right: u32,
+    bottom: u32,
+}
+
+impl FrameProcessor for Crop {
+    fn process(&mut self, buffer: I420Buffer) -> I420Buffer {
+        let (width, height) = (buffer.width(), buffer.height());
+        // Even offsets keep the chroma planes aligned with the luma
+        let x0 = (width * self.left.min(45) / 100) & !1;
+        let y0 = (height * self.top.min(45) / 100) & !1;
+        let x1 = width - width * self.right.min(45) / 100;
+        let y1 = height - height * self.bottom.min(45) / 100;
+        let (new_width, new_height) = ((x1 - x0).max(2) & !1, (y1 - y0).max(2) & !1);
+        if (new_width, new_height) == (width, height) {
+            return buffer;
+        }
+
+        let mut cropped = I420Buffer::new(new_width, new_height);
+        for (i, ((src, _, _, src_stride), (dst, dst_width, dst_height, dst_stride))) in
+            planes(&buffer)
+                .into_iter()
+                .zip(planes_mut(&mut cropped))
+                .enumerate()
+        {
+            let scale = if i == 0 { 1 } else { 2 };
+            let (x, y) = (x0 as usize / scale, y0 as usize / scale);
+            for row in 0..dst_height {
+                let src_start = (y + row) * src_stride + x;
+                dst[row * dst_stride..row * dst_stride + dst_width]
+                    .copy_from_slice(&src[src_start..src_start + dst_width]);
+            }
+        }
+        cropped
+    }
+}
+
+struct Rotate(Rotation);
+
+impl FrameProcessor for Rotate {
+    fn process(&mut self, buffer: I420Buffer) -> I420Buffer {
+        let (width, height) = (buffer.width(), buffer.height());
+        let mut rotated = match self.0 {
+            Rotation::Rotate180 => I420Buffer::new(width, height),
+            Rotation::Rotate90 | Rotation::Rotate270 => I420Buffer::new(height, width),
+        };
+
+        for ((src, src_width, src_height, src_stride), (dst, _, _, dst_stride)) in
+            planes(&buffer).into_iter().zip(planes_mut(&mut rotated))
+        {
+            for y in 0..src_height {
+                let src_row = &src[y * src_stride..y * src_stride + src_width];
+                for (x, &value) in src_row.iter().enumerate() {
+                    let (dst_x, dst_y) = match self.0 {
+                        Rotation::Rotate90 => (src_height - 1 - y, x),
+                        Rotation::Rotate180 => (src_width - 1 - x, src_height - 1 - y),
+                        Rotation::Rotate270 => (y, src_width - 1 - x),
+                    };
+                    dst[dst_y * dst_stride + dst_x] = value;
+                }
+            }
+        }
+        rotated
+    }
+}
+
+struct Brightness {
+    lut: [u8; 256],
+}
+
+impl Brightness {
+    fn new(offset: i32) -> Self {
+        let mut lut = [0; 256];
+        for (luma, out) in lut.iter_mut().enumerate() {
+            *out = (luma as i32 + offset.clamp(-100, 100)).clamp(0, 255) as u8;
+        }
+        Self { lut }
+    }
+}
+
+impl FrameProcessor for Brightness {
+    fn process(&mut self, mut buffer: I420Buffer) -> I420Buffer {
+        let [(data, width, height, stride), ..] = planes_mut(&mut buffer);
+        for row in data.chunks_mut(stride).take(height) {
+            for luma in &mut row[..width] {
+                *luma = self.lut[*luma as usize];
+            }
+        }
+        buffer
+    }
+}
+
+struct Watermark {
+    text_renderer: TextRenderer,
+    name: String,
+}
+
+impl FrameProcessor for Watermark {
+    fn process(&mut self, mut buffer: I420Buffer) -> I420Buffer {
+        if self.name.is_empty() {
+            return buffer;
+        }
+
+        let size = (buffer.height() as f32 * WATERMARK_SCALE).max(8.0);
+        let padding = (size / 4.0).ceil() as i32;
+        let (text_width, text_height) = self.text_renderer.measure(size, &self.name);
+        let x0 = padding * 2;
+        let y0 = buffer.height() as i32 - text_height - padding * 4;
+        let (x1, y1) = (
+            x0 + text_width + padding * 2,
+            y0 + text_height + padding * 2,
+        );
+
+        let [(luma, width, height, stride), u_plane, v_plane] = planes_mut(&mut buffer);
+        let (chroma_width, chroma_height) = (u_plane.1, u_plane.2);
+
+        // Darken and desaturate a box behind the text so it reads on any background
+        let clip = |value: i32, max: usize| value.clamp(0, max as i32) as usize;
+        for y in clip(y0, height)..clip(y1, height) {
+            for value in &mut luma[y * stride + clip(x0, width)..y * stride + clip(x1, width)] {
+                *value = 16 + value.saturating_sub(16) / 3;
+            }
+        }
+        for (chroma, _, _, chroma_stride) in [u_plane, v_plane] {
+            for y in clip(y0 / 2, chroma_height)..clip(y1 / 2, chroma_height) {
+                let row = y * chroma_stride;
+                for value in
+                    &mut chroma[row + clip(x0 / 2, chroma_width)..row + clip(x1 / 2, chroma_width)]
+                {
+                    *value = (128 + (*value as i32 - 128) / 3) as u8;
+                }
+            }
+        }
+
+        self.text_renderer.draw(
+            size,
+            x0 + padding,
+            y0 + padding,
+            &self.name,
+            |x, y, coverage| {
+                if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+                    return;
+                }
+                let value = &mut luma[y as usize * stride + x as usize];
+                *value += ((235 - (*value).min(235)) as f32 * coverage) as u8;
+            },
+        );
+        buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame whose samples count up row by row, the chroma planes starting at 64 and 128.
+    fn frame(width: u32, height: u32) -> I420Buffer {
+        let mut buffer = I420Buffer::new(width, height);
+        for (i, (data, width, height, stride)) in planes_mut(&mut buffer).into_iter().enumerate() {
+            for y in 0..height {
+                for x in 0..width {
+                    data[y * stride + x] = (i * 64 + y * width + x) as u8;
+                }
+            }
+        }
+        buffer
+    }
+
+    /// The samples of each plane without the stride padding.
+    fn samples(buffer: &I420Buffer) -> Vec<Vec<u8>> {
+        planes(buffer)
+            .into_iter()
+            .map(|(data, width, height, stride)| {
+                data.chunks(stride)
+                    .take(height)
+                    .flat_map(|row| &row[..width])
+                    .copied()
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn crop(left: u32, top: u32, right: u32, bottom: u32) -> Crop {
+        Crop {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    #[test]
+    fn crop_cuts_each_plane() {
+        let cropped = crop(25, 0, 25, 50).process(frame(8, 4));
+        assert_eq!((cropped.width(), cropped.height()), (4, 2));
+        assert_eq!(
+            samples(&cropped),
+            [
+                vec![2, 3, 4, 5, 10, 11, 12, 13],
+                vec![65, 66],
+                vec![129, 130]
+            ]
+        );
+    }
+
+    #[test]
+    fn crop_keeps_uncropped_frames() {
+        let cropped = crop(0, 0, 0, 0).process(frame(8, 4));
+        assert_eq!((cropped.width(), cropped.height()), (8, 4));
+        assert_eq!(samples(&cropped), samples(&frame(8, 4)));
+    }
+
+    #[test]
+    fn rotate_90_turns_clockwise() {
+        let rotated = Rotate(Rotation::Rotate90).process(frame(4, 2));
+        assert_eq!((rotated.width(), rotated.height()), (2, 4));
+        assert_eq!(
+            samples(&rotated),
+            [vec![4, 0, 5, 1, 6, 2, 7, 3], vec![64, 65], vec![128, 129]]
+        );
+    }
+
+    #[test]
+    fn rotate_180_turns_upside_down() {
+        let rotated = Rotate(Rotation::Rotate180).process(frame(4, 2));
+        assert_eq!((rotated.width(), rotated.height()), (4, 2));
+        assert_eq!(
+            samples(&rotated),
+            [vec![7, 6, 5, 4, 3, 2, 1, 0], vec![65, 64], vec![129, 128]]
+        );
+    }
+
+    #[test]
+    fn rotate_270_turns_counter_clockwise() {
+        let rotated = Rotate(Rotation::Rotate270).process(frame(4, 2));
+        assert_eq!((rotated.width(), rotated.height()), (2, 4));
+        assert_eq!(
+            samples(&rotated),
+            [vec![3, 7, 2, 6, 1, 5, 0, 4], vec![65, 64], vec![129, 128]]
+        );
+    }
+}
@@ -1,9 +1,10 @@
+use eframe::wgpu;
 use futures::StreamExt;
 use livekit::webrtc::native::yuv_helper;
 use livekit::webrtc::prelude::*;
 use livekit::webrtc::video_stream::native::NativeVideoStream;
 use parking_lot::Mutex;
-use std::{ops::DerefMut, sync::Arc};
+use std::sync::Arc;
 
 pub struct VideoRenderer {
     internal: Arc<Mutex<RendererInternal>>,
@@ -16,10 +17,16 @@ struct RendererInternal {
     render_state: egui_wgpu::RenderState,
     width: u32,
     height: u32,
-    rgba_data: Vec<u8>,
-    texture: Option<eframe::wgpu::Texture>,
-    texture_view: Option<eframe::wgpu::TextureView>,
-    egui_texture: Option<egui::TextureId>,
+    upload: Upload,
+}
+
+/// How frames get from WebRTC to the screen.
+enum Upload {
+    /// Converted to RGBA on the CPU, for software adapters where the shader would run on the
+    /// CPU as well and libyuv is faster
+    Cpu(RgbaTexture),
+    /// Y, U and V uploaded as they are and converted by a shader while drawing
+    Gpu(YuvTextures),
 }
 
 impl VideoRenderer {
@@ -28,14 +35,18 @@ impl VideoRenderer {
         render_state: egui_wgpu::RenderState,
         rtc_track: RtcVideoTrack,
     ) -> Self {
+        // Software adapters such as llvmpipe, in CI
+        let upload = if render_state.adapter.get_info().device_type == wgpu::DeviceType::Cpu {
+            Upload::Cpu(RgbaTexture::default())
+        } else {
+            Upload::Gpu(YuvTextures::new(YuvPipeline::shared(&render_state)))
+        };
+
         let internal = Arc::new(Mutex::new(RendererInternal {
             render_state,
             width: 0,
             height: 0,
-            rgba_data: Vec::default(),
-            texture: None,
-            texture_view: None,
-            egui_texture: None,
+            upload,
         }));
 
         // TODO(theomonnom) Gracefully close the thread
@@ -46,52 +57,8 @@ impl VideoRenderer {
             let internal = internal.clone();
             move || {
                 while let Some(frame) = async_handle.block_on(video_sink.next()) {
-                    // Process the frame
-                    let mut internal = internal.lock();
                     let buffer = frame.buffer.to_i420();
-
-                    let width: u32 = buffer.width();
-                    let height: u32 = buffer.height();
-
-                    internal.ensure_texture_size(width, height);
-
-                    let rgba_ptr = internal.rgba_data.deref_mut();
-                    let rgba_stride = buffer.width() * 4;
-
-                    let (stride_y, stride_u, stride_v) = buffer.strides();
-                    let (data_y, data_u, data_v) = buffer.data();
-
-                    yuv_helper::i420_to_abgr(
-                        data_y,
-                        stride_y,
-                        data_u,
-                        stride_u,
-                        data_v,
-                        stride_v,
-                        rgba_ptr,
-                        rgba_stride,
-                        buffer.width() as i32,
-                        buffer.height() as i32,
-                    );
-
-                    internal.render_state.queue.write_texture(
-                        eframe::wgpu::TexelCopyTextureInfo {
-                            texture: internal.texture.as_ref().unwrap(),
-                            mip_level: 0,
-                            origin: eframe::wgpu::Origin3d::default(),
-                            aspect: eframe::wgpu::TextureAspect::default(),
-                        },
-                        &internal.rgba_data,
-                        eframe::wgpu::TexelCopyBufferLayout {
-                            bytes_per_row: Some(width * 4),
-                            ..Default::default()
-                        },
-                        eframe::wgpu::Extent3d {
-                            width,
-                            height,
-                            ..Default::default()
-                        },
-                    );
+                    internal.lock().upload(&buffer);
                 }
             }
         });
@@ -108,45 +75,133 @@ impl VideoRenderer {
         (internal.width, internal.height)
     }
 
-    // Returns the texture id, can be used to draw the texture on the UI
-    pub fn texture_id(&self) -> Option<egui::TextureId> {
-        self.internal.lock().egui_texture
+    /// Draw the last frame stretched over `rect`, nothing is drawn until a frame arrives.
+    pub fn paint(&self, painter: &egui::Painter, rect: egui::Rect) {
+        let internal = self.internal.lock();
+        match &internal.upload {
+            Upload::Cpu(texture) => {
+                if let Some(texture_id) = texture.egui_texture {
+                    painter.image(
+                        texture_id,
+                        rect,
+                        egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+                        egui::Color32::WHITE,
+                    );
+                }
+            }
+            Upload::Gpu(textures) => {
+                if let Some(bind_group) = &textures.bind_group {
+                    painter.add(egui_wgpu::Callback::new_paint_callback(
+                        rect,
+                        YuvCallback {
+                            pipeline: textures.pipeline.pipeline.clone(),
+                            bind_group: bind_group.clone(),
+                        },
+                    ));
+                }
+            }
+        }
     }
 }
 
 impl RendererInternal {
-    fn ensure_texture_size(&mut self, width: u32, height: u32) {
-        if self.width == width && self.height == height {
-            return;
-        }
-
+    fn upload(&mut self, buffer: &I420Buffer) {
+        let (width, height) = (buffer.width(), buffer.height());
+        let resized = self.width != width || self.height != height;
         self.width = width;
         self.height = height;
-        self.rgba_data.resize((width * height * 4) as usize, 0);
 
-        self.texture = Some(self.render_state.device.create_texture(
-            &eframe::wgpu::TextureDescriptor {
-                label: Some("lk-videotexture"),
-                usage: eframe::wgpu::TextureUsages::TEXTURE_BINDING
-                    | eframe::wgpu::TextureUsages::COPY_DST,
-                dimension: eframe::wgpu::TextureDimension::D2,
-                size: eframe::wgpu::Extent3d {
-                    width,
-                    height,
-                    ..Default::default()
-                },
-                sample_count: 1,
-                mip_level_count: 1,
-                format: eframe::wgpu::TextureFormat::Rgba8UnormSrgb,
-                view_formats: &[eframe::wgpu::TextureFormat::Rgba8UnormSrgb],
+        match &mut self.upload {
+            Upload::Cpu(texture) => texture.upload(&self.render_state, buffer, resized),
+            Upload::Gpu(textures) => textures.upload(&self.render_state, buffer, resized),
+        }
+    }
+}
+
+#[derive(Default)]
+struct RgbaTexture {
+    rgba_data: Vec<u8>,
+    texture: Option<wgpu::Texture>,
+    texture_view: Option<wgpu::TextureView>,
+    egui_texture: Option<egui::TextureId>,
+}
+
+impl RgbaTexture {
+    fn upload(
+        &mut self,
+        render_state: &egui_wgpu::RenderState,
+        buffer: &I420Buffer,
+        resized: bool,
+    ) {
+        let (width, height) = (buffer.width(), buffer.height());
+        if resized || self.texture.is_none() {
+            self.resize(render_state, width, height);
+        }
+
+        let rgba_stride = width * 4;
+        let (stride_y, stride_u, stride_v) = buffer.strides();
+        let (data_y, data_u, data_v) = buffer.data();
+
+        yuv_helper::i420_to_abgr(
+            data_y,
+            stride_y,
+            data_u,
+            stride_u,
+            data_v,
+            stride_v,
+            &mut self.rgba_data,
+            rgba_stride,
+            width as i32,
+            height as i32,
+        );
+
+        render_state.queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: self.texture.as_ref().unwrap(),
+                mip_level: 0,
+                origin: wgpu::Origin3d::default(),
+                aspect: wgpu::TextureAspect::default(),
             },
-        ));
+            &self.rgba_data,
+            wgpu::TexelCopyBufferLayout {
+                bytes_per_row: Some(width * 4),
+                ..Default::default()
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                ..Default::default()
+            },
+        );
+    }
+
+    fn resize(&mut self, render_state: &egui_wgpu::RenderState, width: u32, height: u32) {
+        self.rgba_data.resize((width * height * 4) as usize, 0);
+
+        self.texture = Some(
+            render_state
+                .device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some("lk-videotexture"),
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                    dimension: wgpu::TextureDimension::D2,
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        ..Default::default()
+                    },
+                    sample_count: 1,
+                    mip_level_count: 1,
+                    format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                    view_formats: &[wgpu::TextureFormat::Rgba8UnormSrgb],
+                }),
+        );
 
         self.texture_view = Some(self.texture.as_mut().unwrap().create_view(
-            &eframe::wgpu::TextureViewDescriptor {
+            &wgpu::TextureViewDescriptor {
                 label: Some("lk-videotexture-view"),
-                format: Some(eframe::wgpu::TextureFormat::Rgba8UnormSrgb),
-                dimension: Some(eframe::wgpu::TextureViewDimension::D2),
+                format: Some(wgpu::TextureFormat::Rgba8UnormSrgb),
+                dimension: Some(wgpu::TextureViewDimension::D2),
                 mip_level_count: Some(1),
                 array_layer_count: Some(1),
                 ..Default::default()
@@ -155,21 +210,247 @@ impl RendererInternal {
 
         if let Some(texture_id) = self.egui_texture {
             // Update the existing texture
-            self.render_state
+            render_state
                 .renderer
                 .write()
                 .update_egui_texture_from_wgpu_texture(
-                    &self.render_state.device,
+                    &render_state.device,
                     self.texture_view.as_ref().unwrap(),
-                    eframe::wgpu::FilterMode::Linear,
+                    wgpu::FilterMode::Linear,
                     texture_id,
                 );
         } else {
-            self.egui_texture = Some(self.render_state.renderer.write().register_native_texture(
-                &self.render_state.device,
+            self.egui_texture = Some(render_state.renderer.write().register_native_texture(
+                &render_state.device,
                 self.texture_view.as_ref().unwrap(),
-                eframe::wgpu::FilterMode::Linear,
+                wgpu::FilterMode::Linear,
             ));
         }
     }
 }
+
+/// The shader converting I420 to RGB, built once and kept in egui's callback resources.
+#[derive(Clone)]
+struct YuvPipeline {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+}
+
+impl YuvPipeline {
+    fn shared(render_state: &egui_wgpu::RenderState) -> Self {
+        let mut renderer = render_state.renderer.write();
+        if let Some(pipeline) = renderer.callback_resources.get::<YuvPipeline>() {
+            return pipeline.clone();
+        }
+
+        let pipeline = Self::new(&render_state.device, render_state.target_format);
+        renderer.callback_resources.insert(pipeline.clone());
+        pipeline
+    }
+
+    fn new(device: &wgpu::Device, target_format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("video_renderer.wgsl"));
+
+        let plane = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lk-yuv-bind-group-layout"),
+            entries: &[
+                plane(0),
+                plane(1),
+                plane(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("lk-yuv-pipeline-layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        // sRGB framebuffers expect linear colors, the others what egui writes: gamma encoded
+        let fragment_entry = if target_format.is_srgb() {
+            "fs_main_linear"
+        } else {
+            "fs_main_gamma"
+        };
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("lk-yuv-pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some(fragment_entry),
+                compilation_options: Default::default(),
+                targets: &[Some(target_format.into())],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("lk-yuv-sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            pipeline,
+            layout,
+            sampler,
+        }
+    }
+}
+
+/// One single channel texture per plane, the chroma ones at half the size.
+struct YuvTextures {
+    pipeline: YuvPipeline,
+    planes: Vec<wgpu::Texture>,
+    bind_group: Option<wgpu::BindGroup>,
+}
+
+impl YuvTextures {
+    fn new(pipeline: YuvPipeline) -> Self {
+        Self {
+            pipeline,
+            planes: Vec::new(),
+            bind_group: None,
+        }
+    }
+
+    fn upload(
+        &mut self,
+        render_state: &egui_wgpu::RenderState,
+        buffer: &I420Buffer,
+        resized: bool,
+    ) {
+        let sizes = [
+            (buffer.width(), buffer.height()),
+            (buffer.chroma_width(), buffer.chroma_height()),
+            (buffer.chroma_width(), buffer.chroma_height()),
+        ];
+        if resized || self.bind_group.is_none() {
+            self.resize(&render_state.device, sizes);
+        }
+
+        let (stride_y, stride_u, stride_v) = buffer.strides();
+        let (data_y, data_u, data_v) = buffer.data();
+        let planes = [(data_y, stride_y), (data_u, stride_u), (data_v, stride_v)];
+        for ((texture, (data, stride)), (width, height)) in
+            self.planes.iter().zip(planes).zip(sizes)
+        {
+            render_state.queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::default(),
+                    aspect: wgpu::TextureAspect::default(),
+                },
+                data,
+                wgpu::TexelCopyBufferLayout {
+                    bytes_per_row: Some(stride),
+                    ..Default::default()
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    // The previous bind group stays valid for paint callbacks already queued
+    fn resize(&mut self, device: &wgpu::Device, sizes: [(u32, u32); 3]) {
+        self.planes = sizes
+            .iter()
+            .map(|&(width, height)| {
+                device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("lk-videotexture-plane"),
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                    dimension: wgpu::TextureDimension::D2,
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        ..Default::default()
+                    },
+                    sample_count: 1,
+                    mip_level_count: 1,
+                    format: wgpu::TextureFormat::R8Unorm,
+                    view_formats: &[],
+                })
+            })
+            .collect();
+
+        let views = self
+            .planes
+            .iter()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+            .collect::<Vec<_>>();
+        self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lk-yuv-bind-group"),
+            layout: &self.pipeline.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&views[0]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&views[1]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&views[2]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&self.pipeline.sampler),
+                },
+            ],
+        }));
+    }
+}
+
+/// Draws the planes bound in `bind_group` over the callback's rect.
+struct YuvCallback {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl egui_wgpu::CallbackTrait for YuvCallback {
+    fn paint(
+        &self,
+        _info: egui::PaintCallbackInfo,
+        render_pass: &mut wgpu::RenderPass<'static>,
+        _callback_resources: &egui_wgpu::CallbackResources,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,51 @@
+// Draws an I420 frame over the paint callback's viewport, converting BT.601 limited range
+// YUV to RGB like libyuv's i420_to_abgr.
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@group(0) @binding(0) var y_plane: texture_2d<f32>;
+@group(0) @binding(1) var u_plane: texture_2d<f32>;
+@group(0) @binding(2) var v_plane: texture_2d<f32>;
+@group(0) @binding(3) var plane_sampler: sampler;
+
+// One triangle covering the viewport
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Gamma encoded RGB, as the frame was captured
+fn yuv_to_rgb(uv: vec2<f32>) -> vec3<f32> {
+    let y = (textureSample(y_plane, plane_sampler, uv).r - 16.0 / 255.0) * 1.164383;
+    let u = textureSample(u_plane, plane_sampler, uv).r - 0.5;
+    let v = textureSample(v_plane, plane_sampler, uv).r - 0.5;
+    let rgb = vec3<f32>(
+        y + 1.596027 * v,
+        y - 0.391762 * u - 0.812968 * v,
+        y + 2.017232 * u,
+    );
+    return clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// For framebuffers without an sRGB format, egui writes gamma encoded colors too
+@fragment
+fn fs_main_gamma(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(yuv_to_rgb(in.uv), 1.0);
+}
+
+// For sRGB framebuffers, which encode what we write
+@fragment
+fn fs_main_linear(in: VertexOutput) -> @location(0) vec4<f32> {
+    let rgb = yuv_to_rgb(in.uv);
+    let cutoff = rgb < vec3<f32>(0.04045);
+    let lower = rgb / 12.92;
+    let higher = pow((rgb + 0.055) / 1.055, vec3<f32>(2.4));
+    return vec4<f32>(select(higher, lower, cutoff), 1.0);
+}